@@ -27,7 +27,8 @@ type Nrf70SpiBus = SpiBus<ExclusiveDevice<Spim<'static, SERIAL0>, Output<'static
 
 #[embassy_executor::task]
 async fn nrf70_task(mut runner: nrf70::Runner<'static, Nrf70SpiBus, Input<'static>, Output<'static>>) -> ! {
-    runner.run().await
+    let error = runner.run().await;
+    panic!("nRF70 runner stopped: {:?}", error)
 }
 
 #[embassy_executor::task]
@@ -76,12 +77,39 @@ async fn main(spawner: Spawner) {
         Err(error) => error!("Failed to initialize {:?}", error),
     };
 
+    match control.capabilities().await {
+        Ok(capabilities) => {
+            for band in &capabilities.bands {
+                info!(
+                    "{:?}: {} channels, max TX power {:?} dBm",
+                    band.band,
+                    band.channels.len(),
+                    band.max_tx_power()
+                );
+            }
+        }
+        Err(error) => error!("Failed to get capabilities {}", error),
+    }
+
     let mut scan_options = ScanOptions::default();
     scan_options.scan_type = ScanType::Active;
     scan_options.dwell_time = Some(Duration::from_millis(300));
+    scan_options.channels = &[1, 6, 11];
 
     match control.scan(scan_options).await {
-        Ok(()) => info!("Requested scan..."),
+        Ok(mut scanner) => loop {
+            match scanner.next().await {
+                Ok(Some(result)) => info!(
+                    "{:02x} {=[u8]:a} channel {} {} dBm {:?}",
+                    result.bssid, &result.ssid[..], result.channel, result.rssi, result.security
+                ),
+                Ok(None) => break,
+                Err(error) => {
+                    error!("Failed to get scan results {}", error);
+                    break;
+                }
+            }
+        },
         Err(error) => error!("Failed to perform scan {}", error),
     }
 
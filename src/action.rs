@@ -5,64 +5,164 @@ use core::task::{Poll, Waker};
 
 use embassy_sync::waitqueue::WakerRegistration;
 
-use crate::bindings::nrf_wifi_host_rpu_msg_type;
+use crate::bindings::{
+    nrf_wifi_host_rpu_msg_type, nrf_wifi_sys_commands, nrf_wifi_sys_head, nrf_wifi_umac_commands, nrf_wifi_umac_hdr,
+};
+use crate::supplicant::Supplicant;
+use crate::util::unsliceit_padded;
 use crate::Error;
 
+/// The number of actions which can be in flight at once, beyond which [`ActionState::issue`]
+/// fails with [`Error::Busy`].
+pub const MAX_PENDING_ACTIONS: usize = 4;
+
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Item {
     UmacInfo,
 }
 
+/// A request from the control to the runner. The runner reads the buffers an action points to as
+/// soon as it takes the action, before it yields, so they only have to outlive the call to
+/// [`ActionState::issue`]. Response buffers are only written while the issuer still waits.
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Action {
     Boot(*const [u8]),
     Command((nrf_wifi_host_rpu_msg_type, bool, *const [u8], Option<*mut [u8]>)),
     Get((Item, *mut [u8])),
+    /// Hands the runner the supplicant for the network being joined, or removes it
+    Supplicant(Option<*const Supplicant>),
+}
+
+/// The event from the RPU which completes an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Response {
+    InitDone,
+    Stats,
+    InterfaceStatus,
+    Wiphy,
+    /// The status of the UMAC command with the given identifier
+    CommandStatus(u32),
+}
+
+impl Response {
+    /// The response to a command which is waited for, or `None` if there is no event which
+    /// completes it.
+    fn of_command(kind: nrf_wifi_host_rpu_msg_type, command: &[u8]) -> Option<Self> {
+        match kind {
+            nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_SYSTEM => {
+                let header: nrf_wifi_sys_head = unsliceit_padded(command);
+
+                if header.cmd_event == nrf_wifi_sys_commands::NRF_WIFI_CMD_INIT as u32 {
+                    Some(Self::InitDone)
+                } else if header.cmd_event == nrf_wifi_sys_commands::NRF_WIFI_CMD_GET_STATS as u32 {
+                    Some(Self::Stats)
+                } else {
+                    None
+                }
+            }
+            nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_UMAC => {
+                let header: nrf_wifi_umac_hdr = unsliceit_padded(command);
+
+                match nrf_wifi_umac_commands::try_from(header.cmd_evnt) {
+                    Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_SET_IFFLAGS) => Some(Self::InterfaceStatus),
+                    Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_GET_WIPHY) => Some(Self::Wiphy),
+                    _ => Some(Self::CommandStatus(header.cmd_evnt)),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An action taken by the runner, to be sent to the RPU.
+#[derive(Clone, Copy, Debug)]
+pub struct PendingAction {
+    /// The slot of the action, which its result is given back in
+    pub slot: usize,
+    /// The sequence number to place in the header of a UMAC command, which the RPU may echo in
+    /// the events for it
+    pub sequence: u32,
+    pub action: Action,
 }
 
 #[derive(Clone, Copy)]
 enum ActionStateInner {
-    Pending(Action),
-    Sent { response_buffer: Option<*mut [u8]> },
-    Done { result: Result<Option<usize>, Error> },
+    Free,
+    Pending {
+        action: Action,
+        sequence: u32,
+    },
+    Sent {
+        response: Option<Response>,
+        sequence: u32,
+        response_buffer: Option<*mut [u8]>,
+    },
+    /// Sent, but the issuer stopped waiting for it. The slot is held until the response arrives
+    /// so that it is not taken for a later action, and the response is then dropped.
+    Abandoned {
+        response: Option<Response>,
+        sequence: u32,
+    },
+    Done {
+        result: Result<Option<usize>, Error>,
+    },
+}
+
+/// Gives up the slot of an action when [`ActionState::issue`] is dropped before it completes,
+/// e.g. when it times out or loses a `select`.
+struct IssueGuard<'a> {
+    state: &'a ActionState,
+    slot: usize,
+}
+
+impl Drop for IssueGuard<'_> {
+    fn drop(&mut self) {
+        self.state.abandon(self.slot);
+    }
 }
 
 struct Wakers {
-    control: WakerRegistration,
+    control: [WakerRegistration; MAX_PENDING_ACTIONS],
     runner: WakerRegistration,
 }
 
 impl Wakers {
     const fn new() -> Self {
         Self {
-            control: WakerRegistration::new(),
+            control: [const { WakerRegistration::new() }; MAX_PENDING_ACTIONS],
             runner: WakerRegistration::new(),
         }
     }
 }
 
+/// The actions in flight between the control and the runner. Each one takes a slot from when it
+/// is issued until its result is read out, so several tasks can have commands outstanding at once.
 pub struct ActionState {
-    state: Cell<ActionStateInner>,
+    slots: [Cell<ActionStateInner>; MAX_PENDING_ACTIONS],
+    /// The sequence number of the next action, which is never zero so that it is not mistaken for
+    /// a command the runner sends on its own
+    next_sequence: Cell<u32>,
     wakers: RefCell<Wakers>,
 }
 
-#[allow(dead_code)]
 impl ActionState {
     pub const fn new() -> Self {
         Self {
-            state: Cell::new(ActionStateInner::Done { result: Ok(None) }),
+            slots: [const { Cell::new(ActionStateInner::Free) }; MAX_PENDING_ACTIONS],
+            next_sequence: Cell::new(1),
             wakers: RefCell::new(Wakers::new()),
         }
     }
 
-    fn wake_control(&self) {
-        self.wakers.borrow_mut().control.wake();
+    fn wake_control(&self, slot: usize) {
+        self.wakers.borrow_mut().control[slot].wake();
     }
 
-    fn register_control(&self, waker: &Waker) {
-        self.wakers.borrow_mut().control.register(waker);
+    fn register_control(&self, slot: usize, waker: &Waker) {
+        self.wakers.borrow_mut().control[slot].register(waker);
     }
 
     fn wake_runner(&self) {
@@ -73,98 +173,236 @@ impl ActionState {
         self.wakers.borrow_mut().runner.register(waker);
     }
 
-    pub fn wait_complete(&self) -> impl Future<Output = Result<Option<usize>, Error>> + '_ {
-        poll_fn(|cx| {
-            if let ActionStateInner::Done { result } = self.state.get() {
+    fn take_sequence(&self) -> u32 {
+        let sequence = self.next_sequence.get();
+        self.next_sequence.set(sequence.checked_add(1).unwrap_or(1));
+        sequence
+    }
+
+    /// How many actions were issued after the one with the sequence number, to find the oldest.
+    fn age(&self, sequence: u32) -> u32 {
+        self.next_sequence.get().wrapping_sub(sequence)
+    }
+
+    fn wait_complete(&self, slot: usize) -> impl Future<Output = Result<Option<usize>, Error>> + '_ {
+        poll_fn(move |cx| {
+            if let ActionStateInner::Done { result } = self.slots[slot].get() {
+                self.slots[slot].set(ActionStateInner::Free);
                 Poll::Ready(result)
             } else {
-                self.register_control(cx.waker());
+                self.register_control(slot, cx.waker());
                 Poll::Pending
             }
         })
     }
 
-    pub fn wait_pending(&self) -> impl Future<Output = Action> + '_ {
+    /// Waits for the oldest action which is not yet sent, and marks it as sent.
+    pub fn wait_pending(&self) -> impl Future<Output = PendingAction> + '_ {
         poll_fn(|cx| {
-            if let ActionStateInner::Pending(pending) = self.state.get() {
-                self.state.set(ActionStateInner::Sent {
-                    response_buffer: match pending {
-                        Action::Command((_, _, _, response_buffer)) => response_buffer,
-                        Action::Get((_, response_buffer)) => Some(response_buffer),
-                        _ => None,
-                    },
-                });
+            let oldest = self
+                .slots
+                .iter()
+                .enumerate()
+                .filter_map(|(slot, state)| match state.get() {
+                    ActionStateInner::Pending { action, sequence } => Some(PendingAction { slot, sequence, action }),
+                    _ => None,
+                })
+                .max_by_key(|pending| self.age(pending.sequence));
 
-                Poll::Ready(pending)
-            } else {
+            let Some(pending) = oldest else {
                 self.register_runner(cx.waker());
-                Poll::Pending
+                return Poll::Pending;
+            };
+
+            let (response, response_buffer) = match pending.action {
+                Action::Boot(_) => (Some(Response::InitDone), None),
+                Action::Command((kind, true, command, response_buffer)) => {
+                    (Response::of_command(kind, unsafe { &*command }), response_buffer)
+                }
+                Action::Command((_, false, _, response_buffer)) => (None, response_buffer),
+                Action::Get((_, response_buffer)) => (None, Some(response_buffer)),
+                Action::Supplicant(_) => (None, None),
+            };
+
+            self.slots[pending.slot].set(ActionStateInner::Sent {
+                response,
+                sequence: pending.sequence,
+                response_buffer,
+            });
+
+            Poll::Ready(pending)
+        })
+    }
+
+    /// Hands the action to the runner and waits for its result. It is safe to drop the returned
+    /// future at any point, the action is then either never sent or its response is discarded.
+    pub async fn issue(&self, action: Action) -> Result<Option<usize>, Error> {
+        // Booting again would start over the boot in progress, whose result is waited for instead
+        let resumed = match action {
+            Action::Boot(_) => self.resume_boot(),
+            _ => None,
+        };
+
+        let slot = match resumed {
+            Some(slot) => slot,
+            None => {
+                let slot = self.free_slot().ok_or(Error::Busy)?;
+
+                self.slots[slot].set(ActionStateInner::Pending {
+                    action,
+                    sequence: self.take_sequence(),
+                });
+
+                slot
             }
+        };
+
+        let _guard = IssueGuard { state: self, slot };
+
+        self.wake_runner();
+        self.wait_complete(slot).await
+    }
+
+    /// Takes back the slot of a boot which the issuer stopped waiting for, if the RPU is still
+    /// booting.
+    fn resume_boot(&self) -> Option<usize> {
+        self.slots.iter().position(|state| match state.get() {
+            ActionStateInner::Abandoned {
+                response: Some(Response::InitDone),
+                sequence,
+            } => {
+                state.set(ActionStateInner::Sent {
+                    response: Some(Response::InitDone),
+                    sequence,
+                    response_buffer: None,
+                });
+                true
+            }
+            _ => false,
         })
     }
 
-    pub fn cancel(&self) {
-        self.state.set(ActionStateInner::Done { result: Ok(None) });
+    /// A free slot. If there is none, the oldest abandoned action gives up its slot, as the RPU
+    /// may never answer it.
+    fn free_slot(&self) -> Option<usize> {
+        if let Some(slot) = self
+            .slots
+            .iter()
+            .position(|state| matches!(state.get(), ActionStateInner::Free))
+        {
+            return Some(slot);
+        }
+
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, state)| match state.get() {
+                ActionStateInner::Abandoned { sequence, .. } => Some((slot, sequence)),
+                _ => None,
+            })
+            .max_by_key(|(_, sequence)| self.age(*sequence))
+            .map(|(slot, _)| slot)
     }
 
-    pub async fn issue(&self, action: Action) -> Result<Option<usize>, Error> {
-        match self.state.get() {
-            ActionStateInner::Done { result: _ } => (),
-            _ => return Err(Error::Busy),
+    fn abandon(&self, slot: usize) {
+        match self.slots[slot].get() {
+            ActionStateInner::Pending { .. } | ActionStateInner::Done { .. } => {
+                self.slots[slot].set(ActionStateInner::Free);
+            }
+            ActionStateInner::Sent { response, sequence, .. } => {
+                self.slots[slot].set(ActionStateInner::Abandoned { response, sequence });
+            }
+            ActionStateInner::Free | ActionStateInner::Abandoned { .. } => {}
         }
+    }
 
-        self.state.set(ActionStateInner::Pending(action));
+    /// Completes the action in `slot` right away, such as when it could not be sent or there is
+    /// no event to wait for.
+    pub fn respond(&self, slot: usize, result: Result<Option<*const [u8]>, Error>) {
+        match self.slots[slot].get() {
+            ActionStateInner::Sent { response_buffer, .. } => self.finish(slot, result, response_buffer),
+            ActionStateInner::Abandoned { .. } => self.slots[slot].set(ActionStateInner::Free),
+            _ => warn!("Acking action, but no pending action"),
+        }
+    }
 
-        self.wake_runner();
-        self.wait_complete().await
-    }
-
-    pub fn respond(&self, result: Result<Option<*const [u8]>, Error>) {
-        if let ActionStateInner::Sent { response_buffer } = self.state.get() {
-            // Response buffer may be a value (given by the optional) and should be filled under the following conditions:
-            //
-            // * The result is OK and its optional contains a value
-            // * The response buffer has enough space for the result
-            fn get_result(
-                result: Result<Option<*const [u8]>, Error>,
-                response_buffer: Option<*mut [u8]>,
-            ) -> Result<Option<usize>, Error> {
-                match result {
-                    Ok(Some(result_data)) => unsafe {
-                        match response_buffer {
-                            Some(response_buffer_ptr) => {
-                                let result_data_length = result_data.len();
-                                let response_buffer: &mut [u8] = &mut *response_buffer_ptr;
-
-                                if response_buffer.len() < result_data_length {
-                                    return Err(Error::BufferTooSmall);
-                                }
-
-                                let result_data_ptr: &[u8] = &*result_data;
-
-                                ptr::copy_nonoverlapping(
-                                    result_data_ptr.as_ptr(),
-                                    response_buffer.as_mut_ptr(),
-                                    result_data_length,
-                                );
-
-                                Ok(Some(result_data_length))
+    /// Completes the action waiting for `response`. The one with the same sequence number is
+    /// picked if the RPU echoed it, and otherwise the oldest, as the RPU answers commands in
+    /// order.
+    pub fn complete(&self, response: Response, sequence: Option<u32>, result: Result<Option<*const [u8]>, Error>) {
+        let waiting = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, state)| match state.get() {
+                ActionStateInner::Sent {
+                    response: Some(expected),
+                    sequence,
+                    ..
+                }
+                | ActionStateInner::Abandoned {
+                    response: Some(expected),
+                    sequence,
+                } if expected == response => Some((slot, sequence)),
+                _ => None,
+            });
+
+        let matching = match sequence {
+            Some(sequence) => waiting
+                .clone()
+                .find(|(_, waiting_sequence)| *waiting_sequence == sequence)
+                .or_else(|| waiting.max_by_key(|(_, sequence)| self.age(*sequence))),
+            None => waiting.max_by_key(|(_, sequence)| self.age(*sequence)),
+        };
+
+        match matching {
+            Some((slot, _)) => self.respond(slot, result),
+            None => warn!("Got response {:?}, but no action waits for it", response),
+        }
+    }
+
+    fn finish(&self, slot: usize, result: Result<Option<*const [u8]>, Error>, response_buffer: Option<*mut [u8]>) {
+        // Response buffer may be a value (given by the optional) and should be filled under the following conditions:
+        //
+        // * The result is OK and its optional contains a value
+        // * The response buffer has enough space for the result
+        fn get_result(
+            result: Result<Option<*const [u8]>, Error>,
+            response_buffer: Option<*mut [u8]>,
+        ) -> Result<Option<usize>, Error> {
+            match result {
+                Ok(Some(result_data)) => unsafe {
+                    match response_buffer {
+                        Some(response_buffer_ptr) => {
+                            let result_data_length = result_data.len();
+                            let response_buffer: &mut [u8] = &mut *response_buffer_ptr;
+
+                            if response_buffer.len() < result_data_length {
+                                return Err(Error::BufferTooSmall);
                             }
-                            None => Ok(None),
+
+                            let result_data_ptr: &[u8] = &*result_data;
+
+                            ptr::copy_nonoverlapping(
+                                result_data_ptr.as_ptr(),
+                                response_buffer.as_mut_ptr(),
+                                result_data_length,
+                            );
+
+                            Ok(Some(result_data_length))
                         }
-                    },
-                    Ok(None) => Ok(None),
-                    Err(e) => Err(e),
-                }
+                        None => Ok(None),
+                    }
+                },
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
             }
+        }
 
-            self.state.set(ActionStateInner::Done {
-                result: get_result(result, response_buffer),
-            });
+        self.slots[slot].set(ActionStateInner::Done {
+            result: get_result(result, response_buffer),
+        });
 
-            self.wake_control();
-        } else {
-            warn!("Acking action, but no pending action");
-        }
+        self.wake_control(slot);
     }
 }
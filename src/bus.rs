@@ -1,15 +1,60 @@
 use embedded_hal::spi::Operation;
 use embedded_hal_async::spi::SpiDevice;
 
-use crate::{slice8, util::slice8_mut};
+use crate::{slice8, util::slice8_mut, Error};
+
+pub mod replay;
+pub mod trace;
+
+/// Most dummy words sent by the RPU before the data of a read, see [`Bus::read_with_latency`].
+pub const MAX_LATENCY: usize = 2;
+
+/// Converts the error of a bus into [`Error::Bus`], logging it as it is lost in the conversion.
+pub(crate) fn bus_error<E: core::fmt::Debug>(error: E) -> Error {
+    #[cfg(feature = "defmt")]
+    warn!("Bus error: {:?}", defmt::Debug2Format(&error));
+    #[cfg(not(feature = "defmt"))]
+    warn!("Bus error: {:?}", error);
+
+    Error::Bus
+}
 
 pub trait Bus {
-    async fn read(&mut self, addr: u32, buf: &mut [u32]);
-    async fn write(&mut self, addr: u32, buf: &[u32]);
-    async fn read_sr0(&mut self) -> u8;
-    async fn read_sr1(&mut self) -> u8;
-    async fn read_sr2(&mut self) -> u8;
-    async fn write_sr2(&mut self, val: u8);
+    type Error: core::fmt::Debug;
+
+    async fn read(&mut self, addr: u32, buf: &mut [u32]) -> Result<(), Self::Error>;
+
+    /// Reads `buf.len()` words starting at `addr` as a single burst. Memory regions of the RPU with
+    /// a latency send that many dummy words before the data, which are discarded.
+    ///
+    /// The default implementation bursts through a small buffer holding the dummy words, so it
+    /// takes a transaction per 32 words. Buses which can discard bytes as part of a transaction
+    /// should do so instead.
+    #[allow(clippy::cast_possible_truncation)]
+    async fn read_with_latency(&mut self, addr: u32, latency: usize, buf: &mut [u32]) -> Result<(), Self::Error> {
+        const CHUNK_WORDS: usize = 32;
+
+        if latency == 0 {
+            return self.read(addr, buf).await;
+        }
+
+        let mut chunk = [0u32; MAX_LATENCY + CHUNK_WORDS];
+
+        for (index, words) in buf.chunks_mut(CHUNK_WORDS).enumerate() {
+            let chunk = &mut chunk[..latency + words.len()];
+
+            self.read(addr + (index * CHUNK_WORDS * 4) as u32, chunk).await?;
+            words.copy_from_slice(&chunk[latency..]);
+        }
+
+        Ok(())
+    }
+
+    async fn write(&mut self, addr: u32, buf: &[u32]) -> Result<(), Self::Error>;
+    async fn read_sr0(&mut self) -> Result<u8, Self::Error>;
+    async fn read_sr1(&mut self) -> Result<u8, Self::Error>;
+    async fn read_sr2(&mut self) -> Result<u8, Self::Error>;
+    async fn write_sr2(&mut self, val: u8) -> Result<(), Self::Error>;
 }
 
 pub struct SpiBus<T> {
@@ -23,98 +68,160 @@ impl<T> SpiBus<T> {
 }
 
 impl<T: SpiDevice> Bus for SpiBus<T> {
+    type Error = T::Error;
+
+    #[allow(clippy::cast_possible_truncation)]
+    async fn read(&mut self, addr: u32, buf: &mut [u32]) -> Result<(), Self::Error> {
+        self.spi
+            .transaction(&mut [
+                Operation::Write(&[0x0B, (addr >> 16) as u8, (addr >> 8) as u8, addr as u8, 0x00]),
+                Operation::Read(slice8_mut(buf)),
+            ])
+            .await
+    }
+
     #[allow(clippy::cast_possible_truncation)]
-    async fn read(&mut self, addr: u32, buf: &mut [u32]) {
+    async fn read_with_latency(&mut self, addr: u32, latency: usize, buf: &mut [u32]) -> Result<(), Self::Error> {
+        // Framed exactly like a plain read, rather than with an empty read of the dummy words
+        // between the header and the data
+        if latency == 0 {
+            return self.read(addr, buf).await;
+        }
+
+        let mut dummy = [0u8; MAX_LATENCY * 4];
+
         self.spi
             .transaction(&mut [
                 Operation::Write(&[0x0B, (addr >> 16) as u8, (addr >> 8) as u8, addr as u8, 0x00]),
+                Operation::Read(&mut dummy[..latency * 4]),
                 Operation::Read(slice8_mut(buf)),
             ])
             .await
-            .unwrap();
     }
 
     #[allow(clippy::cast_possible_truncation)]
-    async fn write(&mut self, addr: u32, buf: &[u32]) {
+    async fn write(&mut self, addr: u32, buf: &[u32]) -> Result<(), Self::Error> {
         self.spi
             .transaction(&mut [
                 Operation::Write(&[0x02, (addr >> 16) as u8 | 0x80, (addr >> 8) as u8, addr as u8]),
                 Operation::Write(slice8(buf)),
             ])
             .await
-            .unwrap();
     }
 
-    async fn read_sr0(&mut self) -> u8 {
+    async fn read_sr0(&mut self) -> Result<u8, Self::Error> {
         let mut buf = [0; 2];
-        self.spi.transfer(&mut buf, &[0x05]).await.unwrap();
+        self.spi.transfer(&mut buf, &[0x05]).await?;
         let val = buf[1];
         trace!("read sr0 = {:02x}", val);
-        val
+        Ok(val)
     }
 
-    async fn read_sr1(&mut self) -> u8 {
+    async fn read_sr1(&mut self) -> Result<u8, Self::Error> {
         let mut buf = [0; 2];
-        self.spi.transfer(&mut buf, &[0x1f]).await.unwrap();
+        self.spi.transfer(&mut buf, &[0x1f]).await?;
         let val = buf[1];
         trace!("read sr1 = {:02x}", val);
-        val
+        Ok(val)
     }
 
-    async fn read_sr2(&mut self) -> u8 {
+    async fn read_sr2(&mut self) -> Result<u8, Self::Error> {
         let mut buf = [0; 2];
-        self.spi.transfer(&mut buf, &[0x2f]).await.unwrap();
+        self.spi.transfer(&mut buf, &[0x2f]).await?;
         let val = buf[1];
         trace!("read sr2 = {:02x}", val);
-        val
+        Ok(val)
     }
 
-    async fn write_sr2(&mut self, val: u8) {
+    async fn write_sr2(&mut self, val: u8) -> Result<(), Self::Error> {
         trace!("write sr2 = {:02x}", val);
-        self.spi.write(&[0x3f, val]).await.unwrap();
+        self.spi.write(&[0x3f, val]).await
     }
 }
 
-/*
-pub struct QspiBus<'a> {
-    qspi: Qspi<'a, QSPI>,
+/// A QSPI peripheral, as needed by [`QspiBus`].
+///
+/// The peripheral should be configured for quad reads and writes (e.g. the `READ4IO` and `PP4IO`
+/// opcodes) with 24-bit addresses, and with the dummy cycles the nRF70 expects before read data.
+pub trait QspiDevice {
+    type Error: core::fmt::Debug;
+
+    /// Reads `buf.len()` bytes starting at `addr` with the configured quad read opcode.
+    async fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Reads like [`QspiDevice::read`], with `dummy_cycles` more dummy cycles than configured
+    /// between the address and the data.
+    async fn read_with_dummy_cycles(
+        &mut self,
+        addr: u32,
+        dummy_cycles: usize,
+        buf: &mut [u8],
+    ) -> Result<(), Self::Error>;
+
+    /// Writes `buf` starting at `addr` with the configured quad write opcode.
+    async fn write(&mut self, addr: u32, buf: &[u8]) -> Result<(), Self::Error>;
+
+    /// Sends `opcode` on a single line, then clocks out `tx` while clocking in `rx`, as an
+    /// instruction of `1 + max(tx.len(), rx.len())` bytes.
+    async fn custom_instruction(&mut self, opcode: u8, tx: &[u8], rx: &mut [u8]) -> Result<(), Self::Error>;
 }
 
-impl<'a> QspiBus<'a> {}
+pub struct QspiBus<T> {
+    qspi: T,
+}
+
+impl<T> QspiBus<T> {
+    pub fn new(qspi: T) -> Self {
+        Self { qspi }
+    }
+}
+
+impl<T: QspiDevice> Bus for QspiBus<T> {
+    type Error = T::Error;
 
-impl<'a> Bus for QspiBus<'a> {
-    async fn read(&mut self, addr: u32, buf: &mut [u32]) {
-        self.qspi.read(addr, slice8_mut(buf)).await.unwrap();
+    async fn read(&mut self, addr: u32, buf: &mut [u32]) -> Result<(), Self::Error> {
+        self.qspi.read(addr, slice8_mut(buf)).await
+    }
+
+    async fn read_with_latency(&mut self, addr: u32, latency: usize, buf: &mut [u32]) -> Result<(), Self::Error> {
+        if latency == 0 {
+            return self.read(addr, buf).await;
+        }
+
+        // A dummy word takes 8 cycles over the four lines
+        self.qspi
+            .read_with_dummy_cycles(addr, latency * 8, slice8_mut(buf))
+            .await
     }
 
-    async fn write(&mut self, addr: u32, buf: &[u32]) {
-        self.qspi.write(addr, slice8(buf)).await.unwrap();
+    async fn write(&mut self, addr: u32, buf: &[u32]) -> Result<(), Self::Error> {
+        // Writes are flagged in the address, as over SPI
+        self.qspi.write(addr | 0x80_0000, slice8(buf)).await
     }
 
-    async fn read_sr0(&mut self) -> u8 {
-        let mut status = [4; 1];
-        unwrap!(self.qspi.custom_instruction(0x05, &[0x00], &mut status).await);
+    async fn read_sr0(&mut self) -> Result<u8, Self::Error> {
+        let mut status = [0; 1];
+        self.qspi.custom_instruction(0x05, &[], &mut status).await?;
         trace!("read sr0 = {:02x}", status[0]);
-        status[0]
+        Ok(status[0])
     }
 
-    async fn read_sr1(&mut self) -> u8 {
-        let mut status = [4; 1];
-        unwrap!(self.qspi.custom_instruction(0x1f, &[0x00], &mut status).await);
+    async fn read_sr1(&mut self) -> Result<u8, Self::Error> {
+        let mut status = [0; 1];
+        self.qspi.custom_instruction(0x1f, &[], &mut status).await?;
         trace!("read sr1 = {:02x}", status[0]);
-        status[0]
+        Ok(status[0])
     }
 
-    async fn read_sr2(&mut self) -> u8 {
-        let mut status = [4; 1];
-        unwrap!(self.qspi.custom_instruction(0x2f, &[0x00], &mut status).await);
+    async fn read_sr2(&mut self) -> Result<u8, Self::Error> {
+        let mut status = [0; 1];
+        self.qspi.custom_instruction(0x2f, &[], &mut status).await?;
         trace!("read sr2 = {:02x}", status[0]);
-        status[0]
+        Ok(status[0])
     }
 
-    async fn write_sr2(&mut self, val: u8) {
+    async fn write_sr2(&mut self, val: u8) -> Result<(), Self::Error> {
         trace!("write sr2 = {:02x}", val);
-        unwrap!(self.qspi.custom_instruction(0x3f, &[val], &mut []).await);
+        self.qspi.custom_instruction(0x3f, &[val], &mut []).await
     }
 }
- */
@@ -0,0 +1,164 @@
+//! Replay of a trace recorded with [`TracingBus`](super::trace::TracingBus), to check that the
+//! driver still makes the same accesses.
+//!
+//! A [`ReplayBus`] answers every read with the recorded data and checks that each access matches
+//! the next record of the trace: its kind, address and length, and for writes also the data. The
+//! timestamps are not compared. Once an access differs the replay is off track, and it fails every
+//! access from then on.
+
+use core::cell::Cell;
+
+use super::trace::{Access, Record};
+use super::Bus;
+use crate::util::{slice8, slice8_mut};
+
+/// Why an access on a [`ReplayBus`] failed. The index is that of the record in the trace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReplayError {
+    /// The driver accessed the bus after the last record
+    EndOfTrace,
+    /// The record could not be parsed
+    InvalidTrace { index: usize },
+    /// The kind, address or length of the access differs from the record
+    UnexpectedAccess { index: usize, access: Access, address: u32 },
+    /// A write of other data than recorded
+    DataMismatch { index: usize, address: u32 },
+}
+
+/// A [`Bus`] which replays a serialized trace, as read out of a
+/// [`TraceBuffer`](super::trace::TraceBuffer).
+///
+/// The bus is implemented for a shared reference, so that the progress of the replay can be
+/// checked while the driver holds it.
+pub struct ReplayBus<'a> {
+    trace: &'a [u8],
+    /// Offset of the next record in the trace
+    position: Cell<usize>,
+    replayed: Cell<usize>,
+    error: Cell<Option<ReplayError>>,
+}
+
+impl<'a> ReplayBus<'a> {
+    #[must_use]
+    pub fn new(trace: &'a [u8]) -> Self {
+        Self {
+            trace,
+            position: Cell::new(0),
+            replayed: Cell::new(0),
+            error: Cell::new(None),
+        }
+    }
+
+    /// Number of records replayed so far.
+    #[must_use]
+    pub fn replayed(&self) -> usize {
+        self.replayed.get()
+    }
+
+    /// Whether all the records have been replayed without an error.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.error.get().is_none() && self.position.get() == self.trace.len()
+    }
+
+    /// The first error of the replay, after which every access fails.
+    #[must_use]
+    pub fn error(&self) -> Option<ReplayError> {
+        self.error.get()
+    }
+
+    /// The record the next access is expected to match.
+    #[must_use]
+    pub fn expected(&self) -> Option<Record<'a>> {
+        Record::decode(&self.trace[self.position.get()..])
+            .ok()
+            .map(|(record, _)| record)
+    }
+
+    /// Takes the next record if it matches the access, otherwise the replay fails. The data is
+    /// only compared for writes.
+    fn replay(
+        &self,
+        access: Access,
+        address: u32,
+        length: usize,
+        data: Option<&[u8]>,
+    ) -> Result<Record<'a>, ReplayError> {
+        if let Some(error) = self.error.get() {
+            return Err(error);
+        }
+
+        let index = self.replayed.get();
+        let remaining = &self.trace[self.position.get()..];
+
+        let result = if remaining.is_empty() {
+            Err(ReplayError::EndOfTrace)
+        } else {
+            match Record::decode(remaining) {
+                Ok((record, _))
+                    if record.access != access || record.address != address || record.data.len() != length =>
+                {
+                    Err(ReplayError::UnexpectedAccess { index, access, address })
+                }
+                Ok((record, _)) if data.is_some_and(|data| data != record.data) => {
+                    Err(ReplayError::DataMismatch { index, address })
+                }
+                Ok((record, record_length)) => {
+                    self.position.set(self.position.get() + record_length);
+                    self.replayed.set(index + 1);
+                    Ok(record)
+                }
+                Err(_) => Err(ReplayError::InvalidTrace { index }),
+            }
+        };
+
+        if let Err(error) = result {
+            self.error.set(Some(error));
+        }
+
+        result
+    }
+
+    fn replay_write(&self, access: Access, address: u32, data: &[u8]) -> Result<(), ReplayError> {
+        self.replay(access, address, data.len(), Some(data)).map(|_| ())
+    }
+
+    fn replay_status(&self, access: Access) -> Result<u8, ReplayError> {
+        self.replay(access, 0, 1, None).map(|record| record.data[0])
+    }
+}
+
+impl Bus for &ReplayBus<'_> {
+    type Error = ReplayError;
+
+    async fn read(&mut self, addr: u32, buf: &mut [u32]) -> Result<(), Self::Error> {
+        let record = self.replay(Access::Read, addr, buf.len() * 4, None)?;
+        slice8_mut(buf).copy_from_slice(record.data);
+        Ok(())
+    }
+
+    async fn read_with_latency(&mut self, addr: u32, _latency: usize, buf: &mut [u32]) -> Result<(), Self::Error> {
+        self.read(addr, buf).await
+    }
+
+    async fn write(&mut self, addr: u32, buf: &[u32]) -> Result<(), Self::Error> {
+        self.replay_write(Access::Write, addr, slice8(buf))
+    }
+
+    async fn read_sr0(&mut self) -> Result<u8, Self::Error> {
+        self.replay_status(Access::ReadSr0)
+    }
+
+    async fn read_sr1(&mut self) -> Result<u8, Self::Error> {
+        self.replay_status(Access::ReadSr1)
+    }
+
+    async fn read_sr2(&mut self) -> Result<u8, Self::Error> {
+        self.replay_status(Access::ReadSr2)
+    }
+
+    async fn write_sr2(&mut self, val: u8) -> Result<(), Self::Error> {
+        self.replay_write(Access::WriteSr2, 0, &[val])
+    }
+}
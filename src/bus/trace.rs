@@ -0,0 +1,344 @@
+//! Recording of the traffic on a [`Bus`], to compare it against the reference driver or to replay
+//! it later.
+//!
+//! Wrap the bus in a [`TracingBus`], which passes every access on to a [`TraceSink`] as a
+//! [`Record`]. A [`TraceBuffer`] keeps the most recent records in their serialized form, which can
+//! be read out and parsed again with [`Record::decode`].
+
+use core::fmt;
+
+use embassy_time::Instant;
+use heapless::Deque;
+
+use super::Bus;
+use crate::{util::slice8, Error};
+
+const HEADER_SIZE: usize = 17;
+
+/// The kind of access made on the bus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum Access {
+    Read = 0,
+    Write = 1,
+    ReadSr0 = 2,
+    ReadSr1 = 3,
+    ReadSr2 = 4,
+    WriteSr2 = 5,
+}
+
+impl Access {
+    fn from_u8(value: u8) -> Option<Access> {
+        match value {
+            0 => Some(Access::Read),
+            1 => Some(Access::Write),
+            2 => Some(Access::ReadSr0),
+            3 => Some(Access::ReadSr1),
+            4 => Some(Access::ReadSr2),
+            5 => Some(Access::WriteSr2),
+            _ => None,
+        }
+    }
+}
+
+/// A single access on the bus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record<'a> {
+    /// When the access completed
+    pub timestamp: Instant,
+    pub access: Access,
+
+    /// Address on the bus, zero for the status registers
+    pub address: u32,
+
+    /// The bytes read or written, without the dummy bytes of the memory region
+    pub data: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    /// Size of the serialized record without its data: the access (1 byte), address (4 bytes),
+    /// timestamp in microseconds (8 bytes) and data length (4 bytes), all little endian.
+    pub const HEADER_SIZE: usize = HEADER_SIZE;
+
+    /// Size of the serialized record.
+    #[must_use]
+    pub fn encoded_len(&self) -> usize {
+        Self::HEADER_SIZE + self.data.len()
+    }
+
+    /// Serializes the record into `buffer`, returning the number of bytes written.
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let length = self.encoded_len();
+
+        let Some(buffer) = buffer.get_mut(..length) else {
+            return Err(Error::BufferTooSmall);
+        };
+
+        buffer[..Self::HEADER_SIZE].copy_from_slice(&self.header());
+        buffer[Self::HEADER_SIZE..].copy_from_slice(self.data);
+
+        Ok(length)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn header(&self) -> [u8; HEADER_SIZE] {
+        let mut header = [0u8; HEADER_SIZE];
+
+        header[0] = self.access as u8;
+        header[1..5].copy_from_slice(&self.address.to_le_bytes());
+        header[5..13].copy_from_slice(&self.timestamp.as_micros().to_le_bytes());
+        header[13..17].copy_from_slice(&(self.data.len() as u32).to_le_bytes());
+
+        header
+    }
+
+    /// Parses a record serialized with [`Record::encode`] at the start of `buffer`, returning it
+    /// along with the number of bytes it took up.
+    pub fn decode(buffer: &'a [u8]) -> Result<(Record<'a>, usize), Error> {
+        if buffer.len() < Self::HEADER_SIZE {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let access = Access::from_u8(buffer[0]).ok_or(Error::InvalidArgument)?;
+        let address = u32::from_le_bytes(unwrap!(buffer[1..5].try_into()));
+        let timestamp = Instant::from_micros(u64::from_le_bytes(unwrap!(buffer[5..13].try_into())));
+        let length = Self::HEADER_SIZE + u32::from_le_bytes(unwrap!(buffer[13..17].try_into())) as usize;
+
+        let data = buffer.get(Self::HEADER_SIZE..length).ok_or(Error::BufferTooSmall)?;
+
+        Ok((
+            Record {
+                timestamp,
+                access,
+                address,
+                data,
+            },
+            length,
+        ))
+    }
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::ReadSr0 => "read sr0",
+            Access::ReadSr1 => "read sr1",
+            Access::ReadSr2 => "read sr2",
+            Access::WriteSr2 => "write sr2",
+        };
+
+        write!(
+            f,
+            "{:>12} {:<9} {:06x} [{}]",
+            self.timestamp.as_micros(),
+            access,
+            self.address,
+            self.data.len()
+        )?;
+
+        for byte in self.data {
+            write!(f, " {byte:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Receives the records of a [`TracingBus`].
+pub trait TraceSink {
+    fn record(&mut self, record: &Record<'_>);
+}
+
+impl<T: TraceSink + ?Sized> TraceSink for &mut T {
+    fn record(&mut self, record: &Record<'_>) {
+        (**self).record(record);
+    }
+}
+
+/// A ring buffer of `N` bytes holding the most recent records in their serialized form. The
+/// oldest records are dropped to make room for new ones.
+pub struct TraceBuffer<const N: usize> {
+    bytes: Deque<u8, N>,
+    dropped: usize,
+}
+
+impl<const N: usize> Default for TraceBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> TraceBuffer<N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            bytes: Deque::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Number of serialized bytes in the buffer.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Number of records dropped so far, either to make room or because they would never fit.
+    #[must_use]
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    /// Moves as many whole records as fit into `buffer`, oldest first, and returns the number of
+    /// bytes written. The result is a trace which can be parsed with [`Record::decode`].
+    pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+        let mut written = 0;
+
+        while let Some(length) = self.front_record_len() {
+            if written + length > buffer.len() {
+                break;
+            }
+
+            for byte in &mut buffer[written..written + length] {
+                *byte = unwrap!(self.bytes.pop_front());
+            }
+
+            written += length;
+        }
+
+        written
+    }
+
+    /// Serialized length of the oldest record in the buffer.
+    fn front_record_len(&self) -> Option<usize> {
+        if self.bytes.len() < Record::HEADER_SIZE {
+            return None;
+        }
+
+        let mut length = [0u8; 4];
+
+        for (byte, value) in length.iter_mut().zip(self.bytes.iter().skip(13)) {
+            *byte = *value;
+        }
+
+        Some(Record::HEADER_SIZE + u32::from_le_bytes(length) as usize)
+    }
+}
+
+impl<const N: usize> TraceSink for TraceBuffer<N> {
+    fn record(&mut self, record: &Record<'_>) {
+        let length = record.encoded_len();
+
+        if length > N {
+            self.dropped += 1;
+            return;
+        }
+
+        while N - self.bytes.len() < length {
+            for _ in 0..unwrap!(self.front_record_len()) {
+                self.bytes.pop_front();
+            }
+
+            self.dropped += 1;
+        }
+
+        for byte in record.header().iter().chain(record.data) {
+            unwrap!(self.bytes.push_back(*byte));
+        }
+    }
+}
+
+/// A [`Bus`] which passes every access made on the wrapped bus on to a [`TraceSink`]. Failed
+/// accesses are not recorded.
+pub struct TracingBus<B, S> {
+    bus: B,
+    sink: S,
+}
+
+impl<B, S> TracingBus<B, S> {
+    pub fn new(bus: B, sink: S) -> Self {
+        Self { bus, sink }
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_inner(self) -> (B, S) {
+        (self.bus, self.sink)
+    }
+}
+
+impl<B: Bus, S: TraceSink> TracingBus<B, S> {
+    fn record(&mut self, access: Access, address: u32, data: &[u8]) {
+        self.sink.record(&Record {
+            timestamp: Instant::now(),
+            access,
+            address,
+            data,
+        });
+    }
+}
+
+impl<B: Bus, S: TraceSink> Bus for TracingBus<B, S> {
+    type Error = B::Error;
+
+    async fn read(&mut self, addr: u32, buf: &mut [u32]) -> Result<(), Self::Error> {
+        self.bus.read(addr, buf).await?;
+        self.record(Access::Read, addr, slice8(buf));
+        Ok(())
+    }
+
+    async fn read_with_latency(&mut self, addr: u32, latency: usize, buf: &mut [u32]) -> Result<(), Self::Error> {
+        self.bus.read_with_latency(addr, latency, buf).await?;
+        self.record(Access::Read, addr, slice8(buf));
+        Ok(())
+    }
+
+    async fn write(&mut self, addr: u32, buf: &[u32]) -> Result<(), Self::Error> {
+        self.bus.write(addr, buf).await?;
+        self.record(Access::Write, addr, slice8(buf));
+        Ok(())
+    }
+
+    async fn read_sr0(&mut self) -> Result<u8, Self::Error> {
+        let value = self.bus.read_sr0().await?;
+        self.record(Access::ReadSr0, 0, &[value]);
+        Ok(value)
+    }
+
+    async fn read_sr1(&mut self) -> Result<u8, Self::Error> {
+        let value = self.bus.read_sr1().await?;
+        self.record(Access::ReadSr1, 0, &[value]);
+        Ok(value)
+    }
+
+    async fn read_sr2(&mut self) -> Result<u8, Self::Error> {
+        let value = self.bus.read_sr2().await?;
+        self.record(Access::ReadSr2, 0, &[value]);
+        Ok(value)
+    }
+
+    async fn write_sr2(&mut self, val: u8) -> Result<(), Self::Error> {
+        self.bus.write_sr2(val).await?;
+        self.record(Access::WriteSr2, 0, &[val]);
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+//! What the radio and firmware support, as reported by the wiphy (wireless PHY) information which
+//! is queried with [`Control::capabilities`](crate::control::Control::capabilities).
+
+use heapless::Vec;
+
+use crate::{
+    bindings::{
+        nrf_wifi_band, nrf_wifi_channel_flags, nrf_wifi_event_channel, nrf_wifi_event_get_wiphy,
+        nrf_wifi_event_supported_band, nrf_wifi_iftype, NRF_WIFI_EVENT_GET_WIPHY_MAX_CIPHER_COUNT,
+        NRF_WIFI_EVENT_GET_WIPHY_NUM_BANDS, NRF_WIFI_GET_WIPHY_VALID_EXTENDED_CAPABILITIES,
+        NRF_WIFI_GET_WIPHY_VALID_MAX_AP_ASSOC_STA, NRF_WIFI_GET_WIPHY_VALID_MAX_NUM_SCAN_SSIDS,
+    },
+    control::{channel, Band},
+    ie::{HtCapabilities, Suite, VhtCapabilities},
+};
+
+/// Most channels reported for a band.
+const MAX_CHANNELS: usize = 29;
+
+/// Most bitrates reported for a band.
+const MAX_BITRATES: usize = 13;
+
+/// Longest extended capabilities field reported.
+const MAX_EXTENDED_CAPABILITIES_LEN: usize = 10;
+
+/// The capabilities of the radio and firmware.
+///
+/// The firmware does not report HE (802.11ax) capabilities here, as these are set up by the host
+/// when the firmware is initialized.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Capabilities {
+    /// The bands with at least one channel.
+    pub bands: Vec<BandCapabilities, { NRF_WIFI_EVENT_GET_WIPHY_NUM_BANDS as usize }>,
+    /// The supported cipher suites, such as [`Suite::CCMP_128`].
+    pub cipher_suites: Vec<Suite, { NRF_WIFI_EVENT_GET_WIPHY_MAX_CIPHER_COUNT as usize }>,
+    /// Bit mask of the supported interface modes, see [`Capabilities::supports`].
+    pub interface_modes: u16,
+    /// Most SSIDs which can be probed for in a scan, if reported.
+    pub max_scan_ssids: Option<u8>,
+    /// Longest information elements which can be added to the probe requests of a scan.
+    pub max_scan_ie_length: u16,
+    /// Most stations which can be associated in AP mode, if reported.
+    pub max_ap_stations: Option<u32>,
+    /// Retry limit for frames shorter than the RTS threshold.
+    pub retry_short: u8,
+    /// Retry limit for frames longer than the RTS threshold.
+    pub retry_long: u8,
+    /// The extended capabilities field sent in association requests, see
+    /// [`ExtendedCapabilities`](crate::ie::ExtendedCapabilities).
+    pub extended_capabilities: Vec<u8, MAX_EXTENDED_CAPABILITIES_LEN>,
+}
+
+/// An interface mode (type) of the firmware.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InterfaceMode {
+    Station,
+    Ap,
+    Monitor,
+    P2pClient,
+    P2pGo,
+    P2pDevice,
+}
+
+impl InterfaceMode {
+    fn iftype(self) -> nrf_wifi_iftype {
+        match self {
+            InterfaceMode::Station => nrf_wifi_iftype::NRF_WIFI_IFTYPE_STATION,
+            InterfaceMode::Ap => nrf_wifi_iftype::NRF_WIFI_IFTYPE_AP,
+            InterfaceMode::Monitor => nrf_wifi_iftype::NRF_WIFI_IFTYPE_MONITOR,
+            InterfaceMode::P2pClient => nrf_wifi_iftype::NRF_WIFI_IFTYPE_P2P_CLIENT,
+            InterfaceMode::P2pGo => nrf_wifi_iftype::NRF_WIFI_IFTYPE_P2P_GO,
+            InterfaceMode::P2pDevice => nrf_wifi_iftype::NRF_WIFI_IFTYPE_P2P_DEVICE,
+        }
+    }
+}
+
+/// What is supported in a band.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BandCapabilities {
+    pub band: Band,
+    /// The channels of the current regulatory domain.
+    pub channels: Vec<Channel, MAX_CHANNELS>,
+    /// The legacy bitrates in units of 100 kbps.
+    pub bitrates: Vec<u16, MAX_BITRATES>,
+    /// The HT (802.11n) capabilities, if supported in the band.
+    pub ht: Option<HtCapabilities>,
+    /// The VHT (802.11ac) capabilities, if supported in the band.
+    pub vht: Option<VhtCapabilities>,
+}
+
+/// A channel of a band.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Channel {
+    /// Center frequency in MHz.
+    pub frequency: u32,
+    pub number: u8,
+    /// Maximum transmit power in dBm.
+    pub max_tx_power: i32,
+    pub flags: ChannelFlags,
+}
+
+impl Channel {
+    fn from_event(channel_info: &nrf_wifi_event_channel) -> Self {
+        let frequency = u32::from(channel_info.center_frequency);
+
+        Channel {
+            frequency,
+            number: channel(frequency),
+            max_tx_power: channel_info.nrf_wifi_max_power,
+            flags: ChannelFlags(channel_info.nrf_wifi_flags),
+        }
+    }
+
+    /// Whether the channel can be used at all.
+    pub fn is_enabled(&self) -> bool {
+        !self.flags.contains(ChannelFlags::DISABLED)
+    }
+
+    /// Whether probe requests can be sent on the channel, i.e. if it can be scanned actively.
+    pub fn allows_active_scan(&self) -> bool {
+        self.is_enabled() && !self.flags.contains(ChannelFlags::NO_IR)
+    }
+}
+
+/// The regulatory flags of a channel (`nrf_wifi_channel_flags`).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelFlags(pub u16);
+
+impl ChannelFlags {
+    pub const DISABLED: Self = Self::new(nrf_wifi_channel_flags::CHAN_DISABLED);
+    /// Nothing may be transmitted before a beacon is heard, so no probe requests are sent
+    pub const NO_IR: Self = Self::new(nrf_wifi_channel_flags::CHAN_NO_IR);
+    /// Radar detection is required
+    pub const RADAR: Self = Self::new(nrf_wifi_channel_flags::CHAN_RADAR);
+    pub const NO_HT40_PLUS: Self = Self::new(nrf_wifi_channel_flags::CHAN_NO_HT40PLUS);
+    pub const NO_HT40_MINUS: Self = Self::new(nrf_wifi_channel_flags::CHAN_NO_HT40MINUS);
+    pub const NO_OFDM: Self = Self::new(nrf_wifi_channel_flags::CHAN_NO_OFDM);
+    pub const NO_80MHZ: Self = Self::new(nrf_wifi_channel_flags::CHAN_NO_80MHZ);
+    pub const NO_160MHZ: Self = Self::new(nrf_wifi_channel_flags::CHAN_NO_160MHZ);
+    pub const INDOOR_ONLY: Self = Self::new(nrf_wifi_channel_flags::CHAN_INDOOR_ONLY);
+    pub const GO_CONCURRENT: Self = Self::new(nrf_wifi_channel_flags::CHAN_GO_CONCURRENT);
+    pub const NO_20MHZ: Self = Self::new(nrf_wifi_channel_flags::CHAN_NO_20MHZ);
+    pub const NO_10MHZ: Self = Self::new(nrf_wifi_channel_flags::CHAN_NO_10MHZ);
+
+    const fn new(flag: nrf_wifi_channel_flags) -> Self {
+        Self(flag as u16)
+    }
+
+    /// Whether all the flags of `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Capabilities {
+    pub(crate) fn from_event(event: &nrf_wifi_event_get_wiphy) -> Self {
+        let params_valid = event.params_valid;
+
+        let bands = event.sband.iter().filter_map(BandCapabilities::from_event).collect();
+
+        let cipher_suites = event.cipher_suites;
+        let cipher_suites = cipher_suites
+            .iter()
+            .filter(|&&suite| suite != 0)
+            .map(|&suite| Suite(suite.to_be_bytes()))
+            .collect();
+
+        let extended_capabilities = if params_valid & NRF_WIFI_GET_WIPHY_VALID_EXTENDED_CAPABILITIES != 0 {
+            let length = usize::from(event.extended_capabilities_len).min(MAX_EXTENDED_CAPABILITIES_LEN);
+            Vec::from_slice(&event.extended_capabilities[..length]).unwrap()
+        } else {
+            Vec::new()
+        };
+
+        Capabilities {
+            bands,
+            cipher_suites,
+            interface_modes: event.interface_modes,
+            max_scan_ssids: (params_valid & NRF_WIFI_GET_WIPHY_VALID_MAX_NUM_SCAN_SSIDS != 0)
+                .then_some(event.max_scan_ssids),
+            max_scan_ie_length: event.max_scan_ie_len,
+            max_ap_stations: (params_valid & NRF_WIFI_GET_WIPHY_VALID_MAX_AP_ASSOC_STA != 0)
+                .then_some(event.max_ap_assoc_sta),
+            retry_short: event.retry_short,
+            retry_long: event.retry_long,
+            extended_capabilities,
+        }
+    }
+
+    /// Whether the firmware can run an interface in the given mode.
+    pub fn supports(&self, mode: InterfaceMode) -> bool {
+        self.interface_modes & (1 << mode.iftype() as u16) != 0
+    }
+
+    /// The capabilities of a band, `None` if it is not supported.
+    pub fn band(&self, band: Band) -> Option<&BandCapabilities> {
+        self.bands.iter().find(|capabilities| capabilities.band == band)
+    }
+
+    /// Looks up a channel by its number.
+    pub fn channel(&self, number: u8) -> Option<&Channel> {
+        self.bands
+            .iter()
+            .flat_map(|band| band.channels.iter())
+            .find(|channel| channel.number == number)
+    }
+}
+
+impl BandCapabilities {
+    fn from_event(band: &nrf_wifi_event_supported_band) -> Option<Self> {
+        let kind = match band.band {
+            value if value == nrf_wifi_band::NRF_WIFI_BAND_2GHZ as i8 => Band::Band2G4,
+            value if value == nrf_wifi_band::NRF_WIFI_BAND_5GHZ as i8 => Band::Band5G,
+            _ => return None,
+        };
+
+        let number_of_channels = usize::from(band.nrf_wifi_n_channels).min(MAX_CHANNELS);
+        let number_of_bitrates = usize::from(band.nrf_wifi_n_bitrates).min(MAX_BITRATES);
+
+        if number_of_channels == 0 {
+            return None;
+        }
+
+        let channels = band.channels[..number_of_channels]
+            .iter()
+            .filter(|channel| channel.ch_valid == 1)
+            .map(Channel::from_event)
+            .collect();
+
+        let bitrates = band.bitrates[..number_of_bitrates]
+            .iter()
+            .map(|rate| rate.nrf_wifi_bitrate)
+            .collect();
+
+        let ht = band.ht_cap;
+        let mcs = ht.mcs;
+
+        // Laid out as the supported MCS set field of the HT capabilities element
+        let mut supported_mcs_set = [0; 16];
+        supported_mcs_set[..10].copy_from_slice(&mcs.nrf_wifi_rx_mask);
+        supported_mcs_set[10..12].copy_from_slice(&mcs.nrf_wifi_rx_highest.to_le_bytes());
+        supported_mcs_set[12] = mcs.nrf_wifi_tx_params;
+
+        let vht = band.vht_cap;
+        let vht_mcs = vht.vht_mcs;
+
+        Some(BandCapabilities {
+            band: kind,
+            channels,
+            bitrates,
+            ht: (ht.nrf_wifi_ht_supported == 1).then_some(HtCapabilities {
+                capability_information: ht.nrf_wifi_cap,
+                ampdu_parameters: (ht.nrf_wifi_ampdu_factor & 0x03) | ((ht.nrf_wifi_ampdu_density & 0x07) << 2),
+                supported_mcs_set,
+                extended_capabilities: 0,
+                transmit_beamforming_capabilities: 0,
+                asel_capabilities: 0,
+            }),
+            vht: (vht.nrf_wifi_vht_supported == 1).then_some(VhtCapabilities {
+                capability_information: vht.nrf_wifi_cap,
+                rx_mcs_map: vht_mcs.rx_mcs_map,
+                rx_highest_data_rate: vht_mcs.rx_highest,
+                tx_mcs_map: vht_mcs.tx_mcs_map,
+                tx_highest_data_rate: vht_mcs.tx_highest,
+            }),
+        })
+    }
+
+    /// The highest maximum transmit power of the enabled channels in dBm.
+    pub fn max_tx_power(&self) -> Option<i32> {
+        self.channels
+            .iter()
+            .filter(|channel| channel.is_enabled())
+            .map(|channel| channel.max_tx_power)
+            .max()
+    }
+}
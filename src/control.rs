@@ -1,22 +1,70 @@
-use core::{mem::zeroed, ptr};
+use core::{cell::Cell, mem::zeroed, ptr};
 
-use embassy_time::Duration;
+use embassy_net_driver_channel::driver::{HardwareAddress, LinkState};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use embassy_sync::pubsub::WaitResult;
+use embassy_time::{with_timeout, Duration};
+use heapless::Vec;
+use rand_core::{CryptoRng, CryptoRngCore, RngCore};
 
 use crate::{
     action::{Action, Item},
     bindings::{
-        host_rpu_umac_info, nrf_wifi_cmd_get_stats, nrf_wifi_ps_state, nrf_wifi_sys_umac_event_stats,
-        nrf_wifi_umac_change_macaddr_info, nrf_wifi_umac_cmd_change_macaddr, nrf_wifi_umac_cmd_chg_sta,
-        nrf_wifi_umac_cmd_chg_vif_state, nrf_wifi_umac_cmd_get_scan_results, nrf_wifi_umac_cmd_mcast_filter,
-        nrf_wifi_umac_cmd_mgmt_frame_reg, nrf_wifi_umac_cmd_scan, nrf_wifi_umac_cmd_set_power_save,
-        nrf_wifi_umac_frame_match, nrf_wifi_umac_hdr, nrf_wifi_umac_mcast_cfg, nrf_wifi_umac_mgmt_frame_info,
-        nrf_wifi_umac_set_power_save_info, NRF_WIFI_CMD_SET_STATION_STA_FLAGS2_VALID,
+        host_rpu_umac_info, nrf_wifi_auth_type, nrf_wifi_band, nrf_wifi_cmd_get_stats, nrf_wifi_cmd_get_wiphy,
+        nrf_wifi_event_get_wiphy, nrf_wifi_ie, nrf_wifi_mfp, nrf_wifi_ps_state, nrf_wifi_sae, nrf_wifi_ssid,
+        nrf_wifi_sys_umac_event_stats, nrf_wifi_umac_change_macaddr_info, nrf_wifi_umac_cmd_assoc,
+        nrf_wifi_umac_cmd_auth, nrf_wifi_umac_cmd_change_macaddr, nrf_wifi_umac_cmd_chg_sta,
+        nrf_wifi_umac_cmd_chg_vif_state, nrf_wifi_umac_cmd_disconn, nrf_wifi_umac_cmd_get_scan_results,
+        nrf_wifi_umac_cmd_mcast_filter, nrf_wifi_umac_cmd_mgmt_frame_reg, nrf_wifi_umac_cmd_scan,
+        nrf_wifi_umac_cmd_set_power_save, nrf_wifi_umac_frame_match, nrf_wifi_umac_hdr, nrf_wifi_umac_mcast_cfg,
+        nrf_wifi_umac_mgmt_frame_info, nrf_wifi_umac_set_power_save_info, scan_reason,
+        NRF_WIFI_CMD_AUTHENTICATE_BSSID_VALID, NRF_WIFI_CMD_AUTHENTICATE_FREQ_VALID,
+        NRF_WIFI_CMD_AUTHENTICATE_SAE_VALID, NRF_WIFI_CMD_AUTHENTICATE_SSID_VALID, NRF_WIFI_CMD_MLME_MAC_ADDR_VALID,
+        NRF_WIFI_CMD_SET_STATION_STA_FLAGS2_VALID, NRF_WIFI_CONNECT_COMMON_INFO_AKM_SUITES_VALID,
+        NRF_WIFI_CONNECT_COMMON_INFO_CIPHER_SUITES_PAIRWISE_VALID,
+        NRF_WIFI_CONNECT_COMMON_INFO_CIPHER_SUITE_GROUP_VALID, NRF_WIFI_CONNECT_COMMON_INFO_FREQ_VALID,
+        NRF_WIFI_CONNECT_COMMON_INFO_MAC_ADDR_VALID, NRF_WIFI_CONNECT_COMMON_INFO_SSID_VALID,
+        NRF_WIFI_CONNECT_COMMON_INFO_USE_MFP_VALID, NRF_WIFI_CONNECT_COMMON_INFO_WPA_IE_VALID,
+        NRF_WIFI_CONNECT_COMMON_INFO_WPA_VERSIONS_VALID, NRF_WIFI_FMAC_CIPHER_SUITE_CCMP, NRF_WIFI_MAX_IE_LEN,
+        NRF_WIFI_MAX_SSID_LEN, NRF_WIFI_SCAN_MAX_NUM_FREQUENCIES, NRF_WIFI_SCAN_MAX_NUM_SSIDS, NRF_WIFI_WPA_VERSION_2,
+    },
+    capabilities::Capabilities,
+    events::{AuthenticationFrame, BssInfo, ControlEvent, ControlEventSubscriber, EventSubscriber},
+    fmt::Bytes,
+    ie::Elements,
+    rpu::commands::{Command, ScanCommand},
+    sa_query,
+    supplicant::{
+        check_rsn_element,
+        crypto::{derive_pmk, PMK_LENGTH},
+        sae::{self, Sae},
+        Akm, Supplicant, SupplicantError, RSNXE_HASH_TO_ELEMENT,
     },
-    rpu::commands::Command,
     util::sliceit,
     Control, Error,
 };
 
+/// How long to wait for a scan to finish.
+const SCAN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the RPU to boot, which includes loading the firmware.
+const BOOT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long each step of a [`Control`] call may take by default, see [`Control::set_timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest SAE commit or confirm body, limited by the SAE data of the authentication command
+/// which also holds the transaction sequence number and status code.
+const SAE_BODY_MAX_SIZE: usize = 256 - 4;
+
+/// How many times the SAE commit is sent with a new anti-clogging token before giving up.
+const SAE_MAX_COMMIT_ATTEMPTS: usize = 3;
+
+/// Reason code sent to the AP when leaving the network (deauthenticated because sending station
+/// is leaving).
+const DEAUTHENTICATION_REASON_LEAVING: u16 = 3;
+
 /// WiFi scan type.
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -29,16 +77,33 @@ pub enum ScanType {
     Passive,
 }
 
+/// A frequency band.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Band {
+    /// 2.4 GHz, channels 1 to 14
+    Band2G4,
+    /// 5 GHz, channels 32 to 177
+    Band5G,
+}
+
 /// Scan options.
 #[derive(Copy, Clone, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[non_exhaustive]
-pub struct ScanOptions {
-    /// SSID to scan for.
-    // pub ssid: Option<heapless::String<32>>,
+pub struct ScanOptions<'a> {
+    /// SSIDs to probe for, at most 2. Hidden networks only answer probes for their SSID, so they
+    /// are found by an active scan for it.
+    pub ssids: &'a [&'a [u8]],
     /// If set to `None`, all APs will be returned. If set to `Some`, only APs
     /// with the specified BSSID will be returned.
     pub bssid: Option<[u8; 6]>,
+    /// Channels to scan, at most 64. All channels of the band are scanned if empty.
+    pub channels: &'a [u8],
+    /// Band to scan. Both are scanned if set to `None`.
+    pub band: Option<Band>,
+    /// Additional information elements to put in the probe requests of an active scan.
+    pub probe_ies: &'a [u8],
     /// Number of probes to send on each channel.
     pub nprobes: Option<u16>,
     /// Time to spend waiting on the home channel.
@@ -49,10 +114,14 @@ pub struct ScanOptions {
     pub dwell_time: Option<Duration>,
 }
 
-impl Default for ScanOptions {
+impl Default for ScanOptions<'_> {
     fn default() -> Self {
         Self {
+            ssids: &[],
             bssid: None,
+            channels: &[],
+            band: None,
+            probe_ies: &[],
             nprobes: None,
             home_time: None,
             scan_type: ScanType::Passive,
@@ -61,18 +130,206 @@ impl Default for ScanOptions {
     }
 }
 
+/// The security of a network, as advertised in its beacons and probe responses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Security {
+    Open,
+    Wep,
+    /// The original WPA, which is not supported for joining
+    Wpa,
+    Wpa2Personal,
+    /// WPA3-Personal transition mode, accepting both PSK and SAE
+    Wpa2Wpa3Personal,
+    Wpa3Personal,
+    /// 802.1X authentication
+    Enterprise,
+    /// An RSN network with an AKM suite not listed above
+    Unknown,
+}
+
+/// A network found in a scan.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanResult {
+    pub bssid: [u8; 6],
+    /// Empty for hidden networks.
+    pub ssid: Vec<u8, 32>,
+    /// Frequency of the primary channel in MHz.
+    pub frequency: u32,
+    /// Number of the primary channel, 0 if the frequency is not in a known band.
+    pub channel: u8,
+    /// Signal strength in dBm.
+    pub rssi: i32,
+    /// The capability information field of the beacon or probe response.
+    pub capability: u16,
+    pub security: Security,
+    ies: Vec<u8, { NRF_WIFI_MAX_IE_LEN as usize }>,
+}
+
+impl ScanResult {
+    fn from_bss(bss: BssInfo) -> Self {
+        ScanResult {
+            bssid: bss.bssid,
+            ssid: bss
+                .ssid()
+                .and_then(|ssid| Vec::from_slice(ssid).ok())
+                .unwrap_or_default(),
+            frequency: bss.frequency,
+            channel: channel(bss.frequency),
+            rssi: bss.signal / 100,
+            capability: bss.capability,
+            security: bss.security(),
+            ies: bss.ies,
+        }
+    }
+
+    /// The information elements of the probe response, or the beacon if there was none, which
+    /// tell what the network supports beyond the fields above.
+    pub fn elements(&self) -> Elements<'_> {
+        Elements::new(&self.ies)
+    }
+}
+
+/// The channel number of a frequency in MHz in the 2.4, 5 and 6 GHz bands (IEEE 802.11-2020
+/// 15.4.4.3, 17.3.8.4.2 and E.1).
+pub(crate) fn channel(frequency: u32) -> u8 {
+    let channel = match frequency {
+        2484 => 14,
+        2412..=2472 => (frequency - 2407) / 5,
+        5160..=5885 => (frequency - 5000) / 5,
+        5955..=7115 => (frequency - 5950) / 5,
+        _ => 0,
+    };
+
+    channel as u8
+}
+
+/// The center frequency in MHz of a channel in the 2.4 and 5 GHz bands, the inverse of
+/// [`channel`].
+fn frequency(channel: u8) -> Option<u32> {
+    match channel {
+        14 => Some(2484),
+        1..=13 => Some(2407 + 5 * u32::from(channel)),
+        32..=177 => Some(5000 + 5 * u32::from(channel)),
+        _ => None,
+    }
+}
+
+/// The state shared by the handles of [`Control`].
+pub(crate) struct ControlState {
+    /// Held through the calls which take several steps on the RPU, so that those of different
+    /// handles do not interleave
+    operation: Mutex<NoopRawMutex, ()>,
+
+    /// The MAC address of the interface, set during init
+    mac_address: Cell<[u8; 6]>,
+
+    /// The BSS we are associated with, if any
+    bssid: Cell<Option<[u8; 6]>>,
+}
+
+impl ControlState {
+    pub(crate) const fn new() -> Self {
+        Self {
+            operation: Mutex::new(()),
+            mac_address: Cell::new([0; 6]),
+            bssid: Cell::new(None),
+        }
+    }
+
+    /// Forgets the BSS we were associated with, once the association has ended.
+    pub(crate) fn disassociated(&self) {
+        self.bssid.set(None);
+    }
+}
+
+/// Streams the results of a scan started with [`Control::scan`]. Other handles of [`Control`]
+/// cannot scan, join or leave a network until it is dropped.
+pub struct Scanner<'c, 'a> {
+    control: &'c mut Control<'a>,
+    subscriber: ControlEventSubscriber<'a>,
+    state: ScannerState,
+    _operation: MutexGuard<'a, NoopRawMutex, ()>,
+}
+
+enum ScannerState {
+    /// Waiting for the firmware to finish the scan
+    Scanning,
+    /// Receiving the results of a finished scan
+    Results,
+    Done,
+}
+
+impl Scanner<'_, '_> {
+    /// Waits for the next network found. Gives `None` once all results have been received, which
+    /// happens after the scan is done, or right away if it was aborted. Fails with
+    /// [`Error::Timeout`] if the results stop before the last one.
+    pub async fn next(&mut self) -> Result<Option<ScanResult>, Error> {
+        loop {
+            match self.state {
+                ScannerState::Scanning => {
+                    let done = wait_for_scan(&mut self.subscriber, self.control.timeout).await;
+                    self.state = ScannerState::Done;
+
+                    if done? {
+                        self.control.request_scan_results().await?;
+                        self.state = ScannerState::Results;
+                    }
+                }
+                ScannerState::Results => {
+                    let result = next_scan_result(&mut self.subscriber, self.control.timeout).await;
+                    self.state = ScannerState::Done;
+
+                    let Some((bss, last)) = result? else {
+                        return Ok(None);
+                    };
+
+                    if !last {
+                        self.state = ScannerState::Results;
+                    }
+
+                    return Ok(Some(ScanResult::from_bss(bss)));
+                }
+                ScannerState::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+/// What is used to authenticate with the network while joining it.
+enum Credentials<'a> {
+    Open,
+    Psk {
+        pmk: [u8; PMK_LENGTH],
+        rng: &'a mut dyn CryptoRngCore,
+    },
+    Sae {
+        password: &'a [u8],
+        rng: &'a mut dyn CryptoRngCore,
+    },
+}
+
 #[allow(dead_code)]
 impl<'a> Control<'a> {
+    /// Boots the RPU with `firmware` and sets the MAC address. A call made after another was
+    /// dropped or timed out during the boot waits for that boot rather than starting over.
     pub async fn init(&mut self, firmware: &'static [u8]) -> Result<(), Error> {
-        self.action_state.issue(Action::Boot(firmware)).await.map(|_| ())?;
+        let _operation = self.shared.operation.lock().await;
+
+        with_timeout(
+            BOOT_TIMEOUT.max(self.timeout),
+            self.action_state.issue(Action::Boot(firmware)),
+        )
+        .await
+        .map_err(|_| Error::Timeout)??;
         info!("Boot done");
 
         // --- Update MAC address ---
 
         let mut umac_info_buffer = [0u8; size_of::<host_rpu_umac_info>()];
 
-        self.action_state
-            .issue(Action::Get((Item::UmacInfo, &mut umac_info_buffer[..])))
+        self.issue(Action::Get((Item::UmacInfo, &mut umac_info_buffer[..])))
             .await?;
 
         let umac_info: host_rpu_umac_info = unsafe { core::mem::transmute_copy(&umac_info_buffer) };
@@ -94,7 +351,6 @@ impl<'a> Control<'a> {
         command.prepare();
 
         match self
-            .action_state
             .issue(Action::Command((command.domain(), true, sliceit(&command), None)))
             .await
         {
@@ -110,6 +366,11 @@ impl<'a> Control<'a> {
             mac_address[0], mac_address[1], mac_address[2], mac_address[3], mac_address[4], mac_address[5]
         );
 
+        // Frames from the network stack use the hardware address as source address
+        self.state_ch
+            .set_hardware_address(HardwareAddress::Ethernet(mac_address));
+        self.shared.mac_address.set(mac_address);
+
         // --- Bring interface up ---
 
         let mut command = nrf_wifi_umac_cmd_chg_vif_state::default();
@@ -117,7 +378,6 @@ impl<'a> Control<'a> {
         command.prepare();
 
         match self
-            .action_state
             .issue(Action::Command((command.domain(), true, sliceit(&command), None)))
             .await
         {
@@ -149,7 +409,6 @@ impl<'a> Control<'a> {
         command.prepare();
 
         match self
-            .action_state
             .issue(Action::Command((command.domain(), true, sliceit(&command), None)))
             .await
         {
@@ -187,7 +446,6 @@ impl<'a> Control<'a> {
         command.prepare();
 
         match self
-            .action_state
             .issue(Action::Command((command.domain(), true, sliceit(&command), None)))
             .await
         {
@@ -203,11 +461,9 @@ impl<'a> Control<'a> {
         /* WNM - BSS Transition Management Request */
         /* Radio Measurement - Neighbor Report Response */
         /* Radio Measurement - Radio Measurement Request */
-        let frames: [[u8; 2]; 3] = [[0x0a, 0x07], [0x05, 0x05], [0x05, 0x00]];
+        /* SA Query - Response */
+        let frames: [[u8; 2]; 4] = [[0x0a, 0x07], [0x05, 0x05], [0x05, 0x00], sa_query::RESPONSE_MATCH];
         const WLAN_FC_TYPE_MGMT: u16 = 0;
-        const WLAN_FC_TYPE_CTRL: u16 = 1;
-        const WLAN_FC_TYPE_DATA: u16 = 2;
-
         const WLAN_FC_STYPE_ACTION: u16 = 13;
 
         for frame in frames {
@@ -227,7 +483,6 @@ impl<'a> Control<'a> {
             command.prepare();
 
             match self
-                .action_state
                 .issue(Action::Command((command.domain(), false, sliceit(&command), None)))
                 .await
             {
@@ -239,27 +494,6 @@ impl<'a> Control<'a> {
             };
         }
 
-        // --- Get wiphy ---
-        //
-        // TODO: need to handle large messages for this
-        //
-        // let mut command = nrf_wifi_cmd_get_wiphy {
-        //     umac_hdr: nrf_wifi_umac_hdr::default(),
-        // };
-        // command.prepare();
-        //
-        // match self
-        //     .action_state
-        //     .issue(Action::Command((command.domain(), true, sliceit(&command), None)))
-        //     .await
-        // {
-        //     Ok(_) => {}
-        //     Err(err) => {
-        //         error!("Failed to register frame: {:?}", err);
-        //         return Err(err);
-        //     }
-        // };
-
         // --- Delete 6 keys ---
         //
         // TOOD: Unsure of which here or if this is just clearing some keys
@@ -275,7 +509,6 @@ impl<'a> Control<'a> {
         command.prepare();
 
         match self
-            .action_state
             .issue(Action::Command((command.domain(), true, sliceit(&command), None)))
             .await
         {
@@ -288,8 +521,34 @@ impl<'a> Control<'a> {
         Ok(())
     }
 
-    pub async fn scan(&mut self, options: ScanOptions) -> Result<(), Error> {
+    /// Starts a scan for networks, whose results are streamed by the returned [`Scanner`]. Fails
+    /// with [`Error::InvalidArgument`] if the options exceed what the firmware supports.
+    pub async fn scan(&mut self, options: ScanOptions<'_>) -> Result<Scanner<'_, 'a>, Error> {
+        if options.ssids.len() > NRF_WIFI_SCAN_MAX_NUM_SSIDS as usize
+            || options
+                .ssids
+                .iter()
+                .any(|ssid| ssid.len() > NRF_WIFI_MAX_SSID_LEN as usize)
+            || options.probe_ies.len() > NRF_WIFI_MAX_IE_LEN as usize
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut frequencies: Vec<u32, { NRF_WIFI_SCAN_MAX_NUM_FREQUENCIES as usize }> = Vec::new();
+        for channel in options.channels {
+            let frequency = frequency(*channel).ok_or(Error::InvalidArgument)?;
+            frequencies.push(frequency).map_err(|_| Error::InvalidArgument)?;
+        }
+
+        let operation = self.shared.operation.lock().await;
+
+        // Subscribed before the scan starts so that no event is missed
+        let subscriber = self.events.subscriber().map_err(|_| Error::Busy)?;
+
+        // Results of connect scans come with the information elements, which the security is
+        // derived from
         let mut command = nrf_wifi_umac_cmd_scan::default();
+        command.info.scan_reason = scan_reason::SCAN_CONNECT as i32;
 
         match options.scan_type {
             ScanType::Active => {
@@ -312,31 +571,586 @@ impl<'a> Control<'a> {
             command.info.scan_params.mac_addr = bssid;
         }
 
-        command.info.scan_params.num_scan_channels = 20;
+        let mut scan_ssids = [nrf_wifi_ssid::new(&[]); NRF_WIFI_SCAN_MAX_NUM_SSIDS as usize];
+        for (scan_ssid, ssid) in scan_ssids.iter_mut().zip(options.ssids) {
+            *scan_ssid = nrf_wifi_ssid::new(ssid);
+        }
+        command.info.scan_params.scan_ssids = scan_ssids;
+        command.info.scan_params.num_scan_ssids = options.ssids.len() as u8;
 
-        match self
-            .action_state
-            .issue(Action::Command((command.domain(), true, sliceit(&command), None)))
+        // A bitmap of the bands, where none means all of them
+        command.info.scan_params.bands = match options.band {
+            Some(Band::Band2G4) => 1 << nrf_wifi_band::NRF_WIFI_BAND_2GHZ as u8,
+            Some(Band::Band5G) => 1 << nrf_wifi_band::NRF_WIFI_BAND_5GHZ as u8,
+            None => 0,
+        };
+
+        command.info.scan_params.ie = nrf_wifi_ie::new(options.probe_ies);
+
+        let command = ScanCommand::new(command, &frequencies);
+
+        self.issue(Action::Command((command.domain(), false, command.as_bytes(), None)))
+            .await?;
+
+        Ok(Scanner {
+            control: self,
+            subscriber,
+            state: ScannerState::Scanning,
+            _operation: operation,
+        })
+    }
+
+    /// Asks the firmware for the results of the last scan, which arrive as scan result events.
+    async fn request_scan_results(&mut self) -> Result<(), Error> {
+        let command = nrf_wifi_umac_cmd_get_scan_results::default();
+
+        self.issue(Action::Command((command.domain(), false, sliceit(&command), None)))
             .await
-        {
-            Ok(_) => Ok(()),
-            Err(error) => {
-                error!("Failed to get stats: {:?}", error);
+            .map(|_| ())
+    }
+
+    /// Joins an open network, i.e. one without any security.
+    ///
+    /// Scans for the network, authenticates and associates with it, and waits until the link is
+    /// up. If `bssid` is given only that BSS is joined, otherwise the BSS with the strongest signal
+    /// advertising `ssid` is used.
+    pub async fn join_open(&mut self, ssid: &str, bssid: Option<[u8; 6]>) -> Result<(), Error> {
+        self.join(ssid, bssid, Credentials::Open).await
+    }
+
+    /// Joins a WPA2-Personal network, using CCMP and a pre-shared key derived from `passphrase`.
+    ///
+    /// Works like [`Control::join_open`], but also runs the 4-way handshake with the AP and
+    /// installs the keys before the link is up. The SNonces of the handshakes are taken from `rng`.
+    /// Deriving the key from the passphrase is slow, it takes a few seconds on a microcontroller.
+    pub async fn join_wpa2(
+        &mut self,
+        ssid: &str,
+        passphrase: &str,
+        bssid: Option<[u8; 6]>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(), Error> {
+        let pmk = derive_pmk(passphrase.as_bytes(), ssid.as_bytes()).map_err(Error::Supplicant)?;
+
+        self.join(ssid, bssid, Credentials::Psk { pmk, rng }).await
+    }
+
+    /// Joins a WPA3-Personal network, using SAE with `password`, CCMP and management frame
+    /// protection.
+    ///
+    /// Works like [`Control::join_wpa2`], but authenticates with the SAE commit and confirm
+    /// exchange, which gives the PMK for the 4-way handshake. The password element is derived with
+    /// hash-to-element if the AP supports it, hunting-and-pecking otherwise. The secrets of the
+    /// exchange and the SNonces are taken from `rng`.
+    pub async fn join_wpa3(
+        &mut self,
+        ssid: &str,
+        password: &str,
+        bssid: Option<[u8; 6]>,
+        rng: &mut (impl RngCore + CryptoRng),
+    ) -> Result<(), Error> {
+        if password.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.join(
+            ssid,
+            bssid,
+            Credentials::Sae {
+                password: password.as_bytes(),
+                rng,
+            },
+        )
+        .await
+    }
+
+    /// Joins a network with the given credentials.
+    async fn join(&mut self, ssid: &str, bssid: Option<[u8; 6]>, credentials: Credentials<'_>) -> Result<(), Error> {
+        let ssid = ssid.as_bytes();
+
+        if ssid.is_empty() || ssid.len() > NRF_WIFI_MAX_SSID_LEN as usize {
+            return Err(Error::InvalidArgument);
+        }
+
+        let _operation = self.shared.operation.lock().await;
+        let mut subscriber = self.events.subscriber().map_err(|_| Error::Busy)?;
+
+        // --- Find the network ---
+
+        let bss = self.find_bss(&mut subscriber, ssid, bssid).await?;
+
+        info!(
+            "Joining {:02x} on {} MHz, signal {} mBm",
+            Bytes(&bss.bssid),
+            bss.frequency,
+            bss.signal
+        );
+
+        let akm = match credentials {
+            Credentials::Open => None,
+            Credentials::Psk { .. } => Some(Akm::Psk),
+            Credentials::Sae { .. } => Some(Akm::Sae),
+        };
+
+        // The elements we send in the association request, which the supplicant repeats in
+        // message 2
+        let mut own_elements: Vec<u8, 32> = Vec::new();
+        let hash_to_element = akm == Some(Akm::Sae) && bss.supports_sae_hash_to_element();
+        let mut management_frame_protection = false;
+
+        if let Some(akm) = akm {
+            let Some(rsn_element) = bss.rsn_element() else {
+                error!("The network does not advertise WPA2 or WPA3");
+                return Err(Error::Supplicant(SupplicantError::UnsupportedNetwork));
+            };
+
+            management_frame_protection = check_rsn_element(rsn_element, akm).map_err(Error::Supplicant)?;
+
+            if management_frame_protection {
+                info!("Using management frame protection");
+            }
+
+            let _ = own_elements.extend_from_slice(akm.rsn_element(management_frame_protection));
+
+            if hash_to_element {
+                let _ = own_elements.extend_from_slice(&RSNXE_HASH_TO_ELEMENT);
+            }
+        }
+
+        // --- Authenticate ---
+
+        // The PMK for the handshakes, and where the supplicant takes its SNonces from
+        let keys = match credentials {
+            Credentials::Open => {
+                self.authenticate(&mut subscriber, ssid, &bss).await?;
+                None
+            }
+            Credentials::Psk { pmk, rng } => {
+                self.authenticate(&mut subscriber, ssid, &bss).await?;
+                Some((pmk, rng))
+            }
+            Credentials::Sae { password, rng } => {
+                match self
+                    .authenticate_sae(&mut subscriber, ssid, &bss, password, hash_to_element, rng)
+                    .await
+                {
+                    Ok(pmk) => Some((pmk, rng)),
+                    Err(error) => {
+                        // Best effort, the firmware may be in the middle of the exchange
+                        let _ = self.deauthenticate(bss.bssid).await;
+                        return Err(error);
+                    }
+                }
+            }
+        };
+
+        info!("Authenticated");
+
+        // --- Set up the supplicant ---
+        //
+        // The AP starts the handshake right after the association, so the runner needs the
+        // supplicant before that
+
+        let supplicant = match (akm, keys, bss.rsn_element()) {
+            (Some(akm), Some((pmk, rng)), Some(rsn_element)) => Some(
+                Supplicant::new(
+                    akm,
+                    pmk,
+                    rng,
+                    self.shared.mac_address.get(),
+                    &own_elements,
+                    bss.bssid,
+                    rsn_element,
+                )
+                .map_err(Error::Supplicant)?,
+            ),
+            _ => None,
+        };
+
+        // Recorded before the runner gets the supplicant and the association is sent, so that
+        // leaving still ends the association if the call is dropped from here on
+        self.shared.bssid.set(Some(bss.bssid));
+
+        if let Some(supplicant) = &supplicant {
+            if let Err(error) = self.issue(Action::Supplicant(Some(supplicant))).await {
+                self.shared.disassociated();
                 return Err(error);
             }
         }
 
-        // TODO: wait for scan done? Weird that the Zephyr samples receives that event
-        // but this does not for some reason...
+        // --- Associate ---
+
+        let protection = akm.map(|akm| (akm, &own_elements[..]));
+
+        if let Err(error) = self
+            .associate(&mut subscriber, ssid, &bss, protection, management_frame_protection)
+            .await
+        {
+            self.remove_supplicant().await;
+            self.shared.disassociated();
+            return Err(error);
+        }
+
+        // --- Wait for the link ---
+
+        let result = wait_for_event(&mut subscriber, self.timeout, |event| match event {
+            ControlEvent::CarrierOn if supplicant.is_none() => Some(Ok(())),
+            ControlEvent::PortAuthorized => Some(Ok(())),
+            ControlEvent::HandshakeFailed(error) => Some(Err(Error::Supplicant(error))),
+            ControlEvent::Deauthenticate | ControlEvent::Disassociate | ControlEvent::Disconnect => {
+                Some(Err(Error::NoAcknowledgement))
+            }
+            _ => None,
+        })
+        .await;
+
+        if let Err(error) = result {
+            error!("Failed to bring up the link: {:?}", error);
+
+            // Best effort, we are giving up on the network anyway. Still associated if the
+            // deauthentication is not out, so that leaving can be retried
+            if self.deauthenticate(bss.bssid).await.is_ok() {
+                self.shared.disassociated();
+            }
+            self.remove_supplicant().await;
+
+            return Err(error);
+        }
+
+        info!("Joined network");
+
+        Ok(())
     }
 
-    pub async fn get_scan_results(&mut self) -> Result<(), Error> {
-        let command = nrf_wifi_umac_cmd_get_scan_results::default();
+    /// Authenticates with open system authentication.
+    async fn authenticate(
+        &mut self,
+        subscriber: &mut ControlEventSubscriber<'_>,
+        ssid: &[u8],
+        bss: &BssInfo,
+    ) -> Result<(), Error> {
+        let command = authentication_command(ssid, bss, nrf_wifi_auth_type::NRF_WIFI_AUTHTYPE_OPEN_SYSTEM);
 
-        let mut response = [0u8; 1024];
+        self.issue(Action::Command((command.domain(), false, sliceit(&command), None)))
+            .await?;
+
+        let frame = wait_for_authentication(subscriber, self.timeout).await?;
+
+        if frame.status != 0 {
+            error!("Authentication rejected with status {}", frame.status);
+            return Err(Error::Code(i32::from(frame.status)));
+        }
+
+        Ok(())
+    }
+
+    /// Authenticates with the SAE commit and confirm exchange and returns the PMK.
+    async fn authenticate_sae(
+        &mut self,
+        subscriber: &mut ControlEventSubscriber<'_>,
+        ssid: &[u8],
+        bss: &BssInfo,
+        password: &[u8],
+        hash_to_element: bool,
+        rng: &mut dyn CryptoRngCore,
+    ) -> Result<[u8; PMK_LENGTH], Error> {
+        let password_element = if hash_to_element {
+            sae::password_element_from_pt(
+                &sae::derive_pt(ssid, password, None),
+                &self.shared.mac_address.get(),
+                &bss.bssid,
+            )
+        } else {
+            sae::hunting_and_pecking(password, None, &self.shared.mac_address.get(), &bss.bssid)
+                .map_err(Error::Supplicant)?
+        };
+
+        let mut sae = Sae::new(&password_element, hash_to_element, rng);
+        let mut body = [0u8; SAE_BODY_MAX_SIZE];
+
+        // --- Commit ---
+
+        let mut token: Vec<u8, SAE_BODY_MAX_SIZE> = Vec::new();
+        let mut attempts = 0;
+
+        let commit = loop {
+            let length = sae
+                .write_commit((!token.is_empty()).then_some(&token[..]), &mut body)
+                .map_err(Error::Supplicant)?;
+
+            self.send_sae(ssid, bss, sae::TRANSACTION_COMMIT, sae.commit_status(), &body[..length])
+                .await?;
+
+            let frame = wait_for_authentication(subscriber, self.timeout).await?;
+
+            match frame.status {
+                sae::STATUS_ANTI_CLOGGING_TOKEN_REQUIRED if attempts < SAE_MAX_COMMIT_ATTEMPTS => {
+                    debug!("AP asked for an anti-clogging token");
+
+                    let received = sae.anti_clogging_token(&frame.body).map_err(Error::Supplicant)?;
+                    token = Vec::from_slice(received).map_err(|()| Error::Supplicant(SupplicantError::InvalidFrame))?;
+                    attempts += 1;
+                }
+                status @ (sae::STATUS_SUCCESS | sae::STATUS_SAE_HASH_TO_ELEMENT)
+                    if frame.transaction == sae::TRANSACTION_COMMIT =>
+                {
+                    if status != sae.commit_status() {
+                        error!("SAE commit with status {}, which does not match ours", status);
+                        return Err(Error::Supplicant(SupplicantError::CommitStatusMismatch));
+                    }
+
+                    break frame;
+                }
+                status => {
+                    error!("SAE commit rejected with status {}", status);
+                    return Err(Error::Code(i32::from(status)));
+                }
+            }
+        };
+
+        sae.process_commit(&commit.body).map_err(Error::Supplicant)?;
+
+        // --- Confirm ---
+
+        let length = sae.write_confirm(&mut body).map_err(Error::Supplicant)?;
+
+        self.send_sae(
+            ssid,
+            bss,
+            sae::TRANSACTION_CONFIRM,
+            sae::STATUS_SUCCESS,
+            &body[..length],
+        )
+        .await?;
+
+        let confirm = wait_for_authentication(subscriber, self.timeout).await?;
+
+        if confirm.status != sae::STATUS_SUCCESS || confirm.transaction != sae::TRANSACTION_CONFIRM {
+            error!("SAE confirm rejected with status {}", confirm.status);
+            return Err(Error::Code(i32::from(confirm.status)));
+        }
+
+        sae.process_confirm(&confirm.body).map_err(Error::Supplicant)
+    }
+
+    /// Sends a SAE commit or confirm in an authentication frame.
+    async fn send_sae(
+        &mut self,
+        ssid: &[u8],
+        bss: &BssInfo,
+        transaction: u16,
+        status: u16,
+        body: &[u8],
+    ) -> Result<(), Error> {
+        let mut command = authentication_command(ssid, bss, nrf_wifi_auth_type::NRF_WIFI_AUTHTYPE_SAE);
+        command.valid_fields |= NRF_WIFI_CMD_AUTHENTICATE_SAE_VALID;
+        command.info.sae = nrf_wifi_sae::new(transaction, status, body);
+
+        self.issue(Action::Command((command.domain(), false, sliceit(&command), None)))
+            .await
+            .map(|_| ())
+    }
+
+    /// Associates with the BSS, announcing the AKM and the RSN elements for protected networks and
+    /// requiring management frame protection when it has been negotiated.
+    async fn associate(
+        &mut self,
+        subscriber: &mut ControlEventSubscriber<'_>,
+        ssid: &[u8],
+        bss: &BssInfo,
+        protection: Option<(Akm, &[u8])>,
+        management_frame_protection: bool,
+    ) -> Result<(), Error> {
+        let mut command = nrf_wifi_umac_cmd_assoc::default();
+        command.connect_common_info.valid_fields = NRF_WIFI_CONNECT_COMMON_INFO_MAC_ADDR_VALID
+            | NRF_WIFI_CONNECT_COMMON_INFO_FREQ_VALID
+            | NRF_WIFI_CONNECT_COMMON_INFO_SSID_VALID;
+        command.connect_common_info.frequency = bss.frequency;
+        command.connect_common_info.mac_addr = bss.bssid;
+        command.connect_common_info.ssid = nrf_wifi_ssid::new(ssid);
+
+        if let Some((akm, own_elements)) = protection {
+            let info = &mut command.connect_common_info;
+
+            info.valid_fields |= NRF_WIFI_CONNECT_COMMON_INFO_WPA_IE_VALID
+                | NRF_WIFI_CONNECT_COMMON_INFO_WPA_VERSIONS_VALID
+                | NRF_WIFI_CONNECT_COMMON_INFO_CIPHER_SUITES_PAIRWISE_VALID
+                | NRF_WIFI_CONNECT_COMMON_INFO_CIPHER_SUITE_GROUP_VALID
+                | NRF_WIFI_CONNECT_COMMON_INFO_AKM_SUITES_VALID;
+            info.wpa_ie = nrf_wifi_ie::new(own_elements);
+            info.wpa_versions = NRF_WIFI_WPA_VERSION_2;
+            info.num_cipher_suites_pairwise = 1;
+            info.cipher_suites_pairwise[0] = NRF_WIFI_FMAC_CIPHER_SUITE_CCMP;
+            info.cipher_suite_group = NRF_WIFI_FMAC_CIPHER_SUITE_CCMP;
+            info.num_akm_suites = 1;
+            info.akm_suites[0] = akm.suite();
+
+            // Once negotiated the firmware must drop unprotected robust management frames, WPA3-Personal
+            // always negotiates it
+            if management_frame_protection {
+                info.valid_fields |= NRF_WIFI_CONNECT_COMMON_INFO_USE_MFP_VALID;
+                info.use_mfp = nrf_wifi_mfp::NRF_WIFI_MFP_REQUIRED as i32;
+            }
+
+            // Keeps the port closed for data until the supplicant has authorized it
+            info.control_port = 1;
+        }
+
+        self.issue(Action::Command((command.domain(), false, sliceit(&command), None)))
+            .await?;
+
+        let status = wait_for_event(subscriber, self.timeout, |event| match event {
+            ControlEvent::Associate { status } => Some(status.ok_or(Error::Timeout)),
+            ControlEvent::Deauthenticate | ControlEvent::Disconnect => Some(Err(Error::NoAcknowledgement)),
+            _ => None,
+        })
+        .await?;
+
+        if status != 0 {
+            error!("Association rejected with status {}", status);
+            return Err(Error::Code(i32::from(status)));
+        }
+
+        info!("Associated");
+
+        Ok(())
+    }
+
+    /// Removes the supplicant from the runner, if any.
+    async fn remove_supplicant(&mut self) {
+        if let Err(error) = self.issue(Action::Supplicant(None)).await {
+            warn!("Failed to remove supplicant: {:?}", error);
+        }
+    }
+
+    /// Sends a deauthentication to the AP.
+    async fn deauthenticate(&mut self, bssid: [u8; 6]) -> Result<(), Error> {
+        let mut command = nrf_wifi_umac_cmd_disconn::default();
+        command.valid_fields = NRF_WIFI_CMD_MLME_MAC_ADDR_VALID;
+        command.info.reason_code = DEAUTHENTICATION_REASON_LEAVING;
+        command.info.mac_addr = bssid;
+
+        self.issue(Action::Command((command.domain(), false, sliceit(&command), None)))
+            .await
+            .map(|_| ())
+    }
+
+    /// Leaves the network joined with [`Control::join_open`], [`Control::join_wpa2`] or
+    /// [`Control::join_wpa3`]. Does nothing if no network is joined.
+    pub async fn leave(&mut self) -> Result<(), Error> {
+        let _operation = self.shared.operation.lock().await;
+
+        let Some(bssid) = self.shared.bssid.get() else {
+            return Ok(());
+        };
+
+        let mut subscriber = self.events.subscriber().map_err(|_| Error::Busy)?;
+
+        // Still associated until the deauthentication is out, so that leaving can be retried
+        self.deauthenticate(bssid).await?;
+        self.shared.disassociated();
+
+        let result = wait_for_event(&mut subscriber, self.timeout, |event| match event {
+            ControlEvent::Deauthenticate | ControlEvent::Disconnect => Some(Ok(())),
+            _ => None,
+        })
+        .await;
+
+        // The link is down regardless of whether the AP got the deauthentication
+        self.state_ch.set_link_state(LinkState::Down);
+        self.remove_supplicant().await;
+
+        info!("Left network");
+
+        result
+    }
+
+    /// Scans for `ssid` and picks the BSS to join from the results.
+    async fn find_bss(
+        &mut self,
+        subscriber: &mut ControlEventSubscriber<'_>,
+        ssid: &[u8],
+        bssid: Option<[u8; 6]>,
+    ) -> Result<BssInfo, Error> {
+        let mut command = nrf_wifi_umac_cmd_scan::default();
+        command.info.scan_reason = scan_reason::SCAN_CONNECT as i32;
+        command.info.scan_params.num_scan_ssids = 1;
+        command.info.scan_params.scan_ssids[0] = nrf_wifi_ssid::new(ssid);
+
+        if let Some(bssid) = bssid {
+            command.info.scan_params.mac_addr = bssid;
+        }
+
+        self.issue(Action::Command((command.domain(), false, sliceit(&command), None)))
+            .await?;
+
+        if !wait_for_scan(subscriber, self.timeout).await? {
+            return Err(Error::NoData);
+        }
+
+        self.request_scan_results().await?;
+
+        let mut best: Option<BssInfo> = None;
+
+        while let Some((bss, last)) = next_scan_result(subscriber, self.timeout).await? {
+            let is_match = bss.ssid() == Some(ssid) && bssid.is_none_or(|bssid| bssid == bss.bssid);
+
+            if is_match && best.as_ref().is_none_or(|best| bss.signal > best.signal) {
+                best = Some(bss);
+            }
+
+            if last {
+                break;
+            }
+        }
+
+        best.ok_or(Error::NotFound)
+    }
+
+    /// Subscribes to the events of the driver: connects and disconnects, beacon loss, scan results,
+    /// carrier changes, registered management frames and power monitor readings. A subscriber gets
+    /// the events published from then on, and if it falls more than
+    /// [`SUBSCRIBER_QUEUE_SIZE`](crate::events::SUBSCRIBER_QUEUE_SIZE) events behind it misses the
+    /// oldest.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Busy`] if there are [`MAX_SUBSCRIBERS`](crate::events::MAX_SUBSCRIBERS)
+    /// subscribers already.
+    pub fn subscribe(&self) -> Result<EventSubscriber<'a>, Error> {
+        self.event_stream.subscriber().map_err(|_| Error::Busy)
+    }
+
+    /// Sets how long each step of a call may take: every command to the RPU and every wait for
+    /// the AP. The wait for a scan to finish is never cut below the time the firmware may need to
+    /// visit all the channels, nor the boot below the time it takes to load the firmware. A step
+    /// which runs out of time fails the call with [`Error::Timeout`]. Defaults to
+    /// [`DEFAULT_TIMEOUT`].
+    ///
+    /// Calls can also be dropped at any point, such as when they lose a `select`. Commands not yet
+    /// sent are then never sent, and responses to the ones already sent are discarded.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Hands an action to the runner and waits for its result, for at most the timeout.
+    async fn issue(&self, action: Action) -> Result<Option<usize>, Error> {
+        with_timeout(self.timeout, self.action_state.issue(action))
+            .await
+            .map_err(|_| Error::Timeout)?
+    }
+
+    /// Queries what the radio and firmware support: the bands with their channels and transmit
+    /// power limits, the HT and VHT capabilities, the cipher suites and the interface modes.
+    pub async fn capabilities(&mut self) -> Result<Capabilities, Error> {
+        let mut command = nrf_wifi_cmd_get_wiphy {
+            umac_hdr: nrf_wifi_umac_hdr::default(),
+        };
+        command.prepare();
+
+        let mut response = [0u8; size_of::<nrf_wifi_event_get_wiphy>()];
 
         match self
-            .action_state
             .issue(Action::Command((
                 command.domain(),
                 true,
@@ -345,18 +1159,16 @@ impl<'a> Control<'a> {
             )))
             .await
         {
-            Ok(response_length) => {
-                if let Some(length) = response_length {
-                    info!("Response length: {}", length);
-                }
+            Ok(Some(length)) if length == response.len() => {
+                let event: nrf_wifi_event_get_wiphy = unsafe { ptr::read_unaligned(response.as_ptr() as *const _) };
+                Ok(Capabilities::from_event(&event))
             }
+            Ok(_) => Err(Error::NoData),
             Err(error) => {
-                error!("Failed to get stats: {:?}", error);
-                return Err(error);
+                error!("Failed to get wiphy: {:?}", error);
+                Err(error)
             }
         }
-
-        Ok(())
     }
 
     pub async fn get_stats(&mut self) -> Result<(), Error> {
@@ -365,7 +1177,6 @@ impl<'a> Control<'a> {
         let mut response = [0u8; 1024];
 
         match self
-            .action_state
             .issue(Action::Command((
                 command.domain(),
                 true,
@@ -389,3 +1200,86 @@ impl<'a> Control<'a> {
         }
     }
 }
+
+/// The authentication command for the BSS, without any SAE data.
+fn authentication_command(ssid: &[u8], bss: &BssInfo, auth_type: nrf_wifi_auth_type) -> nrf_wifi_umac_cmd_auth {
+    let mut command = nrf_wifi_umac_cmd_auth::default();
+    command.valid_fields = NRF_WIFI_CMD_AUTHENTICATE_BSSID_VALID
+        | NRF_WIFI_CMD_AUTHENTICATE_FREQ_VALID
+        | NRF_WIFI_CMD_AUTHENTICATE_SSID_VALID;
+    command.info.frequency = bss.frequency;
+    command.info.auth_type = auth_type as i32;
+    command.info.ssid = nrf_wifi_ssid::new(ssid);
+    command.info.nrf_wifi_bssid = bss.bssid;
+    command.info.nrf_wifi_signal = bss.signal;
+    command.info.bss_ie = nrf_wifi_ie::new(&bss.ies);
+    command.info.capability = bss.capability;
+    command.info.beacon_interval = bss.beacon_interval;
+    command.info.tsf = bss.tsf;
+    command
+}
+
+/// Waits for the scan to finish, giving whether it is done rather than aborted. Waits for at least
+/// [`SCAN_TIMEOUT`], however short `timeout` is.
+async fn wait_for_scan(subscriber: &mut ControlEventSubscriber<'_>, timeout: Duration) -> Result<bool, Error> {
+    wait_for_event(subscriber, SCAN_TIMEOUT.max(timeout), |event| match event {
+        ControlEvent::ScanDone => Some(Ok(true)),
+        ControlEvent::ScanAborted => Some(Ok(false)),
+        _ => None,
+    })
+    .await
+}
+
+/// Waits for the next result requested with [`Control::request_scan_results`] and whether it is the
+/// last one. Gives `None` if the command finishes without a result, which is how the UMAC tells
+/// that there are none.
+async fn next_scan_result(
+    subscriber: &mut ControlEventSubscriber<'_>,
+    timeout: Duration,
+) -> Result<Option<(BssInfo, bool)>, Error> {
+    wait_for_event(subscriber, timeout, |event| match event {
+        ControlEvent::ScanResult { bss, last } => Some(Ok(Some((bss, last)))),
+        ControlEvent::ScanResultsDone => Some(Ok(None)),
+        _ => None,
+    })
+    .await
+}
+
+/// Waits for the next authentication frame from the AP.
+async fn wait_for_authentication(
+    subscriber: &mut ControlEventSubscriber<'_>,
+    timeout: Duration,
+) -> Result<AuthenticationFrame, Error> {
+    wait_for_event(subscriber, timeout, |event| match event {
+        ControlEvent::Authenticate(frame) => Some(frame.ok_or(Error::Timeout)),
+        _ => None,
+    })
+    .await
+}
+
+/// Waits until `filter` picks an event and returns its result, or fails with [`Error::Timeout`].
+/// Fails with [`Error::BufferOverflow`] if events were lost, as the one waited for may be among
+/// them.
+async fn wait_for_event<T>(
+    subscriber: &mut ControlEventSubscriber<'_>,
+    timeout: Duration,
+    mut filter: impl FnMut(ControlEvent) -> Option<Result<T, Error>>,
+) -> Result<T, Error> {
+    with_timeout(timeout, async {
+        loop {
+            match subscriber.next_message().await {
+                WaitResult::Message(event) => {
+                    if let Some(result) = filter(event) {
+                        return result;
+                    }
+                }
+                WaitResult::Lagged(count) => {
+                    warn!("Lost {} events", count);
+                    return Err(Error::BufferOverflow);
+                }
+            }
+        }
+    })
+    .await
+    .map_err(|_| Error::Timeout)?
+}
@@ -0,0 +1,651 @@
+//! The events of the RPU, decoded from the messages in its event queue.
+
+use core::mem::size_of;
+
+use embassy_sync::{
+    blocking_mutex::raw::NoopRawMutex,
+    pubsub::{PubSubChannel, Publisher, Subscriber},
+};
+use embassy_time::Duration;
+use heapless::Vec;
+
+use crate::{
+    bindings::{
+        host_rpu_msg, nrf_wifi_host_rpu_msg_type, nrf_wifi_rx_buff, nrf_wifi_rx_buff_info, nrf_wifi_rx_pkt_type,
+        nrf_wifi_sys_events, nrf_wifi_sys_head, nrf_wifi_tx_buff_done, nrf_wifi_umac_data_commands,
+        nrf_wifi_umac_event_cmd_status, nrf_wifi_umac_event_mlme, nrf_wifi_umac_event_new_scan_results,
+        nrf_wifi_umac_event_vif_state, nrf_wifi_umac_events, nrf_wifi_umac_hdr, nrf_wifi_umac_head,
+        NRF_WIFI_EVENT_MLME_ACK, NRF_WIFI_EVENT_MLME_FRAME_VALID, NRF_WIFI_EVENT_MLME_TIMED_OUT,
+        NRF_WIFI_EVENT_NEW_SCAN_RESULTS_IES_VALID, NRF_WIFI_MAX_FRAME_LEN, NRF_WIFI_MAX_IE_LEN, PKT_TYPE_MPDU,
+        PKT_TYPE_MSDU, PKT_TYPE_MSDU_WITH_MAC,
+    },
+    control::Security,
+    ie::{id, Elements, Rsn, RsnExtension, Ssid, Suite, Wpa},
+    rpu::RX_BUFS,
+    supplicant::SupplicantError,
+    util::{meh, unsliceit_padded},
+    Error,
+};
+
+/// Events held for the control before the runner waits for it to take them.
+const CONTROL_EVENT_QUEUE_SIZE: usize = 4;
+
+/// How long the runner waits for the control to make room for an event, before it drops the
+/// oldest one instead.
+pub(crate) const CONTROL_EVENT_PUBLISH_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Events published by the runner which the control waits on while it drives a procedure such
+/// as joining a network. The runner waits a little for room in the queue, so a procedure which
+/// keeps up sees every event, while one which stops taking them fails with
+/// [`Error::BufferOverflow`](crate::Error::BufferOverflow) rather than holding up the runner.
+/// Nothing is queued when no one is subscribed.
+pub(crate) type ControlEventQueue = PubSubChannel<NoopRawMutex, ControlEvent, CONTROL_EVENT_QUEUE_SIZE, 1, 1>;
+pub(crate) type ControlEventPublisher<'a> = Publisher<'a, NoopRawMutex, ControlEvent, CONTROL_EVENT_QUEUE_SIZE, 1, 1>;
+pub(crate) type ControlEventSubscriber<'a> = Subscriber<'a, NoopRawMutex, ControlEvent, CONTROL_EVENT_QUEUE_SIZE, 1, 1>;
+
+/// Most subscribers to the events of the driver at once, see
+/// [`Control::subscribe`](crate::Control::subscribe).
+pub const MAX_SUBSCRIBERS: usize = 4;
+
+/// Events held for each subscriber. One which falls further behind misses the oldest.
+pub const SUBSCRIBER_QUEUE_SIZE: usize = 4;
+
+/// Events published by the runner to applications.
+pub(crate) type EventChannel = PubSubChannel<NoopRawMutex, Event, SUBSCRIBER_QUEUE_SIZE, MAX_SUBSCRIBERS, 1>;
+
+/// Receives the events of the driver, see [`Control::subscribe`](crate::Control::subscribe).
+pub type EventSubscriber<'a> = Subscriber<'a, NoopRawMutex, Event, SUBSCRIBER_QUEUE_SIZE, MAX_SUBSCRIBERS, 1>;
+
+/// Size of the header of a 802.11 management frame.
+const MANAGEMENT_FRAME_HEADER_SIZE: usize = 24;
+
+/// Offset of the status code in the body of an authentication frame (after the algorithm number
+/// and the transaction sequence number).
+const AUTHENTICATION_STATUS_OFFSET: usize = 4;
+
+/// Offset of the status code in the body of an association response frame (after the capability
+/// information).
+const ASSOCIATION_STATUS_OFFSET: usize = 2;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum ControlEvent {
+    ScanDone,
+    ScanAborted,
+    /// A BSS reported by the get scan results command. `last` is set for the final result.
+    ScanResult {
+        bss: BssInfo,
+        last: bool,
+    },
+    /// The get scan results command is done, which follows its results and is the only sign of
+    /// there being none.
+    ScanResultsDone,
+    /// The authentication frame from the AP, `None` if the AP did not respond.
+    Authenticate(Option<AuthenticationFrame>),
+    /// The status code of the association response, `None` if the AP did not respond.
+    Associate {
+        status: Option<u16>,
+    },
+    Deauthenticate,
+    Disassociate,
+    Disconnect,
+    CarrierOn,
+    CarrierOff,
+    /// The 4-way handshake completed and the keys are installed.
+    PortAuthorized,
+    /// The handshake with the AP can not complete.
+    HandshakeFailed(SupplicantError),
+}
+
+/// An event sent by the RPU, see [`Event::decode`], or one the runner derives from them.
+///
+/// Only the events the driver makes use of carry a typed payload, the rest are given as
+/// [`Event::Other`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Event {
+    /// The firmware is initialized and ready for commands.
+    InitDone,
+    /// The firmware is deinitialized.
+    DeinitDone,
+    /// The statistics requested with [`Control::get_stats`](crate::Control::get_stats).
+    Stats,
+    /// Readings of the power monitors of the RPU, which are not converted to units.
+    PowerData {
+        lo_frequency_error: i32,
+        battery_voltage: i32,
+        temperature: i32,
+    },
+    /// A UMAC command finished, with a status of zero on success. The sequence number is the one
+    /// of the command, when the firmware echoes it.
+    CommandStatus {
+        command: u32,
+        sequence: u32,
+        status: i32,
+    },
+    /// The interface was brought up or down, with a status of zero on success.
+    InterfaceStatus {
+        status: i32,
+    },
+    ScanStarted,
+    ScanAborted,
+    ScanDone,
+    /// A BSS reported by the get scan results command. `last` is set for the final result.
+    ScanResult {
+        bss: BssInfo,
+        last: bool,
+    },
+    /// The authentication frame from the AP, `None` if the AP did not respond.
+    Authenticate(Option<AuthenticationFrame>),
+    /// The status code of the association response, `None` if the AP did not respond.
+    Associate {
+        status: Option<u16>,
+    },
+    /// The association ended, with the reason code of the frame if there was one.
+    /// `locally_generated` is set when the frame was not sent by the AP.
+    Deauthenticate {
+        reason: Option<u16>,
+        locally_generated: bool,
+    },
+    /// The association ended, see [`Event::Deauthenticate`].
+    Disassociate {
+        reason: Option<u16>,
+        locally_generated: bool,
+    },
+    /// A deauthentication which failed the management frame protection. Anyone can send these, so
+    /// they do not end the association.
+    UnprotectedDeauthenticate {
+        reason: Option<u16>,
+    },
+    /// A disassociation which failed the management frame protection.
+    UnprotectedDisassociate {
+        reason: Option<u16>,
+    },
+    Disconnect,
+    /// A management frame of a kind registered with the UMAC.
+    Frame(ManagementFrame),
+    /// Whether a management frame sent by the host was acknowledged.
+    FrameTxStatus {
+        cookie: u64,
+        acknowledged: bool,
+    },
+    /// The response to the get wiphy command, which is decoded by
+    /// [`Control::capabilities`](crate::Control::capabilities).
+    Wiphy,
+    CarrierOn,
+    CarrierOff,
+    /// The RPU is done with the frames of a transmit descriptor.
+    TransmitDone {
+        descriptor: u8,
+        packets: u8,
+    },
+    /// Packets were placed in receive buffers.
+    Receive(Receive),
+    /// The link is up: associated with an open network, or with the keys installed for a
+    /// protected one. Published by the runner.
+    Connected,
+    /// The link went down after [`Event::Connected`], with the reason code of the
+    /// deauthentication or disassociation if there was one. Published by the runner.
+    Disconnected {
+        reason: Option<u16>,
+    },
+    /// The UMAC ended the association on its own, which it does once the beacons of the AP are
+    /// lost. Published by the runner before [`Event::Disconnected`].
+    BeaconLoss,
+    /// An event without a typed payload. `message_type` is one of `nrf_wifi_host_rpu_msg_type` and
+    /// `id` the event or command in the header of the message.
+    Other {
+        message_type: u32,
+        id: u32,
+    },
+}
+
+impl Event {
+    /// Decodes a message of the event queue, which starts with its `host_rpu_msg` header.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::BufferTooSmall`] if the message is shorter than its headers.
+    pub fn decode(message: &[u8]) -> Result<Self, Error> {
+        const HEADER_SIZE: usize = size_of::<host_rpu_msg>();
+
+        if message.len() < HEADER_SIZE {
+            return Err(Error::BufferTooSmall);
+        }
+
+        Self::from_message(&unsliceit_padded(message), &message[HEADER_SIZE..])
+    }
+
+    /// Decodes the message following `header`, as read by `Rpu::read_event`.
+    /// Anything past the length in the header is ignored.
+    pub(crate) fn from_message(header: &host_rpu_msg, message: &[u8]) -> Result<Self, Error> {
+        let length = (header.hdr.len as usize).saturating_sub(size_of::<host_rpu_msg>());
+        let message = &message[..length.min(message.len())];
+        let message_type = header.type_ as u32;
+
+        match nrf_wifi_host_rpu_msg_type::try_from(message_type) {
+            Ok(nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_SYSTEM) => Self::from_system_message(message),
+            Ok(nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_UMAC) => Self::from_umac_message(message),
+            Ok(nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_DATA) => Self::from_data_message(message),
+            _ => Ok(Self::Other {
+                message_type,
+                id: unsliceit_padded(message),
+            }),
+        }
+    }
+
+    fn from_system_message(message: &[u8]) -> Result<Self, Error> {
+        let header: nrf_wifi_sys_head = read_header(message)?;
+
+        Ok(match nrf_wifi_sys_events::try_from(header.cmd_event) {
+            Ok(nrf_wifi_sys_events::NRF_WIFI_EVENT_INIT_DONE) => Self::InitDone,
+            Ok(nrf_wifi_sys_events::NRF_WIFI_EVENT_DEINIT_DONE) => Self::DeinitDone,
+            Ok(nrf_wifi_sys_events::NRF_WIFI_EVENT_STATS) => Self::Stats,
+            Ok(nrf_wifi_sys_events::NRF_WIFI_EVENT_PWR_DATA) => {
+                let event: PowerDataEvent = unsliceit_padded(message);
+                Self::PowerData {
+                    lo_frequency_error: event.lfc_err,
+                    battery_voltage: event.vbat_mon,
+                    temperature: event.temp,
+                }
+            }
+            _ => Self::Other {
+                message_type: nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_SYSTEM as u32,
+                id: header.cmd_event,
+            },
+        })
+    }
+
+    fn from_umac_message(message: &[u8]) -> Result<Self, Error> {
+        let header: nrf_wifi_umac_hdr = read_header(message)?;
+        let mlme = || unsliceit_padded::<nrf_wifi_umac_event_mlme>(message);
+
+        Ok(match nrf_wifi_umac_events::try_from(header.cmd_evnt) {
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_CMD_STATUS) => {
+                let event: nrf_wifi_umac_event_cmd_status = unsliceit_padded(message);
+                Self::CommandStatus {
+                    command: event.cmd_id,
+                    sequence: header.seq,
+                    status: event.cmd_status as i32,
+                }
+            }
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_IFFLAGS_STATUS) => {
+                let event: nrf_wifi_umac_event_vif_state = unsliceit_padded(message);
+                Self::InterfaceStatus { status: event.status }
+            }
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_TRIGGER_SCAN_START) => Self::ScanStarted,
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_SCAN_ABORTED) => Self::ScanAborted,
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_SCAN_DONE) => Self::ScanDone,
+            // The UMAC sets the sequence number of the header to zero for the last result
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_SCAN_RESULT) => Self::ScanResult {
+                bss: BssInfo::from_event(message),
+                last: header.seq == 0,
+            },
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_AUTHENTICATE) => {
+                Self::Authenticate(AuthenticationFrame::from_event(&mlme()))
+            }
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_ASSOCIATE) => Self::Associate {
+                status: mlme_status(&mlme(), ASSOCIATION_STATUS_OFFSET),
+            },
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_DEAUTHENTICATE) => {
+                let event = mlme();
+                Self::Deauthenticate {
+                    reason: mlme_status(&event, 0),
+                    locally_generated: is_locally_generated(&event),
+                }
+            }
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_DISASSOCIATE) => {
+                let event = mlme();
+                Self::Disassociate {
+                    reason: mlme_status(&event, 0),
+                    locally_generated: is_locally_generated(&event),
+                }
+            }
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_UNPROT_DEAUTHENTICATE) => Self::UnprotectedDeauthenticate {
+                reason: mlme_status(&mlme(), 0),
+            },
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_UNPROT_DISASSOCIATE) => Self::UnprotectedDisassociate {
+                reason: mlme_status(&mlme(), 0),
+            },
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_DISCONNECT) => Self::Disconnect,
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_FRAME) => Self::Frame(ManagementFrame::from_event(&mlme())),
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_FRAME_TX_STATUS) => {
+                let event = mlme();
+                Self::FrameTxStatus {
+                    cookie: event.cookie,
+                    acknowledged: event.nrf_wifi_flags & NRF_WIFI_EVENT_MLME_ACK != 0,
+                }
+            }
+            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_NEW_WIPHY) => Self::Wiphy,
+            _ => Self::Other {
+                message_type: nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_UMAC as u32,
+                id: header.cmd_evnt,
+            },
+        })
+    }
+
+    fn from_data_message(message: &[u8]) -> Result<Self, Error> {
+        let header: nrf_wifi_umac_head = read_header(message)?;
+
+        Ok(match nrf_wifi_umac_data_commands::try_from(header.cmd) {
+            Ok(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_CARRIER_ON) => Self::CarrierOn,
+            Ok(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_CARRIER_OFF) => Self::CarrierOff,
+            Ok(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_TX_BUFF_DONE) => {
+                let event: nrf_wifi_tx_buff_done = unsliceit_padded(message);
+                Self::TransmitDone {
+                    descriptor: event.tx_desc_num,
+                    packets: event.num_tx_status_code,
+                }
+            }
+            Ok(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_RX_BUFF) => Self::Receive(Receive::from_event(message)),
+            _ => Self::Other {
+                message_type: nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_DATA as u32,
+                id: header.cmd,
+            },
+        })
+    }
+}
+
+/// Reads the header of a message, which has to be complete.
+fn read_header<T>(message: &[u8]) -> Result<T, Error> {
+    if message.len() < size_of::<T>() {
+        return Err(Error::BufferTooSmall);
+    }
+
+    Ok(unsliceit_padded(message))
+}
+
+/// The management frame of an MLME event, `None` if the AP did not respond.
+fn mlme_frame(event: &nrf_wifi_umac_event_mlme) -> Option<&[u8]> {
+    if event.nrf_wifi_flags & NRF_WIFI_EVENT_MLME_TIMED_OUT != 0
+        || event.valid_fields & NRF_WIFI_EVENT_MLME_FRAME_VALID == 0
+    {
+        return None;
+    }
+
+    let frame_length = usize::try_from(meh(event.frame.frame_len)).ok()?;
+    let frame: &[u8] = unsafe { core::mem::transmute(&event.frame.frame[..]) };
+
+    frame.get(..frame_length)
+}
+
+/// The body of the management frame of an MLME event, `None` if the AP did not respond.
+fn mlme_frame_body(event: &nrf_wifi_umac_event_mlme) -> Option<&[u8]> {
+    mlme_frame(event)?.get(MANAGEMENT_FRAME_HEADER_SIZE..)
+}
+
+/// The status code of an authenticate or associate MLME event, located at `status_offset` in the
+/// body of the management frame. Also gives the reason code of deauthentications and
+/// disassociations, which is at the start of their body.
+fn mlme_status(event: &nrf_wifi_umac_event_mlme, status_offset: usize) -> Option<u16> {
+    let body = mlme_frame_body(event)?;
+
+    if body.len() < status_offset + 2 {
+        return None;
+    }
+
+    Some(u16::from_le_bytes([body[status_offset], body[status_offset + 1]]))
+}
+
+/// Whether the management frame of an MLME event was not sent by the AP, i.e. its source address
+/// differs from the BSSID. Also the case without a frame.
+fn is_locally_generated(event: &nrf_wifi_umac_event_mlme) -> bool {
+    mlme_frame(event)
+        .and_then(|frame| Some((frame.get(10..16)?, frame.get(16..22)?)))
+        .is_none_or(|(source, bssid)| source != bssid)
+}
+
+/// `nrf_wifi_umac_event_pwr_data`, which is left out of the bindings along with the rest of the
+/// radio test interface.
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct PowerDataEvent {
+    sys_head: nrf_wifi_sys_head,
+    mon_id: i32,
+    lfc_err: i32,
+    vbat_mon: i32,
+    temp: i32,
+}
+
+/// A management frame received from the AP.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ManagementFrame {
+    pub frequency: u32,
+    /// Signal strength in dBm.
+    pub signal: i32,
+    /// The frame including its header, empty if the event did not carry one.
+    pub frame: Vec<u8, { NRF_WIFI_MAX_FRAME_LEN as usize }>,
+}
+
+impl ManagementFrame {
+    fn from_event(event: &nrf_wifi_umac_event_mlme) -> Self {
+        ManagementFrame {
+            frequency: event.frequency,
+            signal: event.rx_signal_dbm as i32,
+            frame: Vec::from_slice(mlme_frame(event).unwrap_or_default()).unwrap_or_default(),
+        }
+    }
+}
+
+/// An authentication frame received from the AP.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AuthenticationFrame {
+    pub transaction: u16,
+    pub status: u16,
+    /// The body after the status code, e.g. the SAE commit or confirm.
+    pub body: Vec<u8, MAX_AUTHENTICATION_BODY_SIZE>,
+}
+
+/// Longest body of an authentication frame that fits in an MLME event.
+const MAX_AUTHENTICATION_BODY_SIZE: usize =
+    NRF_WIFI_MAX_FRAME_LEN as usize - MANAGEMENT_FRAME_HEADER_SIZE - AUTHENTICATION_STATUS_OFFSET - 2;
+
+impl AuthenticationFrame {
+    /// Decodes an authenticate MLME event, `None` if the AP did not respond.
+    fn from_event(event: &nrf_wifi_umac_event_mlme) -> Option<Self> {
+        let body = mlme_frame_body(event)?;
+
+        let [_, _, transaction_low, transaction_high, status_low, status_high, rest @ ..] = body else {
+            return None;
+        };
+
+        Some(AuthenticationFrame {
+            transaction: u16::from_le_bytes([*transaction_low, *transaction_high]),
+            status: u16::from_le_bytes([*status_low, *status_high]),
+            body: Vec::from_slice(rest).ok()?,
+        })
+    }
+}
+
+/// The parameters of a BSS found in a scan which are needed to authenticate and associate with it.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BssInfo {
+    pub bssid: [u8; 6],
+    pub frequency: u32,
+    pub capability: u16,
+    pub beacon_interval: u16,
+    pub tsf: u64,
+    /// Signal strength in mBm.
+    pub signal: i32,
+    /// The information elements of the probe response, or the beacon if there were none.
+    pub ies: Vec<u8, { NRF_WIFI_MAX_IE_LEN as usize }>,
+}
+
+impl BssInfo {
+    /// Decodes a `NRF_WIFI_UMAC_EVENT_SCAN_RESULT` event. Information elements which do not fit
+    /// are truncated.
+    fn from_event(buffer: &[u8]) -> Self {
+        let result: nrf_wifi_umac_event_new_scan_results = unsliceit_padded(buffer);
+        let data = buffer
+            .get(size_of::<nrf_wifi_umac_event_new_scan_results>()..)
+            .unwrap_or_default();
+
+        let ies_length = meh(result.ies_len) as usize;
+        let beacon_ies_length = meh(result.beacon_ies_len) as usize;
+
+        let ies = if meh(result.valid_fields) & NRF_WIFI_EVENT_NEW_SCAN_RESULTS_IES_VALID != 0 && ies_length > 0 {
+            &data[..ies_length.min(data.len())]
+        } else {
+            let start = ies_length.min(data.len());
+            &data[start..(start + beacon_ies_length).min(data.len())]
+        };
+
+        let ies = &ies[..ies.len().min(NRF_WIFI_MAX_IE_LEN as usize)];
+
+        BssInfo {
+            bssid: result.mac_addr,
+            frequency: result.frequency,
+            capability: result.capability,
+            beacon_interval: result.beacon_interval,
+            tsf: result.ies_tsf,
+            signal: unsafe { result.signal.signal.mbm_signal } as i32,
+            ies: Vec::from_slice(ies).unwrap_or_default(),
+        }
+    }
+
+    /// The information elements of the BSS.
+    #[must_use]
+    pub fn elements(&self) -> Elements<'_> {
+        Elements::new(&self.ies)
+    }
+
+    /// The SSID advertised in the information elements of the BSS.
+    #[must_use]
+    pub fn ssid(&self) -> Option<&[u8]> {
+        self.elements().get::<Ssid>().map(|ssid| ssid.0)
+    }
+
+    /// The RSN element advertised by the BSS, including the element header.
+    pub(crate) fn rsn_element(&self) -> Option<&[u8]> {
+        self.elements().find_id(id::RSN).map(|element| element.as_bytes())
+    }
+
+    /// The security of the BSS, from its RSN or WPA element and the privacy bit of its capabilities.
+    #[must_use]
+    pub fn security(&self) -> Security {
+        const CAPABILITY_PRIVACY: u16 = 1 << 4;
+
+        if self.rsn_element().is_some() {
+            let Some(rsn) = self.elements().get::<Rsn>() else {
+                return Security::Unknown;
+            };
+
+            let has = |akm: Suite| rsn.akm_suites().any(|suite| suite == akm);
+
+            // PSK and SAE may also be offered with fast transition or SHA-256, which joining does not use
+            return match (has(Suite::AKM_PSK) || has(Suite::AKM_PSK_SHA256), has(Suite::AKM_SAE)) {
+                (true, true) => Security::Wpa2Wpa3Personal,
+                (true, false) => Security::Wpa2Personal,
+                (false, true) => Security::Wpa3Personal,
+                (false, false) if has(Suite::AKM_8021X) || has(Suite::AKM_8021X_SHA256) => Security::Enterprise,
+                (false, false) => Security::Unknown,
+            };
+        }
+
+        if self.elements().get::<Wpa>().is_some() {
+            Security::Wpa
+        } else if self.capability & CAPABILITY_PRIVACY != 0 {
+            Security::Wep
+        } else {
+            Security::Open
+        }
+    }
+
+    /// Whether the BSS supports deriving the SAE password element with hash-to-element, as
+    /// advertised in its RSN extension element.
+    pub(crate) fn supports_sae_hash_to_element(&self) -> bool {
+        self.elements()
+            .get::<RsnExtension>()
+            .is_some_and(|extension| extension.sae_hash_to_element())
+    }
+}
+
+/// Packets placed in receive buffers by the RPU, which are read out by their descriptor.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Receive {
+    pub kind: ReceiveKind,
+    /// Length of the 802.11 header of the packets.
+    pub mac_header_length: u8,
+    pub frequency: u16,
+    pub signal: i16,
+    pub packets: Vec<ReceivedPacket, RX_BUFS>,
+}
+
+/// What the packets of a [`Receive`] event are.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReceiveKind {
+    Data,
+    BeaconOrProbeResponse,
+    Raw,
+    Unknown(i16),
+}
+
+/// A packet in a receive buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReceivedPacket {
+    pub descriptor: u16,
+    pub length: u16,
+    pub format: PacketFormat,
+}
+
+/// How a received packet is framed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PacketFormat {
+    /// The 802.11 header followed by the LLC header and the data.
+    Mpdu,
+    MsduWithMac,
+    Msdu,
+    Unknown(u8),
+}
+
+impl Receive {
+    /// Decodes a `NRF_WIFI_CMD_RX_BUFF` event. Packets beyond the end of the message are left out.
+    fn from_event(buffer: &[u8]) -> Self {
+        const INFO_SIZE: usize = size_of::<nrf_wifi_rx_buff_info>();
+
+        let event: nrf_wifi_rx_buff = unsliceit_padded(buffer);
+        let infos = buffer.get(size_of::<nrf_wifi_rx_buff>()..).unwrap_or_default();
+
+        let kind = match nrf_wifi_rx_pkt_type::try_from(meh(event.rx_pkt_type) as u32) {
+            Ok(nrf_wifi_rx_pkt_type::NRF_WIFI_RX_PKT_DATA) => ReceiveKind::Data,
+            Ok(nrf_wifi_rx_pkt_type::NRF_WIFI_RX_PKT_BCN_PRB_RSP) => ReceiveKind::BeaconOrProbeResponse,
+            Ok(nrf_wifi_rx_pkt_type::NRF_WIFI_RAW_RX_PKT) => ReceiveKind::Raw,
+            Err(_) => ReceiveKind::Unknown(meh(event.rx_pkt_type)),
+        };
+
+        let packets = infos
+            .chunks_exact(INFO_SIZE)
+            .take(event.rx_pkt_cnt as usize)
+            .map(|info| {
+                let info: nrf_wifi_rx_buff_info = unsliceit_padded(info);
+
+                ReceivedPacket {
+                    descriptor: meh(info.descriptor_id),
+                    length: meh(info.rx_pkt_len),
+                    format: match u32::from(info.pkt_type) {
+                        PKT_TYPE_MPDU => PacketFormat::Mpdu,
+                        PKT_TYPE_MSDU_WITH_MAC => PacketFormat::MsduWithMac,
+                        PKT_TYPE_MSDU => PacketFormat::Msdu,
+                        _ => PacketFormat::Unknown(info.pkt_type),
+                    },
+                }
+            })
+            .take(RX_BUFS)
+            .collect();
+
+        Receive {
+            kind,
+            mac_header_length: event.mac_header_len,
+            frequency: meh(event.frequency),
+            signal: meh(event.signal),
+            packets,
+        }
+    }
+}
@@ -0,0 +1,653 @@
+//! Information elements of beacons, probe responses and EAPOL-Key data (IEEE 802.11-2020 9.4.2).
+//!
+//! [`Elements`] iterates over a buffer of elements without copying anything, and the typed
+//! decoders implementing [`Decode`] borrow from the same buffer:
+//!
+//! ```
+//! use nrf70::ie::{DsParameterSet, Elements, Ssid};
+//!
+//! let elements = Elements::new(&[0, 4, b'n', b'r', b'f', b'7', 3, 1, 6]);
+//!
+//! assert_eq!(elements.get::<Ssid>(), Some(Ssid(b"nrf7")));
+//! assert_eq!(elements.get::<DsParameterSet>().map(|ds| ds.channel), Some(6));
+//! ```
+
+/// Element ids.
+pub mod id {
+    pub const SSID: u8 = 0;
+    pub const SUPPORTED_RATES: u8 = 1;
+    pub const DS_PARAMETER_SET: u8 = 3;
+    pub const COUNTRY: u8 = 7;
+    pub const BSS_LOAD: u8 = 11;
+    pub const HT_CAPABILITIES: u8 = 45;
+    pub const RSN: u8 = 48;
+    pub const EXTENDED_SUPPORTED_RATES: u8 = 50;
+    pub const MOBILITY_DOMAIN: u8 = 54;
+    pub const RM_ENABLED_CAPABILITIES: u8 = 70;
+    pub const EXTENDED_CAPABILITIES: u8 = 127;
+    pub const VHT_CAPABILITIES: u8 = 191;
+    pub const VENDOR_SPECIFIC: u8 = 221;
+    pub const RSN_EXTENSION: u8 = 244;
+    /// The element id of elements identified by their element id extension
+    pub const EXTENSION: u8 = 255;
+}
+
+/// Element id extensions, the first byte of elements with the id [`id::EXTENSION`].
+pub mod extension_id {
+    pub const HE_CAPABILITIES: u8 = 35;
+}
+
+/// Iterates over the elements in a buffer. A truncated element ends the iteration.
+#[derive(Clone, Copy, Debug)]
+pub struct Elements<'a>(&'a [u8]);
+
+impl<'a> Elements<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Elements(data)
+    }
+
+    /// Decodes the first element of type `T`, skipping elements of the same id which do not
+    /// decode, e.g. other vendor specific elements.
+    pub fn get<T: Decode<'a>>(self) -> Option<T> {
+        self.into_iter().find_map(|element| element.decode())
+    }
+
+    /// The first element with the given id.
+    pub fn find_id(self, id: u8) -> Option<Element<'a>> {
+        self.into_iter().find(|element| element.id == id)
+    }
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Element<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let length = 2 + usize::from(*self.0.get(1)?);
+        let bytes = self.0.get(..length)?;
+        self.0 = &self.0[length..];
+
+        Some(Element {
+            id: bytes[0],
+            data: &bytes[2..],
+            bytes,
+        })
+    }
+}
+
+/// A single element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Element<'a> {
+    pub id: u8,
+    /// The body after the id and length
+    pub data: &'a [u8],
+    bytes: &'a [u8],
+}
+
+impl<'a> Element<'a> {
+    /// The element id extension, for elements with the id [`id::EXTENSION`].
+    pub fn extension_id(&self) -> Option<u8> {
+        match self.id {
+            id::EXTENSION => self.data.first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Decodes the element as `T`, `None` if it is another element or malformed.
+    pub fn decode<T: Decode<'a>>(&self) -> Option<T> {
+        if self.id != T::ID {
+            return None;
+        }
+
+        match T::EXTENSION_ID {
+            Some(extension_id) if self.extension_id() == Some(extension_id) => T::decode(&self.data[1..]),
+            Some(_) => None,
+            None => T::decode(self.data),
+        }
+    }
+
+    /// The whole element, including the id and length.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+/// A typed element.
+pub trait Decode<'a>: Sized {
+    const ID: u8;
+
+    /// The element id extension, for elements with the id [`id::EXTENSION`]
+    const EXTENSION_ID: Option<u8> = None;
+
+    /// Decodes the body of the element, after the element id extension if there is one.
+    fn decode(data: &'a [u8]) -> Option<Self>;
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().unwrap()))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().unwrap()))
+}
+
+/// The SSID, empty for hidden networks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ssid<'a>(pub &'a [u8]);
+
+impl<'a> Decode<'a> for Ssid<'a> {
+    const ID: u8 = id::SSID;
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        (data.len() <= 32).then_some(Ssid(data))
+    }
+}
+
+/// A rate of the supported rates elements, or a BSS membership selector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rate(pub u8);
+
+impl Rate {
+    /// Selector of stations which have to support SAE hash-to-element to join.
+    pub const SAE_HASH_TO_ELEMENT_ONLY: Rate = Rate(0x80 | 123);
+
+    /// Whether every station in the BSS has to support the rate.
+    pub fn is_basic(self) -> bool {
+        self.0 & 0x80 != 0
+    }
+
+    /// The rate in kbit/s. Not meaningful for BSS membership selectors.
+    pub fn kbps(self) -> u32 {
+        u32::from(self.0 & 0x7f) * 500
+    }
+}
+
+/// The supported rates element, holding up to 8 rates. The rest are in the
+/// [`ExtendedSupportedRates`] element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SupportedRates<'a>(pub &'a [u8]);
+
+impl<'a> SupportedRates<'a> {
+    pub fn rates(&self) -> impl Iterator<Item = Rate> + 'a {
+        self.0.iter().map(|rate| Rate(*rate))
+    }
+}
+
+impl<'a> Decode<'a> for SupportedRates<'a> {
+    const ID: u8 = id::SUPPORTED_RATES;
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        (!data.is_empty()).then_some(SupportedRates(data))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtendedSupportedRates<'a>(pub &'a [u8]);
+
+impl<'a> ExtendedSupportedRates<'a> {
+    pub fn rates(&self) -> impl Iterator<Item = Rate> + 'a {
+        self.0.iter().map(|rate| Rate(*rate))
+    }
+}
+
+impl<'a> Decode<'a> for ExtendedSupportedRates<'a> {
+    const ID: u8 = id::EXTENDED_SUPPORTED_RATES;
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        (!data.is_empty()).then_some(ExtendedSupportedRates(data))
+    }
+}
+
+/// The DS parameter set, giving the channel of 2.4 GHz networks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DsParameterSet {
+    pub channel: u8,
+}
+
+impl Decode<'_> for DsParameterSet {
+    const ID: u8 = id::DS_PARAMETER_SET;
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        Some(DsParameterSet {
+            channel: *data.first()?,
+        })
+    }
+}
+
+/// The country element, with the regulatory domain and its transmit power limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Country<'a> {
+    /// ISO 3166-1 country code
+    pub country: [u8; 2],
+    /// `b' '` for any environment, `b'I'` for indoor and `b'O'` for outdoor
+    pub environment: u8,
+    triplets: &'a [u8],
+}
+
+/// A range of channels and their maximum transmit power.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Subband {
+    pub first_channel: u8,
+    pub channels: u8,
+    /// Maximum transmit power in dBm
+    pub max_transmit_power: i8,
+}
+
+impl Country<'_> {
+    /// The subbands of the country element, leaving out operating class triplets.
+    pub fn subbands(&self) -> impl Iterator<Item = Subband> + '_ {
+        const FIRST_OPERATING_EXTENSION_IDENTIFIER: u8 = 201;
+
+        self.triplets
+            .chunks_exact(3)
+            .filter(|triplet| triplet[0] < FIRST_OPERATING_EXTENSION_IDENTIFIER)
+            .map(|triplet| Subband {
+                first_channel: triplet[0],
+                channels: triplet[1],
+                max_transmit_power: triplet[2] as i8,
+            })
+    }
+}
+
+impl<'a> Decode<'a> for Country<'a> {
+    const ID: u8 = id::COUNTRY;
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        Some(Country {
+            country: data.get(..2)?.try_into().unwrap(),
+            environment: *data.get(2)?,
+            triplets: &data[3..],
+        })
+    }
+}
+
+/// The load of the BSS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BssLoad {
+    pub station_count: u16,
+    /// Share of time the AP sensed the medium busy, scaled to 255
+    pub channel_utilization: u8,
+    /// Remaining medium time for admission control, in units of 32 µs per second
+    pub available_admission_capacity: u16,
+}
+
+impl Decode<'_> for BssLoad {
+    const ID: u8 = id::BSS_LOAD;
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        Some(BssLoad {
+            station_count: u16_at(data, 0)?,
+            channel_utilization: *data.get(2)?,
+            available_admission_capacity: u16_at(data, 3)?,
+        })
+    }
+}
+
+/// The HT (802.11n) capabilities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HtCapabilities {
+    pub capability_information: u16,
+    pub ampdu_parameters: u8,
+    pub supported_mcs_set: [u8; 16],
+    pub extended_capabilities: u16,
+    pub transmit_beamforming_capabilities: u32,
+    pub asel_capabilities: u8,
+}
+
+impl HtCapabilities {
+    /// Whether 40 MHz channels are supported.
+    pub fn supports_40mhz(&self) -> bool {
+        self.capability_information & (1 << 1) != 0
+    }
+
+    /// Whether MCS 0 to 7 with `streams` spatial streams are supported for reception.
+    pub fn supports_streams(&self, streams: usize) -> bool {
+        (1..=4).contains(&streams) && self.supported_mcs_set[streams - 1] == 0xff
+    }
+}
+
+impl Decode<'_> for HtCapabilities {
+    const ID: u8 = id::HT_CAPABILITIES;
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        Some(HtCapabilities {
+            capability_information: u16_at(data, 0)?,
+            ampdu_parameters: *data.get(2)?,
+            supported_mcs_set: data.get(3..19)?.try_into().unwrap(),
+            extended_capabilities: u16_at(data, 19)?,
+            transmit_beamforming_capabilities: u32_at(data, 21)?,
+            asel_capabilities: *data.get(25)?,
+        })
+    }
+}
+
+/// The VHT (802.11ac) capabilities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VhtCapabilities {
+    pub capability_information: u32,
+    /// Two bits per number of spatial streams: MCS 0-7, 0-8, 0-9 or not supported (3)
+    pub rx_mcs_map: u16,
+    pub rx_highest_data_rate: u16,
+    pub tx_mcs_map: u16,
+    pub tx_highest_data_rate: u16,
+}
+
+impl Decode<'_> for VhtCapabilities {
+    const ID: u8 = id::VHT_CAPABILITIES;
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        Some(VhtCapabilities {
+            capability_information: u32_at(data, 0)?,
+            rx_mcs_map: u16_at(data, 4)?,
+            rx_highest_data_rate: u16_at(data, 6)? & 0x1fff,
+            tx_mcs_map: u16_at(data, 8)?,
+            tx_highest_data_rate: u16_at(data, 10)? & 0x1fff,
+        })
+    }
+}
+
+/// The HE (802.11ax) capabilities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HeCapabilities<'a> {
+    pub mac_capabilities: [u8; 6],
+    pub phy_capabilities: [u8; 11],
+    /// The supported HE-MCS and NSS sets, which depend on the supported channel widths, followed
+    /// by the optional PPE thresholds
+    pub mcs_nss: &'a [u8],
+}
+
+impl HeCapabilities<'_> {
+    /// Whether target wake time is supported as responder, i.e. by an AP.
+    pub fn supports_twt_responder(&self) -> bool {
+        self.mac_capabilities[0] & (1 << 2) != 0
+    }
+}
+
+impl<'a> Decode<'a> for HeCapabilities<'a> {
+    const ID: u8 = id::EXTENSION;
+    const EXTENSION_ID: Option<u8> = Some(extension_id::HE_CAPABILITIES);
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        // At least the 80 MHz MCS and NSS set
+        if data.len() < 6 + 11 + 4 {
+            return None;
+        }
+
+        Some(HeCapabilities {
+            mac_capabilities: data[..6].try_into().unwrap(),
+            phy_capabilities: data[6..17].try_into().unwrap(),
+            mcs_nss: &data[17..],
+        })
+    }
+}
+
+/// A cipher or AKM suite of the RSN and WPA elements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Suite(pub [u8; 4]);
+
+impl Suite {
+    const RSN_OUI: [u8; 3] = [0x00, 0x0f, 0xac];
+    const WPA_OUI: [u8; 3] = [0x00, 0x50, 0xf2];
+
+    pub const CCMP_128: Suite = Suite::rsn(4);
+    pub const BIP_CMAC_128: Suite = Suite::rsn(6);
+
+    pub const AKM_8021X: Suite = Suite::rsn(1);
+    pub const AKM_PSK: Suite = Suite::rsn(2);
+    pub const AKM_FT_8021X: Suite = Suite::rsn(3);
+    pub const AKM_FT_PSK: Suite = Suite::rsn(4);
+    pub const AKM_8021X_SHA256: Suite = Suite::rsn(5);
+    pub const AKM_PSK_SHA256: Suite = Suite::rsn(6);
+    pub const AKM_SAE: Suite = Suite::rsn(8);
+    pub const AKM_FT_SAE: Suite = Suite::rsn(9);
+
+    /// A suite of the 00-0F-AC OUI used by the RSN element.
+    pub const fn rsn(suite_type: u8) -> Self {
+        let [a, b, c] = Self::RSN_OUI;
+        Suite([a, b, c, suite_type])
+    }
+
+    /// The suite as a selector of the firmware commands, e.g. `0x000fac04` for CCMP.
+    pub fn selector(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+}
+
+fn suites(data: &[u8]) -> impl Iterator<Item = Suite> + '_ {
+    data.chunks_exact(4).map(|suite| Suite(suite.try_into().unwrap()))
+}
+
+/// Splits a suite count and list off the front of `data`.
+fn split_suites<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let count = usize::from(u16_at(data, 0)?);
+    let suites = data.get(2..2 + 4 * count)?;
+    *data = &data[2 + 4 * count..];
+    Some(suites)
+}
+
+/// The RSN element, announcing the ciphers and authentication of WPA2 and WPA3 networks.
+///
+/// The version, group cipher, pairwise ciphers and AKMs have to be present, the rest is optional.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rsn<'a> {
+    pub version: u16,
+    pub group_cipher: Suite,
+    pairwise_ciphers: &'a [u8],
+    akm_suites: &'a [u8],
+    /// Zero if absent
+    pub capabilities: u16,
+    pmkids: &'a [u8],
+    /// `None` if absent, which means BIP-CMAC-128 when management frame protection is used
+    pub group_management_cipher: Option<Suite>,
+}
+
+impl<'a> Rsn<'a> {
+    /// Management frame protection required and capable bits of the RSN capabilities.
+    pub const CAPABILITY_MFPR: u16 = 1 << 6;
+    pub const CAPABILITY_MFPC: u16 = 1 << 7;
+
+    pub fn pairwise_ciphers(&self) -> impl Iterator<Item = Suite> + 'a {
+        suites(self.pairwise_ciphers)
+    }
+
+    pub fn akm_suites(&self) -> impl Iterator<Item = Suite> + 'a {
+        suites(self.akm_suites)
+    }
+
+    pub fn pmkids(&self) -> impl Iterator<Item = &'a [u8; 16]> + 'a {
+        self.pmkids.chunks_exact(16).map(|pmkid| pmkid.try_into().unwrap())
+    }
+}
+
+impl<'a> Decode<'a> for Rsn<'a> {
+    const ID: u8 = id::RSN;
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        let version = u16_at(data, 0)?;
+        let group_cipher = Suite(data.get(2..6)?.try_into().unwrap());
+
+        let mut data = &data[6..];
+        let pairwise_ciphers = split_suites(&mut data)?;
+        let akm_suites = split_suites(&mut data)?;
+
+        let capabilities = u16_at(data, 0).unwrap_or(0);
+        data = data.get(2..).unwrap_or_default();
+
+        let (pmkids, group_management_cipher) = match u16_at(data, 0) {
+            Some(count) => {
+                let length = 16 * usize::from(count);
+                let pmkids = data.get(2..2 + length)?;
+                let cipher = data.get(2 + length..2 + length + 4);
+                (pmkids, cipher.map(|suite| Suite(suite.try_into().unwrap())))
+            }
+            None => (&[][..], None),
+        };
+
+        Some(Rsn {
+            version,
+            group_cipher,
+            pairwise_ciphers,
+            akm_suites,
+            capabilities,
+            pmkids,
+            group_management_cipher,
+        })
+    }
+}
+
+/// The vendor specific element of the original WPA, which predates the RSN element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Wpa<'a> {
+    pub version: u16,
+    pub group_cipher: Suite,
+    pairwise_ciphers: &'a [u8],
+    akm_suites: &'a [u8],
+}
+
+impl<'a> Wpa<'a> {
+    pub fn pairwise_ciphers(&self) -> impl Iterator<Item = Suite> + 'a {
+        suites(self.pairwise_ciphers)
+    }
+
+    pub fn akm_suites(&self) -> impl Iterator<Item = Suite> + 'a {
+        suites(self.akm_suites)
+    }
+}
+
+impl<'a> Decode<'a> for Wpa<'a> {
+    const ID: u8 = id::VENDOR_SPECIFIC;
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        const OUI_TYPE_WPA: u8 = 1;
+
+        if data.get(..3)? != Suite::WPA_OUI || *data.get(3)? != OUI_TYPE_WPA {
+            return None;
+        }
+
+        let data = &data[4..];
+        let version = u16_at(data, 0)?;
+        let group_cipher = Suite(data.get(2..6)?.try_into().unwrap());
+
+        let mut data = &data[6..];
+        let pairwise_ciphers = split_suites(&mut data)?;
+        let akm_suites = split_suites(&mut data)?;
+
+        Some(Wpa {
+            version,
+            group_cipher,
+            pairwise_ciphers,
+            akm_suites,
+        })
+    }
+}
+
+/// The RSN extension element, with capabilities added after the RSN element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RsnExtension<'a>(pub &'a [u8]);
+
+impl RsnExtension<'_> {
+    /// Whether the SAE password element can be derived with hash-to-element.
+    pub fn sae_hash_to_element(&self) -> bool {
+        self.0.first().is_some_and(|capabilities| capabilities & (1 << 5) != 0)
+    }
+}
+
+impl<'a> Decode<'a> for RsnExtension<'a> {
+    const ID: u8 = id::RSN_EXTENSION;
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        (!data.is_empty()).then_some(RsnExtension(data))
+    }
+}
+
+/// The mobility domain element of networks supporting fast BSS transition (802.11r).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MobilityDomain {
+    pub mobility_domain_identifier: u16,
+    pub ft_capability: u8,
+}
+
+impl MobilityDomain {
+    /// Whether fast BSS transitions can go through the current AP.
+    pub fn ft_over_ds(&self) -> bool {
+        self.ft_capability & 1 != 0
+    }
+}
+
+impl Decode<'_> for MobilityDomain {
+    const ID: u8 = id::MOBILITY_DOMAIN;
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        Some(MobilityDomain {
+            mobility_domain_identifier: u16_at(data, 0)?,
+            ft_capability: *data.get(2)?,
+        })
+    }
+}
+
+/// The radio measurement (802.11k) capabilities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RmEnabledCapabilities(pub [u8; 5]);
+
+impl RmEnabledCapabilities {
+    pub const LINK_MEASUREMENT: usize = 0;
+    pub const NEIGHBOR_REPORT: usize = 1;
+    pub const BEACON_PASSIVE_MEASUREMENT: usize = 4;
+    pub const BEACON_ACTIVE_MEASUREMENT: usize = 5;
+    pub const BEACON_TABLE_MEASUREMENT: usize = 6;
+
+    pub fn has(&self, bit: usize) -> bool {
+        self.0.get(bit / 8).is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+    }
+}
+
+impl Decode<'_> for RmEnabledCapabilities {
+    const ID: u8 = id::RM_ENABLED_CAPABILITIES;
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        Some(RmEnabledCapabilities(data.get(..5)?.try_into().unwrap()))
+    }
+}
+
+/// The extended capabilities, a bit field of variable length where absent bits are not supported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExtendedCapabilities<'a>(pub &'a [u8]);
+
+impl ExtendedCapabilities<'_> {
+    pub const BSS_TRANSITION: usize = 19;
+    pub const INTERWORKING: usize = 31;
+    pub const OPERATING_MODE_NOTIFICATION: usize = 62;
+    pub const TWT_RESPONDER_SUPPORT: usize = 78;
+    pub const SAE_PASSWORD_IDENTIFIERS_USED: usize = 81;
+    pub const SAE_PASSWORD_IDENTIFIERS_EXCLUSIVELY: usize = 82;
+
+    pub fn has(&self, bit: usize) -> bool {
+        self.0.get(bit / 8).is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+    }
+}
+
+impl<'a> Decode<'a> for ExtendedCapabilities<'a> {
+    const ID: u8 = id::EXTENDED_CAPABILITIES;
+
+    fn decode(data: &'a [u8]) -> Option<Self> {
+        Some(ExtendedCapabilities(data))
+    }
+}
@@ -3,31 +3,49 @@
 #![allow(async_fn_in_trait)]
 #![warn(clippy::all, clippy::pedantic, clippy::cargo)]
 
+#[cfg(feature = "sim")]
+extern crate std;
+
 pub(crate) mod fmt;
 
-use core::mem::{transmute, transmute_copy};
+use core::future::pending;
 
-use action::{Action, ActionState, Item};
+use action::{Action, ActionState, Item, PendingAction, Response};
 use bindings::*;
 use bus::Bus;
-use embassy_futures::select::{select3, Either3};
+use control::ControlState;
+use embassy_futures::select::{select4, Either4};
 use embassy_net_driver_channel as ch;
-use embassy_time::{Duration, Timer};
+use embassy_net_driver_channel::driver::LinkState;
+use embassy_time::{with_timeout, Duration, Timer};
 use embedded_hal::digital::{InputPin, OutputPin};
 use embedded_hal_async::digital::Wait;
+use events::{
+    ControlEvent, ControlEventPublisher, ControlEventQueue, Event, EventChannel, PacketFormat, Receive, ReceiveKind,
+    ReceivedPacket, CONTROL_EVENT_PUBLISH_TIMEOUT,
+};
 use fmt::Bytes;
-use heapless::String;
-use net::{eth, NetworkBuffer};
+use net::eth;
+use rpu::commands::Command;
 use rpu::firmware::{FirmwareInfo, FirmwareParseError};
 use rpu::memory::regions::*;
 use rpu::Rpu;
-use util::{meh, slice8, sliceit, unsliceit, unsliceit2};
+use sa_query::SaQuery;
+use supplicant::{eapol::ETH_P_PAE, GroupKey, IntegrityGroupKey, Supplicant, SupplicantError};
+use util::{meh, slice8, sliceit, unsliceit_padded};
 
 mod action;
 pub mod bus;
+pub mod capabilities;
 pub mod control;
+pub mod events;
+pub mod ie;
 mod net;
 mod rpu;
+mod sa_query;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod supplicant;
 mod util;
 
 #[allow(dead_code)]
@@ -35,6 +53,13 @@ mod bindings;
 
 const MTU: usize = 1514;
 
+/// Largest event read from the RPU, which fits a wiphy dump. Events arriving in fragments are
+/// reassembled up to this size, anything larger is dropped.
+const MAX_EVENT_SIZE: usize = 2048;
+
+/// Largest EAPOL frame handed to the supplicant, anything beyond is cut off.
+const EAPOL_FRAME_MAX_SIZE: usize = 512;
+
 // const SR0_WRITE_IN_PROGRESS: u8 = 0x01;
 const SR1_RPU_AWAKE: u8 = 0x02;
 const SR1_RPU_READY: u8 = 0x04;
@@ -54,12 +79,17 @@ pub enum Error {
     NotFound,
     NotHandled(u32),
     Busy,
+    Bus,
     FirmwareParseError(FirmwareParseError),
+    Supplicant(SupplicantError),
     Code(i32),
 }
 
 pub struct State {
     action_state: ActionState,
+    events: ControlEventQueue,
+    event_stream: EventChannel,
+    control: ControlState,
     ch: ch::State<MTU, 4, 4>,
 }
 
@@ -75,14 +105,26 @@ impl State {
         Self {
             ch: ch::State::new(),
             action_state: ActionState::new(),
+            events: ControlEventQueue::new(),
+            event_stream: EventChannel::new(),
+            control: ControlState::new(),
         }
     }
 }
 
+/// Controls the chip. Clones of it can be handed to several tasks: their commands are in flight
+/// at the same time, while init, scans and joining or leaving a network take turns.
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct Control<'a> {
     action_state: &'a ActionState,
+    events: &'a ControlEventQueue,
+    event_stream: &'a EventChannel,
     state_ch: ch::StateRunner<'a>,
+    shared: &'a ControlState,
+
+    /// How long each step of a call may take
+    timeout: Duration,
 }
 
 pub type NetDriver<'a> = ch::Device<'a, MTU>;
@@ -92,6 +134,20 @@ pub struct Runner<'a, BUS: Bus, IN: InputPin + Wait, OUT: OutputPin> {
     ch: ch::Runner<'a, MTU>,
     state_ch: ch::StateRunner<'a>,
     action_state: &'a ActionState,
+    events: ControlEventPublisher<'a>,
+    event_stream: &'a EventChannel,
+    shared: &'a ControlState,
+
+    /// Whether the link is up, to tell subscribers when it goes down
+    connected: bool,
+
+    /// Handles the EAPOL-Key frames of the network we are joined to, if it is protected
+    supplicant: Option<Supplicant>,
+
+    /// Checks with the AP whether we are still associated after an unprotected deauthentication or
+    /// disassociation, only with management frame protection
+    sa_query: Option<SaQuery>,
+    sa_query_transaction: u16,
 
     rpu: Rpu<BUS>,
     bucken: OUT,
@@ -118,6 +174,14 @@ where
         ch: ch_runner,
         state_ch,
         action_state: &state.action_state,
+        // The runner is the only publisher
+        events: state.events.publisher().unwrap(),
+        event_stream: &state.event_stream,
+        shared: &state.control,
+        connected: false,
+        supplicant: None,
+        sa_query: None,
+        sa_query_transaction: 0,
         rpu: Rpu::new(bus),
         bucken,
         iovdd_ctl,
@@ -127,7 +191,11 @@ where
 
     let control = Control {
         action_state: &state.action_state,
+        events: &state.events,
+        event_stream: &state.event_stream,
         state_ch,
+        shared: &state.control,
+        timeout: control::DEFAULT_TIMEOUT,
     };
 
     (device, control, runner)
@@ -142,8 +210,10 @@ impl<'a, BUS: Bus, IN: InputPin + Wait, OUT: OutputPin> Runner<'a, BUS, IN, OUT>
         Timer::after(Duration::from_millis(10)).await;
     }
 
-    pub async fn run(&mut self) -> ! {
-        let mut buffer_u32 = [0u32; (MAX_EVENT_POOL_LEN / 4) as usize];
+    /// Runs the driver, which only returns on an error of the bus. The state of the chip is unknown
+    /// after that, so it should be reset.
+    pub async fn run(&mut self) -> Error {
+        let mut buffer_u32 = [0u32; MAX_EVENT_SIZE / 4];
 
         loop {
             // match select(
@@ -171,8 +241,25 @@ impl<'a, BUS: Bus, IN: InputPin + Wait, OUT: OutputPin> Runner<'a, BUS, IN, OUT>
             // }
 
             let action = self.action_state.wait_pending();
-            let wifi_tx = self.ch.tx_buf();
+            // Only take frames from the network stack when there is a descriptor to place them in,
+            // the rest are held back in the channel until the RPU has returned a token
+            let transmit_token_available = self.rpu.transmit_token_available();
+            let ch = &mut self.ch;
+            let wifi_tx = async move {
+                if transmit_token_available {
+                    ch.tx_buf().await
+                } else {
+                    pending().await
+                }
+            };
             let irq_event = self.host_irq.wait_for_high();
+            let sa_query_retry_at = self.sa_query.as_ref().map(SaQuery::retry_at);
+            let sa_query_retry = async move {
+                match sa_query_retry_at {
+                    Some(instant) => Timer::at(instant).await,
+                    None => pending().await,
+                }
+            };
 
             // Need select here for control
             //
@@ -181,97 +268,127 @@ impl<'a, BUS: Bus, IN: InputPin + Wait, OUT: OutputPin> Runner<'a, BUS, IN, OUT>
             // Wait for TX buffer from ch (on runner). This is the net layer
             // This is basically the entrypoint for sending packets
 
-            match select3(action, wifi_tx, irq_event).await {
-                Either3::First(action) => {
+            match select4(action, wifi_tx, irq_event, sa_query_retry).await {
+                Either4::First(PendingAction { slot, sequence, action }) => {
                     debug!("Action: {:?}", action);
 
                     match action {
                         Action::Boot(firmware) => match self.boot(firmware).await {
                             Ok(()) => (),
-                            Err(error) => self.action_state.respond(Err(error)),
+                            Err(error) => {
+                                self.action_state.respond(slot, Err(error));
+
+                                if let Error::Bus = error {
+                                    return error;
+                                }
+                            }
                         },
                         Action::Command((kind, wait_for_completion, buffer, _)) => {
-                            match self.rpu.send_command_raw(kind, buffer).await {
+                            // The deauthentication the UMAC reports for it is not a lost AP
+                            if let Some(reason) = deauthentication_reason(kind, unsafe { &*buffer }) {
+                                self.disconnected(Some(reason));
+                            }
+
+                            match self.rpu.send_command_raw(kind, buffer, sequence).await {
                                 Ok(()) => {
                                     if !wait_for_completion {
-                                        self.action_state.respond(Ok(None));
+                                        self.action_state.respond(slot, Ok(None));
+                                    }
+                                }
+                                Err(error) => {
+                                    self.action_state.respond(slot, Err(error));
+
+                                    if let Error::Bus = error {
+                                        return error;
                                     }
                                 }
-                                Err(error) => self.action_state.respond(Err(error)),
                             }
                         }
                         Action::Get((item, _)) => match item {
-                            Item::UmacInfo => {
-                                let umac_info = self.rpu.retrieve_umac_info().await;
-                                let umac_info_buffer = sliceit(&umac_info);
+                            Item::UmacInfo => match self.rpu.retrieve_umac_info().await {
+                                Ok(umac_info) => {
+                                    let umac_info_buffer = sliceit(&umac_info);
 
-                                self.action_state.respond(Ok(Some(&umac_info_buffer[..])));
-                            }
+                                    self.action_state.respond(slot, Ok(Some(&umac_info_buffer[..])));
+                                }
+                                Err(error) => {
+                                    self.action_state.respond(slot, Err(error));
+                                    return error;
+                                }
+                            },
                         },
+                        Action::Supplicant(supplicant) => {
+                            self.supplicant = supplicant.map(|supplicant| unsafe { (*supplicant).clone() });
+                            self.sa_query = None;
+                            self.action_state.respond(slot, Ok(None));
+                        }
                     };
                 }
-                Either3::Second(packet) => {
+                Either4::Second(packet) => {
                     debug!("tx pkt {:02x}", Bytes(&packet[..packet.len().min(48)]));
+
+                    match self.rpu.transmit(packet).await {
+                        Ok(()) => {}
+                        Err(Error::Bus) => return Error::Bus,
+                        Err(error) => warn!("Failed to transmit packet: {:?}", error),
+                    }
+
+                    self.ch.tx_done();
                 }
-                Either3::Third(irq) => {
+                Either4::Third(irq) => {
                     debug!("Got IRQ, checking event queue...");
 
                     match irq {
                         Ok(()) => {
-                            self.rpu.irq_ack().await;
+                            if let Err(error) = self.rpu.irq_ack().await {
+                                return error;
+                            }
                         }
                         Err(_) => continue,
                     }
 
                     let event = self.rpu.read_event(&mut buffer_u32).await;
 
+                    match &event {
+                        Ok(_) | Err(Error::NoData) => {}
+                        Err(Error::Bus) => return Error::Bus,
+                        Err(err) => warn!("Failed to read event: {:?}", err),
+                    }
+
                     if let Ok(message) = event {
-                        let message_type = message.type_ as u32;
-                        let message_size = message.hdr.len as usize;
-
-                        let message_type = match nrf_wifi_host_rpu_msg_type::try_from(message_type) {
-                            Ok(message_type) => {
-                                debug!(
-                                    "Got {:?} event ({}). Message length: {}",
-                                    message_type, message_type as u32, message_size
-                                );
-                                Some(message_type)
-                            }
-                            Err(_) => {
-                                warn!("Unknown event type {:08x}", message_type);
-                                None
-                            }
-                        };
+                        debug!(
+                            "Got event of type {}. Message length: {}",
+                            meh(message.type_),
+                            meh(message.hdr.len)
+                        );
 
                         let buffer_u8 = slice8(&buffer_u32);
 
-                        if let Some(message_type) = message_type {
-                            match message_type {
-                                nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_SYSTEM => {
-                                    self.handle_system_message(buffer_u8, message_size);
-                                }
-                                nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_UMAC => {
-                                    self.handle_umac_message(buffer_u8, message_size);
-                                }
-                                nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_DATA => {
-                                    match self.handle_data_message(buffer_u8).await {
-                                        Ok(()) => {}
-                                        Err(err) => warn!("Failed to handle data message {:?}", err),
-                                    }
-                                }
-                                nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_SUPPLICANT => {
-                                    debug!("Got supplicant event, ignoring...");
-                                }
-                            }
-                        } else {
-                            warn!("Unhandled message type: {}", meh(message.type_));
+                        match Event::from_message(&message, buffer_u8) {
+                            Ok(event) => match self.handle_event(event, &message, buffer_u8).await {
+                                Ok(()) => {}
+                                Err(Error::Bus) => return Error::Bus,
+                                Err(err) => warn!("Failed to handle event {:?}", err),
+                            },
+                            Err(err) => warn!("Failed to decode event: {:?}", err),
                         }
                     }
 
-                    if self.rpu.irq_watchdog_check().await {
-                        self.rpu.irq_watchdog_ack().await;
+                    match self.rpu.irq_watchdog_check().await {
+                        Ok(true) => {
+                            if let Err(error) = self.rpu.irq_watchdog_ack().await {
+                                return error;
+                            }
+                        }
+                        Ok(false) => {}
+                        Err(error) => return error,
                     }
                 }
+                Either4::Fourth(()) => match self.send_sa_query().await {
+                    Ok(()) => {}
+                    Err(Error::Bus) => return Error::Bus,
+                    Err(error) => warn!("Failed to send SA Query: {:?}", error),
+                },
             }
         }
     }
@@ -281,219 +398,571 @@ impl<'a, BUS: Bus, IN: InputPin + Wait, OUT: OutputPin> Runner<'a, BUS, IN, OUT>
         self.rpu.boot(&firmware_info).await
     }
 
-    fn handle_system_message(&self, buffer: &[u8], size: usize) {
-        let header: &nrf_wifi_sys_head = unsliceit(buffer);
-        let event = nrf_wifi_sys_events::try_from(header.cmd_event as u32);
-        let payload_length = header.len;
-
-        if let Ok(event) = event {
-            debug!("Processing system event: {:?}, length: {}", event, payload_length);
+    /// Passes the event on to the control, if it is waiting for events. Waits a little for the
+    /// control to have room for it, then drops the oldest event so that the control sees it lost
+    /// events.
+    async fn publish(&self, event: ControlEvent) {
+        if with_timeout(CONTROL_EVENT_PUBLISH_TIMEOUT, self.events.publish(event.clone()))
+            .await
+            .is_err()
+        {
+            warn!("Control is not taking events, dropping the oldest");
+            self.events.publish_immediate(event);
         }
+    }
 
-        match event {
-            Ok(nrf_wifi_sys_events::NRF_WIFI_EVENT_INIT_DONE) => {
-                self.action_state.respond(Ok(None));
-            }
-            Ok(nrf_wifi_sys_events::NRF_WIFI_EVENT_STATS) => self.action_state.respond(Ok(Some(&buffer[..size]))),
-            _ => warn!("System event not handled: {:08x}", meh(header.cmd_event)),
+    /// Passes the event on to the subscribers of the application, see [`Control::subscribe`].
+    fn notify(&self, event: Event) {
+        self.event_stream.immediate_publisher().publish_immediate(event);
+    }
+
+    /// Brings the link up.
+    fn connected(&mut self) {
+        self.state_ch.set_link_state(LinkState::Up);
+
+        if !self.connected {
+            self.connected = true;
+            self.notify(Event::Connected);
         }
     }
 
-    fn handle_umac_message(&self, buffer: &[u8], size: usize) {
-        let header: &nrf_wifi_umac_hdr = unsliceit(buffer);
-        let event = nrf_wifi_umac_events::try_from(header.cmd_evnt as u32);
-        let header_length = size_of::<nrf_wifi_umac_hdr>();
-        let payload_length = size - header_length;
+    /// Takes the link down, with the reason code of the deauthentication or disassociation which
+    /// ended the association.
+    fn disconnected(&mut self, reason: Option<u16>) {
+        self.state_ch.set_link_state(LinkState::Down);
 
-        if let Ok(event) = event {
-            debug!(
-                "Processing UMAC event: {:?} ({}), length: {}",
-                event, event as u32, payload_length
-            );
+        if self.connected {
+            self.connected = false;
+            self.notify(Event::Disconnected { reason });
         }
+    }
 
-        match event {
-            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_CMD_STATUS) => {
-                let command: &nrf_wifi_umac_event_cmd_status = unsliceit(buffer);
-                let status = command.cmd_status as i32;
+    /// Ends the association on a deauthentication or disassociation, which is taken as a beacon
+    /// loss if the UMAC generated it while the link was up.
+    fn association_ended(&mut self, reason: Option<u16>, locally_generated: bool) {
+        self.sa_query = None;
+        self.shared.disassociated();
 
-                let command_type = nrf_wifi_umac_commands::try_from(meh(command.cmd_id));
+        if locally_generated && self.connected {
+            self.notify(Event::BeaconLoss);
+        }
 
-                if let Ok(command_type) = command_type {
-                    debug!(
+        self.disconnected(reason);
+    }
+
+    /// Acts on an event from the RPU. `buffer` holds the message of the event, which is passed on
+    /// as the response to commands whose result is read out by the control.
+    async fn handle_event(&mut self, event: Event, message: &host_rpu_msg, buffer: &[u8]) -> Result<(), Error> {
+        match event {
+            Event::InitDone => self.action_state.complete(Response::InitDone, None, Ok(None)),
+            Event::Stats => {
+                let length = (message.hdr.len as usize).min(buffer.len());
+                self.action_state
+                    .complete(Response::Stats, None, Ok(Some(&buffer[..length])));
+            }
+            Event::CommandStatus {
+                command,
+                sequence,
+                status,
+            } => {
+                match nrf_wifi_umac_commands::try_from(command) {
+                    Ok(command_type) => debug!(
                         "Command {:?} ({}) finished with status {}",
-                        command_type,
-                        meh(command.cmd_id),
-                        status
-                    );
-                } else {
-                    debug!(
-                        "Command UNKNOWN ({}) finished with status {}",
-                        meh(command.cmd_id),
-                        status
-                    );
+                        command_type, command, status
+                    ),
+                    Err(_) => debug!("Command UNKNOWN ({}) finished with status {}", command, status),
                 }
 
-                match status {
-                    0 => self.action_state.respond(Ok(None)),
-                    error => self.action_state.respond(Err(Error::Code(error as i32))),
+                // Issued without waiting for its status, which the scan waits for instead
+                if command == nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_GET_SCAN_RESULTS as u32 {
+                    self.publish(ControlEvent::ScanResultsDone).await;
+                    return Ok(());
                 }
-            }
-            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_IFFLAGS_STATUS) => {
-                let state: &nrf_wifi_umac_event_vif_state = unsliceit(buffer);
-                let status = state.status;
 
+                let result = match status {
+                    0 => Ok(None),
+                    error => Err(Error::Code(error)),
+                };
+
+                self.action_state
+                    .complete(Response::CommandStatus(command), Some(sequence), result);
+            }
+            Event::InterfaceStatus { status } => {
                 debug!("Interface flags update finished with status {}", status);
 
-                match status {
-                    0 => self.action_state.respond(Ok(None)),
-                    error => self.action_state.respond(Err(Error::Code(error))),
-                }
+                let result = match status {
+                    0 => Ok(None),
+                    error => Err(Error::Code(error)),
+                };
+
+                self.action_state.complete(Response::InterfaceStatus, None, result);
+            }
+            Event::ScanStarted => debug!("Scan started"),
+            Event::ScanDone => {
+                self.notify(Event::ScanDone);
+                self.publish(ControlEvent::ScanDone).await;
+            }
+            Event::ScanAborted => {
+                self.notify(Event::ScanAborted);
+                self.publish(ControlEvent::ScanAborted).await;
             }
-            Ok(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_TRIGGER_SCAN_START) => {
-                let response: &nrf_wifi_umac_event_trigger_scan = unsliceit(buffer);
+            Event::ScanResult { bss, last } => {
+                debug!(
+                    "Scan result. BSSID: {:02x}. Frequency: {}. Signal: {} mBm",
+                    Bytes(&bss.bssid),
+                    bss.frequency,
+                    bss.signal
+                );
 
+                self.notify(Event::ScanResult { bss: bss.clone(), last });
+                self.publish(ControlEvent::ScanResult { bss, last }).await;
+            }
+            Event::Authenticate(frame) => {
                 debug!(
-                    "Scan started. Flags {:#x}. SSIDs: {}. Frequencies: {}. IE length: {}.",
-                    meh(response.nrf_wifi_scan_flags),
-                    meh(response.num_scan_ssid),
-                    meh(response.num_scan_frequencies),
-                    meh(response.ie.ie_len)
+                    "Authentication frame {:?} with status {:?}",
+                    frame.as_ref().map(|frame| frame.transaction),
+                    frame.as_ref().map(|frame| frame.status)
                 );
+                self.publish(ControlEvent::Authenticate(frame)).await;
             }
-            _ => warn!("UMAC event not handled: {:#08x}", meh(header.cmd_evnt)),
-        }
-    }
+            Event::Associate { status } => {
+                debug!("Association finished with status {:?}", status);
+                self.publish(ControlEvent::Associate { status }).await;
+            }
+            Event::Deauthenticate {
+                reason,
+                locally_generated,
+            } => {
+                self.association_ended(reason, locally_generated);
+                self.publish(ControlEvent::Deauthenticate).await;
+            }
+            Event::Disassociate {
+                reason,
+                locally_generated,
+            } => {
+                self.association_ended(reason, locally_generated);
+                self.publish(ControlEvent::Disassociate).await;
+            }
+            Event::UnprotectedDeauthenticate { reason } | Event::UnprotectedDisassociate { reason } => {
+                // These are never taken as the end of the association, as anyone can send them
+                warn!(
+                    "Unprotected deauthentication or disassociation with reason {:?}",
+                    reason
+                );
 
-    async fn handle_data_message(&mut self, buffer: &[u8]) -> Result<(), Error> {
-        let header: &nrf_wifi_umac_head = unsliceit(buffer);
-        let command = nrf_wifi_umac_data_commands::try_from(header.cmd);
+                let protected = self
+                    .supplicant
+                    .as_ref()
+                    .is_some_and(Supplicant::management_frame_protection);
+
+                if protected
+                    && self.sa_query.is_none()
+                    && matches!(
+                        reason,
+                        Some(sa_query::REASON_CLASS_2_FRAME | sa_query::REASON_CLASS_3_FRAME)
+                    )
+                {
+                    debug!("Starting SA Query");
+                    self.sa_query = Some(SaQuery::new());
+                }
+            }
+            Event::Frame(frame) => {
+                if let (Some(query), Some(supplicant)) = (self.sa_query.as_ref(), self.supplicant.as_ref()) {
+                    if query.is_response(&frame.frame, &supplicant.authenticator_address()) {
+                        debug!("Got SA Query response, still associated");
+                        self.sa_query = None;
+                    }
+                }
 
-        if let Ok(command) = command {
-            debug!("Processing DATA command: {:?}, length: {}", command, meh(header.len));
-        }
+                self.notify(Event::Frame(frame));
+            }
+            Event::FrameTxStatus { acknowledged, .. } => {
+                debug!("Management frame sent. Acknowledged: {}", acknowledged);
+            }
+            Event::Disconnect => {
+                self.shared.disassociated();
+                self.disconnected(None);
+                self.publish(ControlEvent::Disconnect).await;
+            }
+            Event::Wiphy => match buffer.get(..size_of::<nrf_wifi_event_get_wiphy>()) {
+                Some(wiphy) => self.action_state.complete(Response::Wiphy, None, Ok(Some(wiphy))),
+                None => self
+                    .action_state
+                    .complete(Response::Wiphy, None, Err(Error::BufferTooSmall)),
+            },
+            Event::CarrierOn => {
+                debug!("Carrier state ON");
+
+                // Protected networks are up once the keys are in place
+                if self.supplicant.is_none() {
+                    self.connected();
+                }
 
-        match command {
-            Ok(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_CARRIER_ON) => {
-                let carrier_state: &nrf_wifi_data_carrier_state = unsliceit(buffer);
-                debug!("Carrier state ON for WDEV {}", meh(carrier_state.wdev_id));
-                Ok(())
+                self.notify(Event::CarrierOn);
+                self.publish(ControlEvent::CarrierOn).await;
+            }
+            Event::CarrierOff => {
+                debug!("Carrier state OFF");
+                self.disconnected(None);
+                self.notify(Event::CarrierOff);
+                self.publish(ControlEvent::CarrierOff).await;
+            }
+            Event::Receive(receive) => return self.handle_rx_buffer(&receive).await,
+            Event::TransmitDone { descriptor, packets } => {
+                debug!("TX done for descriptor {}. # packets: {}", descriptor, packets);
+                return self.rpu.transmit_done(descriptor as usize);
             }
-            Ok(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_CARRIER_OFF) => {
-                let carrier_state: &nrf_wifi_data_carrier_state = unsliceit(buffer);
-                debug!("Carrier state OFF for WDEV {}", meh(carrier_state.wdev_id));
-                Ok(())
+            Event::PowerData { .. } => self.notify(event),
+            Event::DeinitDone => debug!("Firmware deinitialized"),
+            // Never decoded, only published by the runner
+            Event::Connected | Event::Disconnected { .. } | Event::BeaconLoss => {}
+            Event::Other { message_type, id } => {
+                warn!("Event not handled: {} of message type {}", id, message_type);
             }
-            Ok(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_RX_BUFF) => self.handle_rx_buffer(buffer).await,
-            _ => Err(Error::NotHandled(meh(header.cmd))),
         }
-    }
-
-    async fn handle_rx_buffer(&mut self, buffer: &[u8]) -> Result<(), Error> {
-        let (rx_packet, buf) = unsliceit2::<nrf_wifi_rx_buff>(buffer);
 
-        let rx_packet_type = nrf_wifi_rx_pkt_type::try_from(meh(rx_packet.rx_pkt_type) as u32);
-
-        let number_of_packets = rx_packet.rx_pkt_cnt as usize;
-        let mac_header_length = rx_packet.mac_header_len as usize;
+        Ok(())
+    }
 
+    async fn handle_rx_buffer(&mut self, receive: &Receive) -> Result<(), Error> {
         debug!(
             "Got RX buffer. # packets: {}. Frequency: {}",
-            number_of_packets,
-            meh(rx_packet.frequency)
+            receive.packets.len(),
+            receive.frequency
         );
 
-        let rx_buffer_infos: &[nrf_wifi_rx_buff_info] = unsafe { transmute(buf) };
+        for packet in &receive.packets {
+            self.rpu.take_receive_buffer(packet.descriptor as usize)?;
 
-        for packet_index in 0..number_of_packets {
-            let rx_buffer_info = rx_buffer_infos[packet_index];
+            let result = self.handle_rx_packet(receive, packet).await;
 
-            let packet_descriptor_identifier = rx_buffer_info.descriptor_id as usize;
-            let packet_length = rx_buffer_info.rx_pkt_len as usize;
-            let packet_type = rx_buffer_info.pkt_type as u32;
+            // The RPU can fill the buffer again once the packet is handled, whether it could be
+            // passed on or not
+            self.rpu.recycle_receive_buffer(packet.descriptor as usize).await?;
 
-            debug!(
-                "RX packet - Descriptor: {}. Length: {}. Type: {}",
-                packet_descriptor_identifier, packet_length, packet_type
-            );
+            result?;
+        }
 
-            self.rpu
-                .update_cached_receive_buffer(packet_descriptor_identifier, packet_length)
-                .await?;
+        Ok(())
+    }
 
-            let raw_buffer = self.rpu.get_cached_receive_buffer_slice(packet_descriptor_identifier)?;
+    /// Reads a received packet from its buffer on the RPU. Data frames are read straight into the
+    /// RX buffer of the network stack, where the 802.11 header is replaced by an Ethernet header.
+    async fn handle_rx_packet(&mut self, receive: &Receive, packet: &ReceivedPacket) -> Result<(), Error> {
+        const ETH_HEADER_SIZE: usize = size_of::<nrf_wifi_fmac_eth_hdr>();
+        const IEEE80211_HEADER_SIZE: usize = size_of::<nrf_wifi_fmac_ieee80211_hdr>();
 
-            let mut network_buffer = NetworkBuffer::new(raw_buffer, packet_length);
+        let mac_header_length = receive.mac_header_length as usize;
 
-            match rx_packet_type {
-                Ok(nrf_wifi_rx_pkt_type::NRF_WIFI_RX_PKT_DATA) => {
-                    match packet_type {
-                        PKT_TYPE_MPDU => {
-                            let header: nrf_wifi_fmac_ieee80211_hdr = unsafe {
-                                let mut header_buffer = [0u8; size_of::<nrf_wifi_fmac_ieee80211_hdr>()];
-                                header_buffer.copy_from_slice(
-                                    &network_buffer.get_data()[..size_of::<nrf_wifi_fmac_ieee80211_hdr>()],
-                                );
+        let packet_descriptor_identifier = packet.descriptor as usize;
+        let packet_length = packet.length as usize;
 
-                                transmute_copy(&header_buffer)
-                            };
+        debug!(
+            "RX packet - Descriptor: {}. Length: {}. Format: {:?}",
+            packet_descriptor_identifier, packet_length, packet.format
+        );
 
-                            let eth_type_buffer: [u8; 2] = network_buffer.get_data()
-                                [mac_header_length + 6..mac_header_length + 8]
-                                .try_into()
-                                .map_err(|_| Error::BufferTooSmall)?;
+        match receive.kind {
+            ReceiveKind::Data => match packet.format {
+                PacketFormat::Mpdu => {
+                    // The 802.11 header followed by the LLC header, which ends with the EtherType
+                    let mut header_buffer = [0u8; IEEE80211_HEADER_SIZE + 8];
+                    let eth_type_offset = mac_header_length + 6;
 
-                            let eth_type = eth::get_type(&eth_type_buffer);
-                            let header_size = mac_header_length + eth::get_skip_header_bytes(eth_type);
+                    if eth_type_offset + 2 > header_buffer.len().min(packet_length) {
+                        return Err(Error::BufferTooSmall);
+                    }
 
-                            const ETH_HEADER_SIZE: usize = size_of::<nrf_wifi_fmac_eth_hdr>();
+                    let header_length = header_buffer.len().min(packet_length);
+                    self.rpu
+                        .read_receive_buffer(packet_descriptor_identifier, 0, &mut header_buffer[..header_length])
+                        .await?;
 
-                            // Skip to the ETH header
-                            network_buffer.increase_head_room(header_size - ETH_HEADER_SIZE)?;
+                    let header: nrf_wifi_fmac_ieee80211_hdr =
+                        unsafe { core::ptr::read_unaligned(header_buffer.as_ptr().cast()) };
 
-                            let eth_header_ptr = network_buffer.get_data().as_ptr() as *mut nrf_wifi_fmac_eth_hdr;
-                            let data_length = network_buffer.get_data().len() - ETH_HEADER_SIZE;
+                    let eth_type = eth::get_type(&[header_buffer[eth_type_offset], header_buffer[eth_type_offset + 1]]);
+                    let header_size = mac_header_length + eth::get_skip_header_bytes(eth_type);
 
-                            unsafe {
-                                eth_header_ptr.write(nrf_wifi_fmac_eth_hdr::new(data_length as u16, &header, eth_type));
-                            }
+                    if header_size > packet_length {
+                        return Err(Error::BufferTooSmall);
+                    }
 
-                            let payload = network_buffer.get_data();
+                    let data_length = packet_length - header_size;
 
-                            match self.ch.try_rx_buf() {
-                                Some(buf) => {
-                                    debug!("Copying {} bytes into buffer", payload.len());
-                                    buf[..payload.len()].copy_from_slice(payload);
-                                    self.ch.rx_done(payload.len())
-                                }
-                                None => warn!("failed to push RX packet to the channel."),
-                            }
-                        }
-                        PKT_TYPE_MSDU_WITH_MAC => {
-                            warn!("PKT_TYPE_MSDU_WITH_MAC is unhandled");
-                        }
-                        PKT_TYPE_MSDU => {
-                            warn!("PKT_TYPE_MSDU is unhandled");
-                        }
-                        _ => warn!("Unknown packet type {}", packet_type),
+                    // Key frames go to the supplicant rather than the network stack
+                    if eth_type == ETH_P_PAE {
+                        let mut frame = [0u8; EAPOL_FRAME_MAX_SIZE];
+                        let frame_length = data_length.min(EAPOL_FRAME_MAX_SIZE);
+
+                        self.rpu
+                            .read_receive_buffer(packet_descriptor_identifier, header_size, &mut frame[..frame_length])
+                            .await?;
+
+                        return self.handle_eapol(&frame[..frame_length]).await;
                     }
+
+                    let Some(buf) = self.ch.try_rx_buf() else {
+                        warn!("failed to push RX packet to the channel.");
+                        return Ok(());
+                    };
+
+                    let frame_length = ETH_HEADER_SIZE + data_length;
+
+                    if frame_length > buf.len() {
+                        warn!("Dropping RX packet of {} bytes, larger than the MTU", frame_length);
+                        return Ok(());
+                    }
+
+                    // Read the data along with the end of the LLC header, which is overwritten
+                    // by the Ethernet header
+                    self.rpu
+                        .read_receive_buffer(
+                            packet_descriptor_identifier,
+                            header_size - ETH_HEADER_SIZE,
+                            &mut buf[..frame_length],
+                        )
+                        .await?;
+
+                    let eth_header = nrf_wifi_fmac_eth_hdr::new(data_length as u16, &header, eth_type);
+                    buf[..ETH_HEADER_SIZE].copy_from_slice(sliceit(&eth_header));
+
+                    debug!("Read {} bytes into buffer", frame_length);
+                    self.ch.rx_done(frame_length);
+                }
+                PacketFormat::MsduWithMac => {
+                    warn!("PKT_TYPE_MSDU_WITH_MAC is unhandled");
                 }
-                Ok(nrf_wifi_rx_pkt_type::NRF_WIFI_RX_PKT_BCN_PRB_RSP) => {
-                    let mut buffer: String<512> = String::new();
-                    hexdump(&mut buffer, network_buffer.get_data());
-                    info!("{}", buffer);
+                PacketFormat::Msdu => {
+                    warn!("PKT_TYPE_MSDU is unhandled");
                 }
-                _ => {
-                    let rx_packet_type = rx_packet.rx_pkt_type;
+                PacketFormat::Unknown(packet_type) => warn!("Unknown packet type {}", packet_type),
+            },
+            ReceiveKind::BeaconOrProbeResponse => {
+                // The management header is followed by the timestamp, beacon interval and
+                // capabilities before the elements
+                const ELEMENTS_OFFSET: usize = 24 + 8 + 2 + 2;
+
+                // Only the start of the frame is read, which holds the SSID and in most cases the
+                // channel
+                let mut frame = [0u8; 128];
+                let frame = &mut frame[..packet_length.min(128)];
 
-                    warn!("Unknown RX packet type: {:#x}", rx_packet_type);
-                    return Err(Error::NotHandled(rx_packet_type as u32));
+                self.rpu
+                    .read_receive_buffer(packet_descriptor_identifier, 0, frame)
+                    .await?;
+
+                if let Some(elements) = frame.get(ELEMENTS_OFFSET..).map(ie::Elements::new) {
+                    let ssid = elements.get::<ie::Ssid>().map(|ssid| ssid.0).unwrap_or_default();
+
+                    debug!(
+                        "Beacon or probe response from {:02x}. SSID: {}. Channel: {:?}",
+                        Bytes(&frame[16..22]),
+                        core::str::from_utf8(ssid).unwrap_or("?"),
+                        elements.get::<ie::DsParameterSet>().map(|ds| ds.channel)
+                    );
                 }
             }
+            ReceiveKind::Raw => warn!("Raw RX packets are unhandled"),
+            ReceiveKind::Unknown(rx_packet_type) => {
+                warn!("Unknown RX packet type: {:#x}", rx_packet_type);
+                return Err(Error::NotHandled(rx_packet_type as u32));
+            }
         }
 
         Ok(())
     }
+
+    /// Passes an EAPOL frame (without the Ethernet header) to the supplicant, sends its response
+    /// and installs the keys it hands out.
+    async fn handle_eapol(&mut self, frame: &[u8]) -> Result<(), Error> {
+        const ETH_HEADER_SIZE: usize = size_of::<nrf_wifi_fmac_eth_hdr>();
+
+        let Some(supplicant) = self.supplicant.as_mut() else {
+            warn!("Got EAPOL frame without a supplicant, dropping it");
+            return Ok(());
+        };
+
+        let mut response = [0u8; ETH_HEADER_SIZE + EAPOL_FRAME_MAX_SIZE];
+
+        let outcome = match supplicant.process(frame, &mut response[ETH_HEADER_SIZE..]) {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                warn!("Dropping EAPOL frame: {:?}", error);
+
+                // Frames which fail the integrity checks might be forged, so the handshake is only
+                // given up on when the AP itself does something we do not support
+                if matches!(
+                    error,
+                    SupplicantError::RsnElementMismatch
+                        | SupplicantError::UnsupportedNetwork
+                        | SupplicantError::InvalidKeyData
+                ) {
+                    self.publish(ControlEvent::HandshakeFailed(error)).await;
+                }
+
+                return Ok(());
+            }
+        };
+
+        let authenticator_address = supplicant.authenticator_address();
+
+        if let Some(length) = outcome.response_length {
+            response[..6].copy_from_slice(&authenticator_address);
+            response[6..12].copy_from_slice(&supplicant.own_address());
+            response[12..14].copy_from_slice(&ETH_P_PAE.to_be_bytes());
+
+            self.rpu.transmit(&response[..ETH_HEADER_SIZE + length]).await?;
+        }
+
+        if let Some(key) = outcome.pairwise_key {
+            let command = nrf_wifi_umac_cmd_key::new_key(
+                nrf_wifi_umac_key_info::ccmp(&key, 0, nrf_wifi_key_type::NRF_WIFI_KEYTYPE_PAIRWISE, None),
+                Some(authenticator_address),
+            );
+
+            self.rpu
+                .send_command_raw(command.domain(), sliceit(&command), 0)
+                .await?;
+        }
+
+        if let Some(group_key) = outcome.group_key {
+            self.install_group_key(&group_key).await?;
+        }
+
+        if let Some(integrity_group_key) = outcome.integrity_group_key {
+            self.install_integrity_group_key(&integrity_group_key).await?;
+        }
+
+        if outcome.pairwise_key.is_some() {
+            let mut command = nrf_wifi_umac_cmd_chg_sta {
+                umac_hdr: nrf_wifi_umac_hdr::default(),
+                valid_fields: NRF_WIFI_CMD_SET_STATION_STA_FLAGS2_VALID,
+                info: unsafe { core::mem::zeroed() },
+            };
+            command.info.sta_flags2.nrf_wifi_mask = NRF_WIFI_STA_FLAG_AUTHORIZED;
+            command.info.sta_flags2.nrf_wifi_set = NRF_WIFI_STA_FLAG_AUTHORIZED;
+            command.info.mac_addr = authenticator_address;
+
+            self.rpu.send_command(command).await?;
+
+            info!("Port authorized");
+
+            self.connected();
+            self.publish(ControlEvent::PortAuthorized).await;
+        }
+
+        Ok(())
+    }
+
+    /// Installs a group key and makes it the default key for multicast frames.
+    async fn install_group_key(&mut self, group_key: &GroupKey) -> Result<(), Error> {
+        let key_info = nrf_wifi_umac_key_info::ccmp(
+            &group_key.key,
+            group_key.index,
+            nrf_wifi_key_type::NRF_WIFI_KEYTYPE_GROUP,
+            Some(group_key.rsc),
+        );
+
+        let command = nrf_wifi_umac_cmd_key::new_key(key_info, None);
+        self.rpu
+            .send_command_raw(command.domain(), sliceit(&command), 0)
+            .await?;
+
+        let mut key_info: nrf_wifi_umac_key_info = unsafe { core::mem::zeroed() };
+        key_info.valid_fields = NRF_WIFI_KEY_IDX_VALID;
+        key_info.nrf_wifi_flags = (NRF_WIFI_KEY_DEFAULT | NRF_WIFI_KEY_DEFAULT_TYPE_MULTICAST) as u16;
+        key_info.key_idx = group_key.index;
+
+        self.rpu
+            .send_command(nrf_wifi_umac_cmd_set_key {
+                umac_hdr: nrf_wifi_umac_hdr::default(),
+                key_info,
+            })
+            .await?;
+
+        debug!("Installed group key {}", group_key.index);
+
+        Ok(())
+    }
+
+    /// Installs an integrity group key and makes it the default key for management frames.
+    async fn install_integrity_group_key(&mut self, integrity_group_key: &IntegrityGroupKey) -> Result<(), Error> {
+        let key_info = nrf_wifi_umac_key_info::aes_cmac(
+            &integrity_group_key.key,
+            integrity_group_key.index,
+            integrity_group_key.ipn,
+        );
+
+        let command = nrf_wifi_umac_cmd_key::new_key(key_info, None);
+        self.rpu
+            .send_command_raw(command.domain(), sliceit(&command), 0)
+            .await?;
+
+        let mut key_info: nrf_wifi_umac_key_info = unsafe { core::mem::zeroed() };
+        key_info.valid_fields = NRF_WIFI_KEY_IDX_VALID;
+        key_info.nrf_wifi_flags = NRF_WIFI_KEY_DEFAULT_MGMT as u16;
+        key_info.key_idx = integrity_group_key.index;
+
+        self.rpu
+            .send_command(nrf_wifi_umac_cmd_set_key {
+                umac_hdr: nrf_wifi_umac_hdr::default(),
+                key_info,
+            })
+            .await?;
+
+        debug!("Installed integrity group key {}", integrity_group_key.index);
+
+        Ok(())
+    }
+
+    /// Sends the next request of the SA Query in progress. When the AP has not answered in time it
+    /// no longer knows us, so we deauthenticate and take the link down.
+    async fn send_sa_query(&mut self) -> Result<(), Error> {
+        /// Previous authentication no longer valid
+        const DEAUTHENTICATION_REASON_INVALID: u16 = 2;
+
+        let (Some(query), Some(supplicant)) = (self.sa_query.as_mut(), self.supplicant.as_ref()) else {
+            self.sa_query = None;
+            return Ok(());
+        };
+
+        let authenticator_address = supplicant.authenticator_address();
+        let transaction = self.sa_query_transaction;
+        self.sa_query_transaction = transaction.wrapping_add(1);
+
+        if query.add_request(transaction).is_ok() {
+            let frame = sa_query::request(&supplicant.own_address(), &authenticator_address, transaction);
+            return self
+                .rpu
+                .send_command(nrf_wifi_umac_cmd_mgmt_tx::new(&frame, u64::from(transaction)))
+                .await;
+        }
+
+        warn!("No SA Query response from the AP, leaving the network");
+
+        self.sa_query = None;
+        self.supplicant = None;
+        self.shared.disassociated();
+        self.disconnected(Some(DEAUTHENTICATION_REASON_INVALID));
+
+        let mut command = nrf_wifi_umac_cmd_disconn::default();
+        command.valid_fields = NRF_WIFI_CMD_MLME_MAC_ADDR_VALID;
+        command.info.reason_code = DEAUTHENTICATION_REASON_INVALID;
+        command.info.mac_addr = authenticator_address;
+
+        self.publish(ControlEvent::Deauthenticate).await;
+        self.rpu.send_command(command).await
+    }
+}
+
+/// The reason code of a deauthenticate command, `None` for any other command.
+fn deauthentication_reason(kind: nrf_wifi_host_rpu_msg_type, command: &[u8]) -> Option<u16> {
+    let command: nrf_wifi_umac_cmd_disconn = unsliceit_padded(command);
+
+    (kind == nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_UMAC
+        && command.umac_hdr.cmd_evnt == nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_DEAUTHENTICATE as u32)
+        .then_some(command.info.reason_code)
 }
 
 use core::fmt::Write;
@@ -1,6 +1,6 @@
 use crate::bindings::{
-    nrf_wifi_fmac_eth_hdr, nrf_wifi_fmac_ieee80211_hdr, NRF_WIFI_FCTL_FROMDS, NRF_WIFI_FCTL_TODS,
-    NRF_WIFI_FMAC_ETH_P_802_3_MIN, NRF_WIFI_FMAC_ETH_P_AARP, NRF_WIFI_FMAC_ETH_P_IPX,
+    nrf_wifi_fmac_eth_hdr, nrf_wifi_fmac_ieee80211_hdr, tx_mac_hdr_info, NRF_WIFI_ETH_ADDR_LEN, NRF_WIFI_FCTL_FROMDS,
+    NRF_WIFI_FCTL_TODS, NRF_WIFI_FMAC_ETH_P_802_3_MIN, NRF_WIFI_FMAC_ETH_P_AARP, NRF_WIFI_FMAC_ETH_P_IPX,
 };
 
 pub fn get_type(buffer: &[u8; 2]) -> u16 {
@@ -64,3 +64,29 @@ impl nrf_wifi_fmac_eth_hdr {
         };
     }
 }
+
+impl tx_mac_hdr_info {
+    /// Creates the MAC header information for a transmit command from the header of an Ethernet frame.
+    pub fn new(frame: &[u8]) -> tx_mac_hdr_info {
+        const ADDRESS_LENGTH: usize = NRF_WIFI_ETH_ADDR_LEN as usize;
+
+        let mut dest = [0u8; ADDRESS_LENGTH];
+        let mut src = [0u8; ADDRESS_LENGTH];
+
+        dest.copy_from_slice(&frame[..ADDRESS_LENGTH]);
+        src.copy_from_slice(&frame[ADDRESS_LENGTH..2 * ADDRESS_LENGTH]);
+
+        let eth_type_buffer: [u8; 2] = [frame[2 * ADDRESS_LENGTH], frame[2 * ADDRESS_LENGTH + 1]];
+
+        tx_mac_hdr_info {
+            umac_fill_flags: 0,
+            fc: 0,
+            dest,
+            src,
+            etype: get_type(&eth_type_buffer),
+            tx_flags: 0,
+            more_data: 0,
+            eosp: 0,
+        }
+    }
+}
@@ -3,7 +3,12 @@ use core::mem::transmute;
 use embassy_time::{Duration, Timer};
 use firmware::FirmwareInfo;
 
-use crate::{bindings::*, bus::Bus, util::slice32_mut, Error, PBUS, SR1_RPU_AWAKE, SR1_RPU_READY, SR2_RPU_WAKEUP_REQ};
+use crate::{
+    bindings::*,
+    bus::{bus_error, Bus},
+    util::{meh, slice32_mut, slice8, slice8_mut, sliceit},
+    Error, PBUS, SR1_RPU_AWAKE, SR1_RPU_READY, SR2_RPU_WAKEUP_REQ,
+};
 
 /*
 pktram: 0xB0000000 - 0xB0030FFF -- 196kb
@@ -26,33 +31,23 @@ Each RX buffer has a "descriptor ID" which is assigned across all queues startin
 */
 
 // Configurable by user
-// const MAX_TX_TOKENS: usize = 10;
+pub(crate) const MAX_TX_TOKENS: usize = 10;
 
 const MAX_TX_AGGREGATION: usize = 6;
-// const TX_MAX_DATA_SIZE: usize = 1600;
+pub const TX_MAX_DATA_SIZE: usize = 1600;
 pub const RX_MAX_DATA_SIZE: usize = 1600;
 const RX_BUFS_PER_QUEUE: u16 = 5;
 
 // Fixed
 
-/*
 const TX_BUFS: usize = MAX_TX_TOKENS * MAX_TX_AGGREGATION;
-const TX_BUF_SIZE: usize = TX_BUF_HEADROOM as usize + TX_MAX_DATA_SIZE;
 const TX_TOTAL_SIZE: usize = TX_BUFS * TX_BUF_SIZE;
-*/
+const TX_BUF_SIZE: usize = TX_BUF_HEADROOM as usize + TX_MAX_DATA_SIZE;
 
 pub const RX_BUFS: usize = (RX_BUFS_PER_QUEUE as usize) * (MAX_NUM_OF_RX_QUEUES as usize);
 pub const RX_BUF_SIZE: usize = RX_BUF_HEADROOM as usize + RX_MAX_DATA_SIZE as usize;
 pub const RX_TOTAL_SIZE: usize = RX_BUFS * RX_BUF_SIZE;
 
-//     // assert!(MAX_TX_TOKENS >= 1, "At least one TX token is required");
-//     // assert!(MAX_TX_AGGREGATION <= 16, "Max TX aggregation is 16");
-//     // assert!(RX_BUFS_PER_QUEUE >= 1, "At least one RX buffer per queue is required");
-//     // assert!(
-//     //     (TX_TOTAL_SIZE + RX_TOTAL_SIZE) as u32 <= RPU_PKTRAM_SIZE,
-//     //     "Packet RAM overflow"
-//     // );
-
 // TODO: should be a config with a range
 // const NRF70_RX_NUM_BUFS: u32 = 48;
 // const NRF70_RX_MAX_DATA_SIZE: u32 = 1600;
@@ -109,22 +104,34 @@ pub struct Rpu<BUS: Bus> {
 
     number_of_receive_queues: usize,
     receive_queues: [ReceiveQueue; MAX_NUM_OF_RX_QUEUES as usize],
+
+    /// Bit mask of the transmit tokens (descriptors) currently owned by the RPU
+    transmit_tokens_in_use: u32,
+
+    /// A fragmented event of which not all fragments have been read yet
+    pending_event: Option<PendingEvent>,
 }
 
-impl Default for ReceiveBuffer {
-    fn default() -> Self {
-        ReceiveBuffer {
-            rpu_address: 0,
-            descriptor_identifier: 0,
-            // TODO: maybe this can be 'static and provided by the user?
-            data: [0u8; RX_MAX_DATA_SIZE as usize],
-        }
-    }
+/// Size of the header in front of every event
+const EVENT_HEADER_SIZE: usize = core::mem::size_of::<host_rpu_msg>();
+
+/// Progress of an event which is being read, possibly in several fragments
+struct PendingEvent {
+    header: host_rpu_msg,
+
+    /// Number of bytes received so far, including the header
+    received: usize,
+
+    /// The event does not fit in the buffer, its fragments are only drained from the queue
+    overflow: bool,
 }
 
 struct ReceiveQueue {
     number_of_buffers: usize,
     buffers: [ReceiveBuffer; RX_BUFS_PER_QUEUE as usize],
+
+    /// Bit mask of the buffers currently posted to the RPU, i.e. which it can fill with packets
+    posted: u32,
 }
 
 impl Default for ReceiveQueue {
@@ -132,26 +139,44 @@ impl Default for ReceiveQueue {
         ReceiveQueue {
             number_of_buffers: RX_BUFS_PER_QUEUE as usize,
             buffers: [ReceiveBuffer::default(); RX_BUFS_PER_QUEUE as usize],
+            posted: 0,
         }
     }
 }
 
-/// This buffer is a mapping to the receive buffers on the RPU
-#[derive(Copy, Clone)]
+/// This buffer is a mapping to the receive buffers on the RPU, the packets are read straight from
+/// there with [`Rpu::read_receive_buffer`]
+#[derive(Copy, Clone, Default)]
 struct ReceiveBuffer {
     /// Points to the base of the receive buffer on the RPU
     rpu_address: u32,
 
     /// The descriptor identifier of this buffer
     descriptor_identifier: usize,
-
-    /// This buffer does not include the 4 byte headroom where the descriptor identifier is placed
-    data: [u8; RX_MAX_DATA_SIZE],
 }
 
 #[allow(dead_code)]
 impl<BUS: Bus> Rpu<BUS> {
     pub fn new(bus: BUS) -> Self {
+        // The buffer configuration is validated at compile time
+        const {
+            core::assert!(MAX_TX_TOKENS >= 1, "At least one TX token is required");
+            core::assert!(
+                MAX_TX_TOKENS <= u32::BITS as usize,
+                "The TX tokens are tracked in a 32 bit mask"
+            );
+            core::assert!(MAX_TX_AGGREGATION <= 16, "Max TX aggregation is 16");
+            core::assert!(RX_BUFS_PER_QUEUE >= 1, "At least one RX buffer per queue is required");
+            core::assert!(
+                RX_BUFS_PER_QUEUE as u32 <= u32::BITS,
+                "The posted RX buffers are tracked in a 32 bit mask"
+            );
+            core::assert!(
+                (TX_TOTAL_SIZE + RX_TOTAL_SIZE) as u32 <= RPU_PKTRAM_SIZE,
+                "Packet RAM overflow"
+            );
+        }
+
         Rpu {
             bus,
 
@@ -168,6 +193,10 @@ impl<BUS: Bus> Rpu<BUS> {
                 ReceiveQueue::default(),
                 ReceiveQueue::default(),
             ],
+
+            transmit_tokens_in_use: 0,
+
+            pending_event: None,
         }
     }
 
@@ -177,15 +206,15 @@ impl<BUS: Bus> Rpu<BUS> {
     ) -> Result<(), Error> {
         self.wake_up().await?;
 
-        self.enable_clocks().await;
-        self.enable_interrupts().await;
+        self.enable_clocks().await?;
+        self.enable_interrupts().await?;
 
-        self.reset().await;
+        self.reset().await?;
 
-        self.firmware_load(firmware_info).await;
-        self.firmware_boot().await;
+        self.firmware_load(firmware_info).await?;
+        self.firmware_boot().await?;
 
-        let version = self.firmware_version().await;
+        let version = self.firmware_version().await?;
 
         info!(
             "Firmware for RPU ({}.{}.{}.{}) booted sucessfully",
@@ -202,17 +231,17 @@ impl<BUS: Bus> Rpu<BUS> {
         // TODO: this read is strictly not needed as we extract the UMAC info further down here
         let mut hpqm_info_buffer = [0; size_of::<host_rpu_hpqm_info>()];
         self.read_buffer(RPU_MEM_HPQ_INFO, None, slice32_mut(&mut hpqm_info_buffer))
-            .await;
+            .await?;
 
         self.hostport_queues_info = Some(unsafe { core::mem::transmute_copy(&hpqm_info_buffer) });
 
         // Fetch the addresses for the RX and TX command bases
-        self.rx_command_base_address = Some(self.read_u32(RPU_MEM_RX_CMD_BASE, None).await);
+        self.rx_command_base_address = Some(self.read_u32(RPU_MEM_RX_CMD_BASE, None).await?);
         self.tx_command_base_address = Some(RPU_MEM_TX_CMD_BASE);
 
         // -- Retrieve OTP info ---
-        let umac_info = self.retrieve_umac_info().await;
-        let otp_flags = self.read_u32(RPU_MEM_OTP_INFO_FLAGS, None).await;
+        let umac_info = self.retrieve_umac_info().await?;
+        let otp_flags = self.read_u32(RPU_MEM_OTP_INFO_FLAGS, None).await?;
 
         // -- Retrieve RF parameters ---
 
@@ -229,7 +258,9 @@ impl<BUS: Bus> Rpu<BUS> {
             max_pwr_5g_high_mcs7: 13 * 4,
         };
 
-        let rf_parameters = self.get_rf_parameters(&umac_info, otp_flags, &tx_pwr_ceil_params).await;
+        let rf_parameters = self
+            .get_rf_parameters(&umac_info, otp_flags, &tx_pwr_ceil_params)
+            .await?;
 
         // -- Initialize RX buffers ---
 
@@ -239,25 +270,14 @@ impl<BUS: Bus> Rpu<BUS> {
             self.receive_queues[queue_index].number_of_buffers = RX_BUFS_PER_QUEUE as usize;
 
             for buffer_index in 0..self.receive_queues[queue_index].number_of_buffers {
-                let descriptor_identifier = queue_index * self.number_of_receive_queues + buffer_index as usize;
+                let descriptor_identifier = queue_index * RX_BUFS_PER_QUEUE as usize + buffer_index;
                 let rpu_address = (RPU_MEM_PKT_BASE + RPU_PKTRAM_SIZE - RX_TOTAL_SIZE as u32)
                     + (RX_BUF_SIZE * descriptor_identifier) as u32;
 
                 self.receive_queues[queue_index].buffers[buffer_index].descriptor_identifier = descriptor_identifier;
                 self.receive_queues[queue_index].buffers[buffer_index].rpu_address = rpu_address;
 
-                let command = host_rpu_rx_buf_info {
-                    addr: rpu_address + RX_BUF_HEADROOM,
-                };
-
-                let command_buffer: [u32; 1] = unsafe { transmute(command) };
-
-                // Write RX buffer header
-                self.write_u32(rpu_address, None, descriptor_identifier as u32).await;
-
-                // TODO: does this need to be a function in itself or can it be inlined here?
-                self.send_rx_command(&command_buffer[..], descriptor_identifier as u32, queue_index)
-                    .await?;
+                self.post_receive_buffer(queue_index, buffer_index).await?;
             }
         }
 
@@ -265,71 +285,129 @@ impl<BUS: Bus> Rpu<BUS> {
         self.firmware_initialize(&rf_parameters).await
     }
 
-    pub async fn read_event(
-        &mut self,
-        message_buffer: &mut [u32; (MAX_EVENT_POOL_LEN / 4) as usize],
-    ) -> Result<host_rpu_msg, Error> {
+    /// Reads the next event from the event queue into `message_buffer` and returns its header. The
+    /// message, i.e. what follows the header, is placed at the start of the buffer.
+    ///
+    /// Events larger than [`MAX_EVENT_POOL_LEN`] are split into fragments by the RPU, which each
+    /// occupy an entry in the event queue. These are reassembled in `message_buffer`. If not all
+    /// the fragments are in the queue yet, [`Error::NoData`] is returned and the reassembly resumes
+    /// on the next call. An event which does not fit in `message_buffer` is drained from the queue
+    /// and dropped with [`Error::BufferTooSmall`].
+    pub async fn read_event(&mut self, message_buffer: &mut [u32]) -> Result<host_rpu_msg, Error> {
         let hostport_queues_info = match self.hostport_queues_info {
             Some(hostport_queues_info) => Ok(hostport_queues_info),
             None => Err(Error::NotInitialized),
         }?;
 
-        // -- Is there an event in the queue ? ---
+        loop {
+            // -- Is there an event (fragment) in the queue ? ---
 
-        let event_address = self.hostport_queue_dequeue(hostport_queues_info.event_busy_queue).await;
+            let event_address = self
+                .hostport_queue_dequeue(hostport_queues_info.event_busy_queue)
+                .await?;
 
-        let event_address = match event_address {
-            // No more events to read. Sometimes when low power mode is enabled
-            // we see a wrong address, but it work after a while, so, add a
-            // check for that.
-            None | Some(0xAAAA_AAAA) => return Err(Error::NoData),
-            Some(event_address) => event_address,
-        };
+            let event_address = match event_address {
+                // No more events to read. Sometimes when low power mode is enabled
+                // we see a wrong address, but it work after a while, so, add a
+                // check for that.
+                None | Some(0xAAAA_AAAA) => return Err(Error::NoData),
+                Some(event_address) => event_address,
+            };
 
-        // -- Read out and decode header ---
+            let event = match self.pending_event.take() {
+                Some(event) => {
+                    // -- Continuation of a fragmented event, read the next fragment ---
 
-        const HEADER_SIZE: usize = core::mem::size_of::<host_rpu_msg>();
-        let mut header_buffer = [0; HEADER_SIZE];
+                    let fragment_length =
+                        (event.header.hdr.len as usize - event.received).min(MAX_EVENT_POOL_LEN as usize);
 
-        self.read_buffer(event_address, None, slice32_mut(&mut header_buffer))
-            .await;
+                    self.read_event_fragment(event_address, event, 0, fragment_length, message_buffer)
+                        .await?
+                }
+                None => {
+                    // -- Read out and decode header ---
 
-        let header: host_rpu_msg = unsafe { core::mem::transmute_copy(&header_buffer) };
+                    let mut header_buffer = [0; EVENT_HEADER_SIZE];
 
-        // -- Read out event from queue ---
+                    self.read_buffer(event_address, None, slice32_mut(&mut header_buffer))
+                        .await?;
 
-        let message_length = header.hdr.len as usize;
+                    let header: host_rpu_msg = unsafe { core::mem::transmute_copy(&header_buffer) };
+                    let message_length = header.hdr.len as usize;
 
-        self.read_buffer(
-            event_address + HEADER_SIZE as u32,
-            None,
-            &mut message_buffer[..message_length / 4],
-        )
-        .await;
+                    if message_length < EVENT_HEADER_SIZE {
+                        warn!("Dropping event with invalid length: {}", message_length);
+                        self.free_event(event_address).await?;
+                        continue;
+                    }
 
-        debug!(
-            "Fetched event from address: {:#x}. Length: {}",
-            event_address, message_length
-        );
+                    let event = PendingEvent {
+                        header,
+                        received: EVENT_HEADER_SIZE,
+                        overflow: (message_length - EVENT_HEADER_SIZE).div_ceil(4) > message_buffer.len(),
+                    };
 
-        if header.hdr.resubmit > 0 {
-            self.free_event(event_address).await?;
+                    let fragment_length = message_length.min(MAX_EVENT_POOL_LEN as usize) - EVENT_HEADER_SIZE;
+
+                    self.read_event_fragment(event_address, event, EVENT_HEADER_SIZE, fragment_length, message_buffer)
+                        .await?
+                }
+            };
+
+            debug!(
+                "Fetched event from address: {:#x}. Length: {}, received: {}",
+                event_address,
+                meh(event.header.hdr.len),
+                event.received
+            );
+
+            if event.received < event.header.hdr.len as usize {
+                self.pending_event = Some(event);
+                continue;
+            }
+
+            if event.overflow {
+                warn!(
+                    "Dropping event of {} bytes, larger than the buffer of {} bytes",
+                    meh(event.header.hdr.len),
+                    message_buffer.len() * 4
+                );
+                return Err(Error::BufferTooSmall);
+            }
+
+            return Ok(event.header);
         }
+    }
+
+    /// Reads `length` bytes of a fragment located at `offset` in the event at `event_address` into
+    /// its position in `message_buffer`, and frees the event if the RPU wants it back.
+    async fn read_event_fragment(
+        &mut self,
+        event_address: u32,
+        mut event: PendingEvent,
+        offset: usize,
+        length: usize,
+        message_buffer: &mut [u32],
+    ) -> Result<PendingEvent, Error> {
+        if !event.overflow {
+            // Fragments other than the last one are a multiple of the word size
+            let start = (event.received - EVENT_HEADER_SIZE) / 4;
 
-        // TODO: fix this
-        if message_length > MAX_EVENT_POOL_LEN as usize {
-            todo!("Fragmented event read is not yet implemented");
-        } else if message_length > RPU_EVENT_COMMON_SIZE_MAX as usize {
-            // This is a longer than usual event. We gotta read it again
             self.read_buffer(
-                event_address + HEADER_SIZE as u32,
+                event_address + offset as u32,
                 None,
-                &mut message_buffer[..(message_length + 3) / 4],
+                &mut message_buffer[start..start + length.div_ceil(4)],
             )
-            .await;
+            .await?;
+        }
+
+        event.received += length;
+
+        if event.header.hdr.resubmit > 0 {
+            self.free_event(event_address).await?;
         }
 
-        Ok(header)
+        Ok(event)
     }
 
     fn descriptor_idenitfier_to_indicies(&self, descriptor_identiifer: usize) -> Result<(usize, usize), Error> {
@@ -345,65 +423,234 @@ impl<BUS: Bus> Rpu<BUS> {
         Err(Error::NotFound)
     }
 
-    /// Fetches the receive buffer for the given descriptor and updates the local copy.
-    pub async fn update_cached_receive_buffer(
+    /// Reads the bytes at `offset` in the packet of the receive buffer for the given descriptor,
+    /// filling `buffer`. The RPU memory is read straight into `buffer`, which is then shifted by a
+    /// few bytes to account for the word alignment of both.
+    pub async fn read_receive_buffer(
         &mut self,
         descriptor_identifier: usize,
-        size: usize,
+        offset: usize,
+        buffer: &mut [u8],
     ) -> Result<(), Error> {
         let (queue_index, buffer_index) = self.descriptor_idenitfier_to_indicies(descriptor_identifier)?;
 
-        let mut data = [0u8; RX_MAX_DATA_SIZE];
+        if offset + buffer.len() > RX_MAX_DATA_SIZE {
+            return Err(Error::BufferOverflow);
+        }
 
-        let buffer = slice32_mut(&mut data);
+        let length = buffer.len();
+        let skip = offset % 4;
+        let address = self.receive_queues[queue_index].buffers[buffer_index].rpu_address
+            + RX_BUF_HEADROOM
+            + (offset - skip) as u32;
+        let words_needed = (skip + length).div_ceil(4);
 
-        self.read_buffer(
-            self.receive_queues[queue_index].buffers[buffer_index].rpu_address + RX_BUF_HEADROOM,
-            None,
-            &mut buffer[..(size + 3) / 4],
-        )
-        .await;
+        // The words which fit in the aligned part of the buffer are read in place
+        let (head, words, _) = unsafe { buffer.align_to_mut::<u32>() };
+        let head = head.len();
+        let words_in_place = words_needed.min(words.len());
+
+        self.read_buffer(address, None, &mut words[..words_in_place]).await?;
+
+        let mut read = words_in_place * 4;
+        let mut copied = 0;
+
+        if read > 0 {
+            buffer.copy_within(head + skip..head + read, 0);
+            copied = read - skip;
+        }
+
+        // The rest, which is at most a few words, goes through a small chunk
+        while copied < length {
+            let mut chunk = [0u32; 4];
+            let chunk_words = (skip + length - read).div_ceil(4).min(chunk.len());
+
+            self.read_buffer(address + read as u32, None, &mut chunk[..chunk_words])
+                .await?;
+
+            let chunk = &slice8(&chunk)[skip.saturating_sub(read)..chunk_words * 4];
+            let count = chunk.len().min(length - copied);
+
+            buffer[copied..copied + count].copy_from_slice(&chunk[..count]);
+
+            copied += count;
+            read += chunk_words * 4;
+        }
+
+        Ok(())
+    }
+
+    /// Takes the receive buffer of a descriptor from the RPU, which has filled it with a packet.
+    pub fn take_receive_buffer(&mut self, descriptor_identifier: usize) -> Result<(), Error> {
+        let (queue_index, buffer_index) = self.descriptor_idenitfier_to_indicies(descriptor_identifier)?;
 
-        self.receive_queues[queue_index].buffers[buffer_index]
-            .data
-            .copy_from_slice(&data);
+        // The RPU is done with the buffer until it is recycled
+        self.receive_queues[queue_index].posted &= !(1 << buffer_index);
 
         Ok(())
     }
 
-    pub fn get_cached_receive_buffer_slice(&mut self, descriptor_identifier: usize) -> Result<&mut [u8], Error> {
+    /// Hands the receive buffer of a descriptor back to the RPU once its packet has been read out.
+    /// The RPU only has [`RX_BUFS_PER_QUEUE`] buffers in each queue, so every received packet
+    /// has to be recycled for receiving to go on.
+    pub async fn recycle_receive_buffer(&mut self, descriptor_identifier: usize) -> Result<(), Error> {
         let (queue_index, buffer_index) = self.descriptor_idenitfier_to_indicies(descriptor_identifier)?;
 
-        Ok(&mut self.receive_queues[queue_index].buffers[buffer_index].data)
+        if self.receive_queues[queue_index].posted & (1 << buffer_index) != 0 {
+            warn!(
+                "RX buffer for descriptor {} is already posted to the RPU",
+                descriptor_identifier
+            );
+            return Ok(());
+        }
+
+        self.post_receive_buffer(queue_index, buffer_index).await
+    }
+
+    /// Posts a receive buffer to the RX queue of the RPU, which it then fills with a packet.
+    async fn post_receive_buffer(&mut self, queue_index: usize, buffer_index: usize) -> Result<(), Error> {
+        let rpu_address = self.receive_queues[queue_index].buffers[buffer_index].rpu_address;
+        let descriptor_identifier = self.receive_queues[queue_index].buffers[buffer_index].descriptor_identifier;
+
+        let command = host_rpu_rx_buf_info {
+            addr: rpu_address + RX_BUF_HEADROOM,
+        };
+
+        let command_buffer: [u32; 1] = unsafe { transmute(command) };
+
+        // Write RX buffer header
+        self.write_u32(rpu_address, None, descriptor_identifier as u32).await?;
+
+        self.send_rx_command(&command_buffer[..], descriptor_identifier as u32, queue_index)
+            .await?;
+
+        self.receive_queues[queue_index].posted |= 1 << buffer_index;
+
+        Ok(())
     }
 
-    pub async fn irq_ack(&mut self) {
+    /// Whether a transmit token is free, i.e. if [`Rpu::transmit`] can be called without it failing
+    /// with [`Error::Busy`].
+    pub fn transmit_token_available(&self) -> bool {
+        self.transmit_tokens_in_use.count_ones() < MAX_TX_TOKENS as u32
+    }
+
+    /// Returns the token of a transmit descriptor to the pool. Called when the RPU signals that it
+    /// is done with the descriptor (`NRF_WIFI_CMD_TX_BUFF_DONE`).
+    pub fn transmit_done(&mut self, descriptor_identifier: usize) -> Result<(), Error> {
+        if descriptor_identifier >= MAX_TX_TOKENS {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mask = 1 << descriptor_identifier;
+
+        if self.transmit_tokens_in_use & mask == 0 {
+            warn!("TX done for descriptor {} which was not in use", descriptor_identifier);
+        }
+
+        self.transmit_tokens_in_use &= !mask;
+
+        Ok(())
+    }
+
+    /// Places an Ethernet frame in the transmit buffer of a free descriptor (token) and posts a
+    /// transmit command for it to the RPU. The token is held until [`Rpu::transmit_done`] is
+    /// called for it.
+    ///
+    /// The RPU converts the Ethernet frame to a 802.11 frame itself, it only needs the Ethernet
+    /// header information (destination, source and type) in the command.
+    pub async fn transmit(&mut self, frame: &[u8]) -> Result<(), Error> {
+        const ETH_HEADER_SIZE: usize = NRF_WIFI_FMAC_ETH_HDR_LEN as usize;
+        const INFO_SIZE: usize = size_of::<nrf_wifi_tx_buff_info>();
+
+        if frame.len() < ETH_HEADER_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+
+        if frame.len() > TX_MAX_DATA_SIZE {
+            return Err(Error::BufferTooSmall);
+        }
+
+        if !self.transmit_token_available() {
+            return Err(Error::Busy);
+        }
+
+        let descriptor_identifier = self.transmit_tokens_in_use.trailing_ones() as usize;
+
+        // The first buffer of the descriptor is used, the rest are reserved for aggregation
+        let rpu_address = RPU_MEM_PKT_BASE + (descriptor_identifier * MAX_TX_AGGREGATION * TX_BUF_SIZE) as u32;
+
+        // -- Write the frame to the transmit buffer, after the head room ---
+
+        let mut data = [0u32; TX_MAX_DATA_SIZE / 4];
+        slice8_mut(&mut data)[..frame.len()].copy_from_slice(frame);
+
+        self.write_buffer(rpu_address + TX_BUF_HEADROOM, None, &data[..(frame.len() + 3) / 4])
+            .await?;
+
+        // -- Prepare and send the command ---
+
+        let command_length = size_of::<nrf_wifi_tx_buff>() + INFO_SIZE;
+
+        let command = nrf_wifi_tx_buff {
+            umac_head: nrf_wifi_umac_head {
+                cmd: nrf_wifi_umac_data_commands::NRF_WIFI_CMD_TX_BUFF as u32,
+                len: command_length as u32,
+            },
+            wdev_id: 0,
+            tx_desc_num: descriptor_identifier as u8,
+            mac_hdr_info: tx_mac_hdr_info::new(frame),
+            pending_buf_size: 0,
+            num_tx_pkts: 1,
+            tx_buff_info: __IncompleteArrayField::new(),
+        };
+
+        let info = nrf_wifi_tx_buff_info {
+            pkt_length: frame.len() as u16,
+            ddr_ptr: rpu_address + TX_BUF_HEADROOM,
+        };
+
+        let mut command_buffer = [0u32; (RPU_DATA_CMD_SIZE_MAX_TX / 4) as usize];
+        let command_buffer_u8 = slice8_mut(&mut command_buffer);
+
+        command_buffer_u8[..size_of::<nrf_wifi_tx_buff>()].copy_from_slice(sliceit(&command));
+        command_buffer_u8[size_of::<nrf_wifi_tx_buff>()..command_length].copy_from_slice(sliceit(&info));
+
+        self.send_tx_command(&command_buffer[..(command_length + 3) / 4], descriptor_identifier)
+            .await?;
+
+        self.transmit_tokens_in_use |= 1 << descriptor_identifier;
+
+        Ok(())
+    }
+
+    pub async fn irq_ack(&mut self) -> Result<(), Error> {
         // TODO: I think this clears the interrupt flag
         self.write_u32(RPU_REG_INT_FROM_MCU_ACK, None, 1 << RPU_REG_BIT_INT_FROM_MCU_ACK)
-            .await;
+            .await
     }
 
     /// Checks if the watchdog was the source of the interrupt
-    pub async fn irq_watchdog_check(&mut self) -> bool {
-        let val = self.read_u32(RPU_REG_MIPS_MCU_UCCP_INT_STATUS, None).await;
-        (val & (1 << RPU_REG_BIT_MIPS_WATCHDOG_INT_STATUS)) > 0
+    pub async fn irq_watchdog_check(&mut self) -> Result<bool, Error> {
+        let val = self.read_u32(RPU_REG_MIPS_MCU_UCCP_INT_STATUS, None).await?;
+        Ok((val & (1 << RPU_REG_BIT_MIPS_WATCHDOG_INT_STATUS)) > 0)
     }
 
-    pub async fn irq_watchdog_ack(&mut self) {
+    pub async fn irq_watchdog_ack(&mut self) -> Result<(), Error> {
         self.write_u32(
             RPU_REG_MIPS_MCU_UCCP_INT_CLEAR,
             None,
             1 << RPU_REG_BIT_MIPS_WATCHDOG_INT_CLEAR,
         )
-        .await;
+        .await
     }
 
-    pub async fn retrieve_umac_info(&mut self) -> host_rpu_umac_info {
+    pub async fn retrieve_umac_info(&mut self) -> Result<host_rpu_umac_info, Error> {
         let mut umac_info_buffer = [0u8; size_of::<host_rpu_umac_info>()];
         self.read_buffer(RPU_MEM_UMAC_BOOT_SIG, None, slice32_mut(&mut umac_info_buffer))
-            .await;
+            .await?;
 
-        unsafe { core::mem::transmute_copy(&umac_info_buffer) }
+        Ok(unsafe { core::mem::transmute_copy(&umac_info_buffer) })
     }
 }
 
@@ -412,20 +659,20 @@ impl<BUS: Bus> Rpu<BUS> {
     async fn wake_up(&mut self) -> Result<(), Error> {
         debug!("Waking up...");
 
-        self.bus.write_sr2(SR2_RPU_WAKEUP_REQ).await;
+        self.bus.write_sr2(SR2_RPU_WAKEUP_REQ).await.map_err(bus_error)?;
 
         self.wait_for_wakeup_request_ack().await?;
 
         self.wait_until_awake().await
     }
 
-    async fn sleep(&mut self) {
+    async fn sleep(&mut self) -> Result<(), Error> {
         debug!("Sleeping...");
 
-        self.bus.write_sr2(0).await;
+        self.bus.write_sr2(0).await.map_err(bus_error)
     }
 
-    async fn reset(&mut self) {
+    async fn reset(&mut self) -> Result<(), Error> {
         let processors = [ProcessorType::Lmac, ProcessorType::Umac];
 
         for processor in processors {
@@ -435,10 +682,10 @@ impl<BUS: Bus> Rpu<BUS> {
             };
 
             // Do pulsed soft reset
-            self.write_u32(control_register_address, Some(processor), 0x1).await;
+            self.write_u32(control_register_address, Some(processor), 0x1).await?;
 
             // Wait for it to come out of reset
-            while self.read_u32(control_register_address, Some(processor)).await & 0x1 != 0 {}
+            while self.read_u32(control_register_address, Some(processor)).await? & 0x1 != 0 {}
 
             // MIPS will restart from its boot exception registers and hit its default wait instruction
             let boot_exception_register_address = match processor {
@@ -446,43 +693,60 @@ impl<BUS: Bus> Rpu<BUS> {
                 ProcessorType::Umac => 0xA400_0118,
             };
 
-            while self.read_u32(boot_exception_register_address, Some(processor)).await & 0x01 != 1 {}
+            while self.read_u32(boot_exception_register_address, Some(processor)).await? & 0x01 != 1 {}
         }
+
+        Ok(())
     }
 
-    async fn enable_clocks(&mut self) {
+    async fn enable_clocks(&mut self) -> Result<(), Error> {
         debug!("Enabling clocks...");
-        self.write_u32_to_region(PBUS, 0x8C20, 0x0100).await;
+        self.write_u32_to_region(PBUS, 0x8C20, 0x0100).await
     }
 
-    async fn enable_interrupts(&mut self) {
+    async fn enable_interrupts(&mut self) -> Result<(), Error> {
         debug!("Enabling interrupts...");
 
         // First enable the block-wise interrupt for the relevant block in the master register
-        let mut value = self.read_u32(RPU_REG_INT_FROM_RPU_CTRL, None).await;
+        let mut value = self.read_u32(RPU_REG_INT_FROM_RPU_CTRL, None).await?;
 
         value |= 1 << RPU_REG_BIT_INT_FROM_RPU_CTRL;
 
-        self.write_u32(RPU_REG_INT_FROM_RPU_CTRL, None, value).await;
+        self.write_u32(RPU_REG_INT_FROM_RPU_CTRL, None, value).await?;
 
         // Now enable the relevant MCU interrupt line
         self.write_u32(RPU_REG_INT_FROM_MCU_CTRL, None, 1 << RPU_REG_BIT_INT_FROM_MCU_CTRL)
-            .await;
+            .await
     }
 
-    async fn disable_interrupts(&mut self) {
+    async fn disable_interrupts(&mut self) -> Result<(), Error> {
         debug!("Disabling interrupts...");
 
-        let mut value = self.read_u32(RPU_REG_INT_FROM_RPU_CTRL, None).await;
+        let mut value = self.read_u32(RPU_REG_INT_FROM_RPU_CTRL, None).await?;
         value &= !(1 << RPU_REG_BIT_INT_FROM_RPU_CTRL);
 
-        self.write_u32(RPU_REG_INT_FROM_RPU_CTRL, None, value).await;
+        self.write_u32(RPU_REG_INT_FROM_RPU_CTRL, None, value).await?;
 
         self.write_u32(RPU_REG_INT_FROM_MCU_CTRL, None, !(1 << RPU_REG_BIT_INT_FROM_MCU_CTRL))
-            .await;
+            .await
     }
 
-    async fn write_core(&mut self, core_address: u32, buf: &[u32], processor: ProcessorType) {
+    /// Writes to the memory of a processor core. Memory which is mapped into the address space of
+    /// the bus (the retained and scratch RAM) is written in a single burst, where the bus
+    /// auto-increments the address. Anything else goes word by word through the indirect access
+    /// registers of the core.
+    async fn write_core(&mut self, core_address: u32, buf: &[u32], processor: ProcessorType) -> Result<(), Error> {
+        const CORE_MEMORY_BASE: u32 = 0x8000_0000;
+
+        let rpu_address = CORE_MEMORY_BASE | (core_address & RPU_ADDR_MASK_OFFSET);
+        let length = (buf.len() * 4) as u32;
+
+        if let Some((memory_region, offset)) = memory::regions::find_region_and_offset(rpu_address, Some(processor)) {
+            if rpu_address + length - 1 <= memory_region.rpu_mem_end {
+                return self.write_buffer_to_region(memory_region, offset, buf).await;
+            }
+        }
+
         // We receive the address as a byte address, while we need to write it as a word address
         let addr = (core_address & RPU_ADDR_MASK_OFFSET) / 4;
 
@@ -495,12 +759,14 @@ impl<BUS: Bus> Rpu<BUS> {
         };
 
         // Write the processor address register
-        self.write_u32(addr_reg, Some(processor), addr).await;
+        self.write_u32(addr_reg, Some(processor), addr).await?;
 
-        // Write to the data register one by one
+        // Write to the data register one by one, which auto-increments the address
         for data in buf {
-            self.write_u32(data_reg, Some(processor), *data).await;
+            self.write_u32(data_reg, Some(processor), *data).await?;
         }
+
+        Ok(())
     }
 
     async fn free_event(&mut self, event_address: u32) -> Result<(), Error> {
@@ -510,14 +776,12 @@ impl<BUS: Bus> Rpu<BUS> {
         }?;
 
         self.hostport_queue_enqueue(hostport_queues_info.event_avl_queue, event_address)
-            .await;
-
-        Ok(())
+            .await
     }
 
     async fn wait_for_wakeup_request_ack(&mut self) -> Result<(), Error> {
         for _ in 0..10 {
-            if self.bus.read_sr2().await == SR2_RPU_WAKEUP_REQ {
+            if self.bus.read_sr2().await.map_err(bus_error)? == SR2_RPU_WAKEUP_REQ {
                 return Ok(());
             }
             Timer::after(Duration::from_millis(1)).await;
@@ -528,7 +792,7 @@ impl<BUS: Bus> Rpu<BUS> {
 
     async fn wait_until_awake(&mut self) -> Result<(), Error> {
         for _ in 0..10 {
-            if self.bus.read_sr1().await & SR1_RPU_AWAKE != 0 {
+            if self.bus.read_sr1().await.map_err(bus_error)? & SR1_RPU_AWAKE != 0 {
                 return Ok(());
             }
             Timer::after(Duration::from_millis(1)).await;
@@ -539,7 +803,7 @@ impl<BUS: Bus> Rpu<BUS> {
 
     async fn wait_until_ready(&mut self) -> Result<(), Error> {
         for _ in 0..10 {
-            if self.bus.read_sr1().await == SR1_RPU_AWAKE | SR1_RPU_READY {
+            if self.bus.read_sr1().await.map_err(bus_error)? == SR1_RPU_AWAKE | SR1_RPU_READY {
                 return Ok(());
             }
             Timer::after(Duration::from_millis(1)).await;
@@ -548,23 +812,23 @@ impl<BUS: Bus> Rpu<BUS> {
         Err(Error::Timeout)
     }
 
-    async fn get_sleep_status(&mut self) -> u8 {
-        self.bus.read_sr1().await
+    async fn get_sleep_status(&mut self) -> Result<u8, Error> {
+        self.bus.read_sr1().await.map_err(bus_error)
     }
 
-    async fn hostport_queue_enqueue(&mut self, hostport_queue: host_rpu_hpq, value: u32) {
-        self.write_u32(hostport_queue.enqueue_addr, None, value).await;
+    async fn hostport_queue_enqueue(&mut self, hostport_queue: host_rpu_hpq, value: u32) -> Result<(), Error> {
+        self.write_u32(hostport_queue.enqueue_addr, None, value).await
     }
 
-    async fn hostport_queue_dequeue(&mut self, hostport_queue: host_rpu_hpq) -> Option<u32> {
-        let value = self.read_u32(hostport_queue.dequeue_addr, None).await;
+    async fn hostport_queue_dequeue(&mut self, hostport_queue: host_rpu_hpq) -> Result<Option<u32>, Error> {
+        let value = self.read_u32(hostport_queue.dequeue_addr, None).await?;
 
         // Pop element only if it is valid
         if value != 0 {
-            self.write_u32(hostport_queue.dequeue_addr, None, value).await;
-            Some(value)
+            self.write_u32(hostport_queue.dequeue_addr, None, value).await?;
+            Ok(Some(value))
         } else {
-            None
+            Ok(None)
         }
     }
 }
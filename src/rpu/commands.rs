@@ -1,19 +1,23 @@
-use core::mem::{size_of, zeroed};
+use core::mem::{offset_of, size_of, zeroed};
 
 use embassy_time::{Duration, Timer};
 
 use crate::{
     bindings::{
         host_rpu_msg, host_rpu_msg_hdr, nrf_wifi_cmd_get_stats, nrf_wifi_cmd_get_wiphy, nrf_wifi_cmd_sys_deinit,
-        nrf_wifi_cmd_sys_init, nrf_wifi_host_rpu_msg_type, nrf_wifi_ie, nrf_wifi_index_ids, nrf_wifi_scan_params,
-        nrf_wifi_sys_commands, nrf_wifi_sys_head, nrf_wifi_umac_chg_vif_state_info, nrf_wifi_umac_cmd_abort_scan,
-        nrf_wifi_umac_cmd_add_vif, nrf_wifi_umac_cmd_change_macaddr, nrf_wifi_umac_cmd_chg_sta,
-        nrf_wifi_umac_cmd_chg_vif_state, nrf_wifi_umac_cmd_get_scan_results, nrf_wifi_umac_cmd_key,
-        nrf_wifi_umac_cmd_mcast_filter, nrf_wifi_umac_cmd_mgmt_frame_reg, nrf_wifi_umac_cmd_scan,
-        nrf_wifi_umac_cmd_set_power_save, nrf_wifi_umac_commands, nrf_wifi_umac_hdr, nrf_wifi_umac_scan_info,
-        rpu_stats_type, scan_reason, MAX_NRF_WIFI_UMAC_CMD_SIZE, NRF_WIFI_HAL_MSG_TYPE,
-        NRF_WIFI_INDEX_IDS_WDEV_ID_VALID, RPU_ADDR_MASK_OFFSET, RPU_DATA_CMD_SIZE_MAX_RX, RPU_MCU_CORE_INDIRECT_BASE,
-        RPU_REG_INT_TO_MCU_CTRL,
+        nrf_wifi_cmd_sys_init, nrf_wifi_host_rpu_msg_type, nrf_wifi_ie, nrf_wifi_index_ids, nrf_wifi_key_type,
+        nrf_wifi_sae, nrf_wifi_scan_params, nrf_wifi_ssid, nrf_wifi_sys_commands, nrf_wifi_sys_head,
+        nrf_wifi_umac_chg_vif_state_info, nrf_wifi_umac_cmd_abort_scan, nrf_wifi_umac_cmd_add_vif,
+        nrf_wifi_umac_cmd_assoc, nrf_wifi_umac_cmd_auth, nrf_wifi_umac_cmd_change_macaddr, nrf_wifi_umac_cmd_chg_sta,
+        nrf_wifi_umac_cmd_chg_vif_state, nrf_wifi_umac_cmd_disconn, nrf_wifi_umac_cmd_get_scan_results,
+        nrf_wifi_umac_cmd_key, nrf_wifi_umac_cmd_mcast_filter, nrf_wifi_umac_cmd_mgmt_frame_reg,
+        nrf_wifi_umac_cmd_mgmt_tx, nrf_wifi_umac_cmd_scan, nrf_wifi_umac_cmd_set_key, nrf_wifi_umac_cmd_set_power_save,
+        nrf_wifi_umac_commands, nrf_wifi_umac_hdr, nrf_wifi_umac_key_info, nrf_wifi_umac_scan_info, rpu_stats_type,
+        scan_reason, MAX_NRF_WIFI_UMAC_CMD_SIZE, NRF_WIFI_CIPHER_SUITE_VALID, NRF_WIFI_CMD_KEY_MAC_ADDR_VALID,
+        NRF_WIFI_FMAC_CIPHER_SUITE_CCMP, NRF_WIFI_HAL_MSG_TYPE, NRF_WIFI_INDEX_IDS_WDEV_ID_VALID,
+        NRF_WIFI_KEY_IDX_VALID, NRF_WIFI_KEY_TYPE_VALID, NRF_WIFI_KEY_VALID, NRF_WIFI_MAX_IE_LEN,
+        NRF_WIFI_MAX_SSID_LEN, NRF_WIFI_SCAN_MAX_NUM_FREQUENCIES, NRF_WIFI_SEQ_VALID, RPU_ADDR_MASK_OFFSET,
+        RPU_DATA_CMD_SIZE_MAX_RX, RPU_DATA_CMD_SIZE_MAX_TX, RPU_MCU_CORE_INDIRECT_BASE, RPU_REG_INT_TO_MCU_CTRL,
     },
     bus::Bus,
     rpu::{Error, ProcessorType},
@@ -168,6 +172,42 @@ impl Default for nrf_wifi_umac_cmd_scan {
     }
 }
 
+/// A scan command followed by the center frequencies of the channels to scan, which the bindings
+/// only declare as a flexible array member.
+#[repr(C, packed)]
+pub struct ScanCommand {
+    command: nrf_wifi_umac_cmd_scan,
+    center_frequencies: [u32; NRF_WIFI_SCAN_MAX_NUM_FREQUENCIES as usize],
+}
+
+impl ScanCommand {
+    /// Appends the frequencies in MHz to the command and sets their number. Anything beyond the
+    /// maximum of 64 frequencies is truncated.
+    pub fn new(mut command: nrf_wifi_umac_cmd_scan, frequencies: &[u32]) -> Self {
+        let mut center_frequencies = [0; NRF_WIFI_SCAN_MAX_NUM_FREQUENCIES as usize];
+        let length = frequencies.len().min(center_frequencies.len());
+        center_frequencies[..length].copy_from_slice(&frequencies[..length]);
+
+        command.info.scan_params.num_scan_channels = length as u16;
+        command.prepare();
+
+        Self {
+            command,
+            center_frequencies,
+        }
+    }
+
+    pub fn domain(&self) -> nrf_wifi_host_rpu_msg_type {
+        nrf_wifi_umac_cmd_scan::MESSAGE_TYPE
+    }
+
+    /// The command with the frequencies in use.
+    pub fn as_bytes(&self) -> &[u8] {
+        let channels = usize::from(self.command.info.scan_params.num_scan_channels);
+        &sliceit(self)[..size_of::<nrf_wifi_umac_cmd_scan>() + channels * 4]
+    }
+}
+
 impl_cmd!(
     umac,
     nrf_wifi_umac_cmd_abort_scan,
@@ -201,6 +241,113 @@ impl Default for nrf_wifi_umac_cmd_get_scan_results {
     }
 }
 
+impl_cmd!(
+    umac,
+    nrf_wifi_umac_cmd_auth,
+    nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_AUTHENTICATE
+);
+
+impl Default for nrf_wifi_umac_cmd_auth {
+    fn default() -> Self {
+        let mut cmd = nrf_wifi_umac_cmd_auth {
+            umac_hdr: nrf_wifi_umac_hdr::default(),
+            valid_fields: 0,
+            info: unsafe { zeroed() },
+        };
+        cmd.prepare();
+        cmd
+    }
+}
+
+impl_cmd!(
+    umac,
+    nrf_wifi_umac_cmd_assoc,
+    nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_ASSOCIATE
+);
+
+impl Default for nrf_wifi_umac_cmd_assoc {
+    fn default() -> Self {
+        let mut cmd = nrf_wifi_umac_cmd_assoc {
+            umac_hdr: nrf_wifi_umac_hdr::default(),
+            valid_fields: 0,
+            connect_common_info: unsafe { zeroed() },
+            mac_addr: [0; 6],
+        };
+        cmd.prepare();
+        cmd
+    }
+}
+
+impl_cmd!(
+    umac,
+    nrf_wifi_umac_cmd_disconn,
+    nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_DEAUTHENTICATE
+);
+
+impl Default for nrf_wifi_umac_cmd_disconn {
+    fn default() -> Self {
+        let mut cmd = nrf_wifi_umac_cmd_disconn {
+            umac_hdr: nrf_wifi_umac_hdr::default(),
+            valid_fields: 0,
+            info: unsafe { zeroed() },
+        };
+        cmd.prepare();
+        cmd
+    }
+}
+
+impl nrf_wifi_ssid {
+    /// Creates a SSID, anything beyond the maximum length of 32 bytes is truncated.
+    pub fn new(ssid: &[u8]) -> Self {
+        let length = ssid.len().min(NRF_WIFI_MAX_SSID_LEN as usize);
+
+        let mut nrf_wifi_ssid = [0; NRF_WIFI_MAX_SSID_LEN as usize];
+        nrf_wifi_ssid[..length].copy_from_slice(&ssid[..length]);
+
+        Self {
+            nrf_wifi_ssid_len: length as u8,
+            nrf_wifi_ssid,
+        }
+    }
+}
+
+impl nrf_wifi_ie {
+    /// Creates an information element buffer, anything beyond the maximum length of 400 bytes is
+    /// truncated.
+    pub fn new(ies: &[u8]) -> Self {
+        let length = ies.len().min(NRF_WIFI_MAX_IE_LEN as usize);
+
+        let mut ie = [0; NRF_WIFI_MAX_IE_LEN as usize];
+        for (destination, source) in ie.iter_mut().zip(&ies[..length]) {
+            *destination = *source as i8;
+        }
+
+        Self {
+            ie_len: length as u16,
+            ie,
+        }
+    }
+}
+
+impl nrf_wifi_sae {
+    /// Creates the SAE data of an authentication command: the transaction sequence number, the
+    /// status code and the body of the authentication frame. Anything beyond the maximum length of
+    /// 256 bytes is truncated.
+    pub fn new(transaction: u16, status: u16, body: &[u8]) -> Self {
+        let mut sae_data = [0; 256];
+        sae_data[..2].copy_from_slice(&transaction.to_le_bytes());
+        sae_data[2..4].copy_from_slice(&status.to_le_bytes());
+
+        let length = body.len().min(sae_data.len() - 4);
+        sae_data[4..4 + length].copy_from_slice(&body[..length]);
+
+        Self {
+            sae_data_len: (4 + length) as i32,
+            sae_data,
+        }
+    }
+}
+
 impl_cmd!(
     umac,
     nrf_wifi_umac_cmd_set_power_save,
@@ -219,6 +366,31 @@ impl_cmd!(
     nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_REGISTER_FRAME
 );
 
+impl_cmd!(
+    umac,
+    nrf_wifi_umac_cmd_mgmt_tx,
+    nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_FRAME
+);
+
+impl nrf_wifi_umac_cmd_mgmt_tx {
+    /// Creates a command transmitting a management frame on the current channel. Anything beyond
+    /// the maximum frame length of 400 bytes is truncated.
+    pub fn new(frame: &[u8], host_cookie: u64) -> Self {
+        let mut cmd: Self = unsafe { zeroed() };
+        cmd.umac_hdr = nrf_wifi_umac_hdr::default();
+
+        let length = frame.len().min(cmd.info.frame.frame.len());
+        for (destination, source) in cmd.info.frame.frame.iter_mut().zip(&frame[..length]) {
+            *destination = *source as i8;
+        }
+
+        cmd.info.frame.frame_len = length as i32;
+        cmd.info.host_cookie = host_cookie;
+
+        cmd
+    }
+}
+
 impl_cmd!(
     umac,
     nrf_wifi_cmd_get_wiphy,
@@ -231,20 +403,96 @@ impl_cmd!(
     nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_DEL_KEY
 );
 
+impl nrf_wifi_umac_cmd_key {
+    /// Creates a `NRF_WIFI_UMAC_CMD_NEW_KEY` command, which shares its structure with the delete
+    /// key command. It has to be sent with [`Rpu::send_command_raw`] as preparing it turns it back
+    /// into a delete key command.
+    ///
+    /// Pairwise keys are given the address of the peer, group keys no address.
+    pub fn new_key(key_info: nrf_wifi_umac_key_info, mac_addr: Option<[u8; 6]>) -> Self {
+        let mut cmd = nrf_wifi_umac_cmd_key {
+            umac_hdr: nrf_wifi_umac_hdr::default(),
+            valid_fields: 0,
+            key_info,
+            mac_addr: mac_addr.unwrap_or_default(),
+        };
+
+        cmd.umac_hdr.cmd_evnt = nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_NEW_KEY as _;
+
+        if mac_addr.is_some() {
+            cmd.valid_fields = NRF_WIFI_CMD_KEY_MAC_ADDR_VALID;
+        }
+
+        cmd
+    }
+}
+
+impl_cmd!(
+    umac,
+    nrf_wifi_umac_cmd_set_key,
+    nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_SET_KEY
+);
+
+/// The BIP-CMAC-128 cipher suite (00-0F-AC:6), which the bindings have no constant for.
+const CIPHER_SUITE_AES_CMAC: u32 = 0x000f_ac06;
+
+impl nrf_wifi_umac_key_info {
+    /// Creates the information of a CCMP key. `rsc` is the receive sequence counter to start from,
+    /// 6 bytes little endian.
+    pub fn ccmp(key: &[u8], key_idx: u8, key_type: nrf_wifi_key_type, rsc: Option<[u8; 6]>) -> Self {
+        Self::new(NRF_WIFI_FMAC_CIPHER_SUITE_CCMP, key, key_idx, key_type, rsc)
+    }
+
+    /// Creates the information of a BIP-CMAC-128 integrity group key. `ipn` is the packet number
+    /// to start from, 6 bytes little endian.
+    pub fn aes_cmac(key: &[u8], key_idx: u8, ipn: [u8; 6]) -> Self {
+        Self::new(
+            CIPHER_SUITE_AES_CMAC,
+            key,
+            key_idx,
+            nrf_wifi_key_type::NRF_WIFI_KEYTYPE_GROUP,
+            Some(ipn),
+        )
+    }
+
+    fn new(cipher_suite: u32, key: &[u8], key_idx: u8, key_type: nrf_wifi_key_type, rsc: Option<[u8; 6]>) -> Self {
+        let mut info: Self = unsafe { zeroed() };
+
+        info.valid_fields =
+            NRF_WIFI_KEY_VALID | NRF_WIFI_KEY_IDX_VALID | NRF_WIFI_KEY_TYPE_VALID | NRF_WIFI_CIPHER_SUITE_VALID;
+        info.cipher_suite = cipher_suite;
+        info.key_type = key_type as i32;
+        info.key_idx = key_idx;
+        info.key.nrf_wifi_key_len = key.len() as u32;
+        info.key.nrf_wifi_key[..key.len()].copy_from_slice(key);
+
+        if let Some(rsc) = rsc {
+            info.valid_fields |= NRF_WIFI_SEQ_VALID;
+            info.seq.nrf_wifi_seq_len = rsc.len() as i32;
+            info.seq.nrf_wifi_seq[..rsc.len()].copy_from_slice(&rsc);
+        }
+
+        info
+    }
+}
+
 impl_cmd!(
     umac,
     nrf_wifi_umac_cmd_chg_sta,
     nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_SET_STATION
 );
 
-// TODO: this is a wild guess.
-const MAX_CMD_SIZE: usize = 1024;
+// TODO: this is a wild guess. Has to fit the authenticate command, which is the largest one we send.
+const MAX_CMD_SIZE: usize = 2048;
 
 impl<BUS: Bus> Rpu<BUS> {
+    /// Sends a prepared command. UMAC commands get `sequence` in their header, by which the events
+    /// for them can be told apart.
     pub(crate) async fn send_command_raw(
         &mut self,
         domain: nrf_wifi_host_rpu_msg_type,
         buffer: *const [u8],
+        sequence: u32,
     ) -> Result<(), Error> {
         let mut buf = [0u32; MAX_CMD_SIZE / 4];
         let buf8 = slice8_mut(&mut buf);
@@ -284,6 +532,13 @@ impl<BUS: Bus> Rpu<BUS> {
         let src: &[u8] = unsafe { &*buffer };
         buf8[cmd_bytes.len()..(cmd_bytes.len() + buffer.len())].copy_from_slice(src);
 
+        if domain == nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_UMAC
+            && buffer.len() >= size_of::<nrf_wifi_umac_hdr>()
+        {
+            let offset = cmd_bytes.len() + offset_of!(nrf_wifi_umac_hdr, seq);
+            buf8[offset..offset + 4].copy_from_slice(&sequence.to_le_bytes());
+        }
+
         let total_length = buffer.len() + cmd_bytes.len();
 
         match embassy_time::with_timeout(
@@ -360,7 +615,7 @@ impl<BUS: Bus> Rpu<BUS> {
                 /* Check if any command pointers are available to post a message */
                 let is_empty = self
                     .read_u32(hostport_queues_info.cmd_avl_queue.dequeue_addr, None)
-                    .await
+                    .await?
                     == 0;
 
                 if !is_empty {
@@ -374,6 +629,10 @@ impl<BUS: Bus> Rpu<BUS> {
         Err(Error::Timeout)
     }
 
+    /// Writes a message to the RPU and posts it to the relevant hostport queue.
+    ///
+    /// The identifier is the receive queue for `NRF_WIFI_HAL_MSG_TYPE_CMD_DATA_RX` and the transmit
+    /// descriptor for `NRF_WIFI_HAL_MSG_TYPE_CMD_DATA_TX`. It is not used for control commands.
     async fn enqueue_command_and_trigger(
         &mut self,
         message: &[u8],
@@ -395,14 +654,29 @@ impl<BUS: Bus> Rpu<BUS> {
                 bytes_left_to_send
             };
 
-            self.wait_until_ready_for_new_command(NRF_WIFI_HAL_MSG_TYPE::NRF_WIFI_HAL_MSG_TYPE_CMD_CTRL)
-                .await?;
+            let message_address = match message_type {
+                NRF_WIFI_HAL_MSG_TYPE::NRF_WIFI_HAL_MSG_TYPE_CMD_DATA_TX => {
+                    // Data commands have a fixed slot per descriptor
+                    let address_base = match self.tx_command_base_address {
+                        Some(address_base) => Ok(address_base),
+                        None => Err(Error::InvalidAddress),
+                    }?;
 
-            // Wait until we get an address to write to
-            // This queue might already be full with other messages, so we'll just have to wait a bit
-            let message_address = loop {
-                if let Some(message_address) = self.hostport_queue_dequeue(hostport_queues_info.cmd_avl_queue).await {
-                    break message_address;
+                    address_base + RPU_DATA_CMD_SIZE_MAX_TX * queue_identifier as u32
+                }
+                _ => {
+                    self.wait_until_ready_for_new_command(NRF_WIFI_HAL_MSG_TYPE::NRF_WIFI_HAL_MSG_TYPE_CMD_CTRL)
+                        .await?;
+
+                    // Wait until we get an address to write to
+                    // This queue might already be full with other messages, so we'll just have to wait a bit
+                    loop {
+                        if let Some(message_address) =
+                            self.hostport_queue_dequeue(hostport_queues_info.cmd_avl_queue).await?
+                        {
+                            break message_address;
+                        }
+                    }
                 }
             };
 
@@ -415,14 +689,14 @@ impl<BUS: Bus> Rpu<BUS> {
 
             // Write the message to the suggested address
             self.write_buffer(message_address, None, slice32(&message[offset..offset + bytes_to_send]))
-                .await;
+                .await?;
 
             match message_type {
                 NRF_WIFI_HAL_MSG_TYPE::NRF_WIFI_HAL_MSG_TYPE_CMD_CTRL
                 | NRF_WIFI_HAL_MSG_TYPE::NRF_WIFI_HAL_MSG_TYPE_CMD_DATA_TX => {
                     // Post the updated information to the RPU
                     self.hostport_queue_enqueue(hostport_queues_info.cmd_busy_queue, message_address)
-                        .await;
+                        .await?;
 
                     // --- Trigger ---
                     //
@@ -432,7 +706,7 @@ impl<BUS: Bus> Rpu<BUS> {
                         Some(ProcessorType::Umac),
                         self.num_commands | 0x7fff_0000,
                     )
-                    .await;
+                    .await?;
 
                     self.num_commands = self.num_commands.wrapping_add(1);
                 }
@@ -441,7 +715,7 @@ impl<BUS: Bus> Rpu<BUS> {
                         hostport_queues_info.rx_buf_busy_queue[queue_identifier],
                         message_address,
                     )
-                    .await;
+                    .await?;
                 }
                 _ => {
                     warn!("Invalid message type: {}", message_type as u32);
@@ -478,12 +752,25 @@ impl<BUS: Bus> Rpu<BUS> {
         let host_address = (address & RPU_ADDR_MASK_OFFSET) | RPU_MCU_CORE_INDIRECT_BASE;
 
         // Write the command to the core
-        self.write_core(host_address, command, ProcessorType::Lmac).await;
+        self.write_core(host_address, command, ProcessorType::Lmac).await?;
 
         // Post the updated information to the RPU
         self.hostport_queue_enqueue(hostport_queue_info.rx_buf_busy_queue[queue_identifier], address)
-            .await;
+            .await?;
 
         Ok(())
     }
+
+    pub(super) async fn send_tx_command(&mut self, command: &[u32], descriptor_identifier: usize) -> Result<(), Error> {
+        if command.len() * 4 > RPU_DATA_CMD_SIZE_MAX_TX as usize {
+            return Err(Error::BufferOverflow);
+        }
+
+        self.enqueue_command_and_trigger(
+            slice8(command),
+            NRF_WIFI_HAL_MSG_TYPE::NRF_WIFI_HAL_MSG_TYPE_CMD_DATA_TX,
+            descriptor_identifier,
+        )
+        .await
+    }
 }
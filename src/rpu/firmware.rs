@@ -156,7 +156,7 @@ impl<BUS: Bus> Rpu<BUS> {
     pub(super) async fn firmware_load<'firmware_info_lifetime>(
         &mut self,
         firmware_info: &FirmwareInfo<'firmware_info_lifetime>,
-    ) {
+    ) -> Result<(), Error> {
         const CHUNK_SIZE: usize = 1024;
 
         for image in firmware_info.images.into_iter().flatten() {
@@ -169,12 +169,14 @@ impl<BUS: Bus> Rpu<BUS> {
                 let chunk_offset = offset + (CHUNK_SIZE * i) as u32;
 
                 self.write_buffer_to_region(memory_region, chunk_offset, slice32(chunk))
-                    .await;
+                    .await?;
             }
         }
+
+        Ok(())
     }
 
-    pub(super) async fn firmware_boot(&mut self) {
+    pub(super) async fn firmware_boot(&mut self) -> Result<(), Error> {
         // This will block until the boot signatures are verified.
         let processsors = [ProcessorType::Lmac, ProcessorType::Umac];
 
@@ -185,7 +187,7 @@ impl<BUS: Bus> Rpu<BUS> {
             };
 
             // Clear the firmware pass signature location
-            self.write_u32(boot_signature_address, Some(processor), 0).await;
+            self.write_u32(boot_signature_address, Some(processor), 0).await?;
 
             self.write_u32(
                 match processor {
@@ -198,7 +200,7 @@ impl<BUS: Bus> Rpu<BUS> {
                     ProcessorType::Umac => NRF_WIFI_UMAC_ROM_PATCH_OFFSET,
                 },
             )
-            .await;
+            .await?;
 
             // Write the boot vectors
             let boot_vectors = match processor {
@@ -217,7 +219,7 @@ impl<BUS: Bus> Rpu<BUS> {
             };
 
             for boot_vector in boot_vectors {
-                self.write_u32(boot_vector[0], Some(processor), boot_vector[1]).await;
+                self.write_u32(boot_vector[0], Some(processor), boot_vector[1]).await?;
             }
 
             // Perform pulsed soft reset
@@ -229,7 +231,7 @@ impl<BUS: Bus> Rpu<BUS> {
                 Some(processor),
                 0x1,
             )
-            .await;
+            .await?;
 
             // Check boot signature
             let expected_boot_signature = match processor {
@@ -237,10 +239,12 @@ impl<BUS: Bus> Rpu<BUS> {
                 ProcessorType::Umac => NRF_WIFI_UMAC_BOOT_SIG,
             };
 
-            while self.read_u32(boot_signature_address, Some(processor)).await != expected_boot_signature {
+            while self.read_u32(boot_signature_address, Some(processor)).await? != expected_boot_signature {
                 Timer::after_millis(10).await;
             }
         }
+
+        Ok(())
     }
 
     pub(super) async fn firmware_initialize(&mut self, rf_parameters: &nrf_wifi_phy_rf_params) -> Result<(), Error> {
@@ -314,14 +318,14 @@ impl<BUS: Bus> Rpu<BUS> {
         self.send_command(init_command).await
     }
 
-    pub async fn firmware_version(&mut self) -> FirmwareVersion {
-        let version = self.read_u32(RPU_MEM_UMAC_VER, None).await;
+    pub async fn firmware_version(&mut self) -> Result<FirmwareVersion, Error> {
+        let version = self.read_u32(RPU_MEM_UMAC_VER, None).await?;
 
-        FirmwareVersion {
+        Ok(FirmwareVersion {
             version: ((version & 0xFF00_0000) >> 24) as u8,
             major: ((version & 0x00FF_0000) >> 16) as u8,
             minor: ((version & 0x0000_FF00) >> 8) as u8,
             extra: (version & 0x0000_00FF) as u8,
-        }
+        })
     }
 }
@@ -1,4 +1,7 @@
-use crate::{bus::Bus, remap_global_addr_to_region_and_offset, slice8};
+use crate::{
+    bus::{bus_error, Bus},
+    remap_global_addr_to_region_and_offset, slice8, Error,
+};
 
 use super::{ProcessorType, Rpu};
 
@@ -40,30 +43,46 @@ pub(crate) mod regions {
 
     #[doc(alias = "pal_rpu_addr_offset_get")]
     pub(crate) fn remap_global_addr_to_region_and_offset(rpu_addr: u32, processor: Option<ProcessorType>) -> (&'static MemoryRegion, u32) {
-        unwrap!(
-            REGIONS
-                .into_iter()
-                .filter(|region| region.processor_restriction.is_none() || region.processor_restriction == processor)
-                .find(|region| rpu_addr >= region.rpu_mem_start && rpu_addr <= region.rpu_mem_end)
-                .map(|region| (region, rpu_addr - region.rpu_mem_start))
-        )
+        unwrap!(find_region_and_offset(rpu_addr, processor))
+    }
+
+    /// The region an RPU address is mapped to on the bus, if any.
+    pub(crate) fn find_region_and_offset(rpu_addr: u32, processor: Option<ProcessorType>) -> Option<(&'static MemoryRegion, u32)> {
+        REGIONS
+            .into_iter()
+            .filter(|region| region.processor_restriction.is_none() || region.processor_restriction == processor)
+            .find(|region| rpu_addr >= region.rpu_mem_start && rpu_addr <= region.rpu_mem_end)
+            .map(|region| (region, rpu_addr - region.rpu_mem_start))
     }
 }
 
 impl<BUS: Bus> Rpu<BUS> {
-    async fn raw_read_u32_from_memory_region_inner(&mut self, memory_region: &MemoryRegion, offset: u32) -> u32 {
+    async fn raw_read_u32_from_memory_region_inner(
+        &mut self,
+        memory_region: &MemoryRegion,
+        offset: u32,
+    ) -> Result<u32, Error> {
         assert!(memory_region.start + offset + 4 <= memory_region.end);
-        let lat = memory_region.latency as usize;
 
-        let mut buf = [0u32; 3];
-        self.bus.read(memory_region.start + offset, &mut buf[..=lat]).await;
-        buf[lat]
+        let mut buf = [0u32; 1];
+        self.bus
+            .read_with_latency(memory_region.start + offset, memory_region.latency as usize, &mut buf)
+            .await
+            .map_err(bus_error)?;
+
+        Ok(buf[0])
     }
 
-    pub(crate) async fn read_u32_from_region(&mut self, memory_region: &MemoryRegion, offset: u32) -> u32 {
-        let result = self.raw_read_u32_from_memory_region_inner(memory_region, offset).await;
+    pub(crate) async fn read_u32_from_region(
+        &mut self,
+        memory_region: &MemoryRegion,
+        offset: u32,
+    ) -> Result<u32, Error> {
+        let result = self
+            .raw_read_u32_from_memory_region_inner(memory_region, offset)
+            .await?;
         trace!("read32 {:08x} {:08x}", memory_region.start + offset, result);
-        result
+        Ok(result)
     }
 
     pub(crate) async fn read_buffer_from_region(
@@ -71,35 +90,40 @@ impl<BUS: Bus> Rpu<BUS> {
         memory_region: &MemoryRegion,
         offset: u32,
         buffer: &mut [u32],
-    ) {
+    ) -> Result<(), Error> {
         assert!(memory_region.start + offset + (buffer.len() as u32 * 4) <= memory_region.end);
 
-        // latency=0 optimization doesn't seem to be working, we read the first word repeatedly.
-        // TODO: Think we need to follow the convention with write enable byte here
-        if memory_region.latency == 0 && false {
-            // No latency, we can do a big read directly.
-            self.bus.read(memory_region.start + offset, buffer).await;
-        } else {
-            // Otherwise, read word by word.
-            for (i, val) in buffer.iter_mut().enumerate() {
-                *val = self
-                    .raw_read_u32_from_memory_region_inner(memory_region, offset + i as u32 * 4)
-                    .await;
-            }
-        }
+        // The whole buffer is read in one burst, the dummy words of the region are only sent once
+        self.bus
+            .read_with_latency(memory_region.start + offset, memory_region.latency as usize, buffer)
+            .await
+            .map_err(bus_error)?;
+
         trace!(
             "read addr={:08x} len={:08x} buf={:02x}",
             memory_region.start + offset,
             buffer.len() * 4,
             slice8(buffer)
         );
+
+        Ok(())
     }
 
-    pub(crate) async fn write_u32_to_region(&mut self, memory_region: &MemoryRegion, offset: u32, value: u32) {
-        self.write_buffer_to_region(memory_region, offset, &[value]).await;
+    pub(crate) async fn write_u32_to_region(
+        &mut self,
+        memory_region: &MemoryRegion,
+        offset: u32,
+        value: u32,
+    ) -> Result<(), Error> {
+        self.write_buffer_to_region(memory_region, offset, &[value]).await
     }
 
-    pub(crate) async fn write_buffer_to_region(&mut self, memory_region: &MemoryRegion, offset: u32, buffer: &[u32]) {
+    pub(crate) async fn write_buffer_to_region(
+        &mut self,
+        memory_region: &MemoryRegion,
+        offset: u32,
+        buffer: &[u32],
+    ) -> Result<(), Error> {
         assert!(memory_region.start + offset + (buffer.len() as u32 * 4) <= memory_region.end);
 
         trace!(
@@ -109,26 +133,44 @@ impl<BUS: Bus> Rpu<BUS> {
             slice8(buffer)
         );
 
-        self.bus.write(memory_region.start + offset, buffer).await;
+        self.bus
+            .write(memory_region.start + offset, buffer)
+            .await
+            .map_err(bus_error)
     }
 
-    pub(crate) async fn read_u32(&mut self, rpu_address: u32, processor: Option<ProcessorType>) -> u32 {
+    pub(crate) async fn read_u32(&mut self, rpu_address: u32, processor: Option<ProcessorType>) -> Result<u32, Error> {
         let (memory_region, offset) = remap_global_addr_to_region_and_offset(rpu_address, processor);
         self.read_u32_from_region(memory_region, offset).await
     }
 
-    pub(crate) async fn read_buffer(&mut self, rpu_address: u32, processor: Option<ProcessorType>, buffer: &mut [u32]) {
+    pub(crate) async fn read_buffer(
+        &mut self,
+        rpu_address: u32,
+        processor: Option<ProcessorType>,
+        buffer: &mut [u32],
+    ) -> Result<(), Error> {
         let (memory_region, offset) = regions::remap_global_addr_to_region_and_offset(rpu_address, processor);
-        self.read_buffer_from_region(memory_region, offset, buffer).await;
+        self.read_buffer_from_region(memory_region, offset, buffer).await
     }
 
-    pub(crate) async fn write_u32(&mut self, rpu_address: u32, processor: Option<ProcessorType>, value: u32) {
+    pub(crate) async fn write_u32(
+        &mut self,
+        rpu_address: u32,
+        processor: Option<ProcessorType>,
+        value: u32,
+    ) -> Result<(), Error> {
         let (memory_region, offset) = remap_global_addr_to_region_and_offset(rpu_address, processor);
-        self.write_u32_to_region(memory_region, offset, value).await;
+        self.write_u32_to_region(memory_region, offset, value).await
     }
 
-    pub(crate) async fn write_buffer(&mut self, rpu_address: u32, processor: Option<ProcessorType>, buffer: &[u32]) {
+    pub(crate) async fn write_buffer(
+        &mut self,
+        rpu_address: u32,
+        processor: Option<ProcessorType>,
+        buffer: &[u32],
+    ) -> Result<(), Error> {
         let (memory_region, offset) = remap_global_addr_to_region_and_offset(rpu_address, processor);
-        self.write_buffer_to_region(memory_region, offset, buffer).await;
+        self.write_buffer_to_region(memory_region, offset, buffer).await
     }
 }
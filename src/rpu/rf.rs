@@ -29,6 +29,7 @@ use crate::{
         RX_GAIN_OFFSET_HB_HIGH_CHAN, RX_GAIN_OFFSET_HB_LOW_CHAN, RX_GAIN_OFFSET_HB_MID_CHAN,
     },
     bus::Bus,
+    Error,
 };
 
 use super::Rpu;
@@ -159,13 +160,13 @@ impl<BUS: Bus> Rpu<BUS> {
         umac_info: &host_rpu_umac_info,
         otp_flags: u32,
         tx_pwr_ceil_params: &nrf_wifi_tx_pwr_ceil_params,
-    ) -> nrf_wifi_phy_rf_params {
+    ) -> Result<nrf_wifi_phy_rf_params, Error> {
         const RF_PARAM_OFFSET: usize =
             EDGE_BACKOFF_OFFSETS::BAND_2G_LW_ED_BKF_DSSS_OFST as usize - NRF_WIFI_RF_PARAMS_CONF_SIZE as usize;
 
-        let ft_prog_version = (self.read_u32(RPU_MEM_OTP_FT_PROG_VERSION, None).await & FT_PROG_VER_MASK) >> 16;
+        let ft_prog_version = (self.read_u32(RPU_MEM_OTP_FT_PROG_VERSION, None).await? & FT_PROG_VER_MASK) >> 16;
 
-        let package_type = self.read_u32(RPU_MEM_OTP_PACKAGE_TYPE, None).await;
+        let package_type = self.read_u32(RPU_MEM_OTP_PACKAGE_TYPE, None).await?;
         let mut phy_rf_params = nrf_wifi_phy_rf_params::default_from(package_type);
 
         // Then populate the configuration based ones
@@ -246,7 +247,7 @@ impl<BUS: Bus> Rpu<BUS> {
             phy_rf_params.max_pwr_ceil.max_hb_high_chan_mcs0_pwr,
         ) - backoff_5g_highband;
 
-        phy_rf_params
+        Ok(phy_rf_params)
     }
 }
 
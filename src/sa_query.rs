@@ -0,0 +1,92 @@
+//! The SA Query procedure (IEEE 802.11-2020 11.13).
+//!
+//! With management frame protection the firmware does not act on deauthentications and
+//! disassociations that are not protected, as anyone can forge them. Instead it reports them, and
+//! we ask the AP over the protected link whether it still knows our association. Only if it does
+//! not answer in time the association is considered lost.
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+/// Time to wait for a response before sending the next request (dot11AssociationSAQueryRetryTimeout).
+pub(crate) const RETRY_TIMEOUT: Duration = Duration::from_millis(201);
+
+/// Time to wait for any response before giving up (dot11AssociationSAQueryMaximumTimeout).
+const MAXIMUM_TIMEOUT: Duration = Duration::from_millis(1000);
+
+const CATEGORY_SA_QUERY: u8 = 8;
+const ACTION_REQUEST: u8 = 0;
+const ACTION_RESPONSE: u8 = 1;
+
+/// The start of the body of SA Query responses, used to register for them with the firmware.
+pub(crate) const RESPONSE_MATCH: [u8; 2] = [CATEGORY_SA_QUERY, ACTION_RESPONSE];
+
+/// Reason codes of deauthentications and disassociations which are sent by an AP that does not
+/// know the station (class 2 or class 3 frame received from nonauthenticated or nonassociated
+/// station). Others are not answered with a query.
+pub(crate) const REASON_CLASS_2_FRAME: u16 = 6;
+pub(crate) const REASON_CLASS_3_FRAME: u16 = 7;
+
+/// Size of the 802.11 header of a management frame.
+const HEADER_SIZE: usize = 24;
+
+/// Size of an SA Query frame: header, category, action and transaction identifier.
+pub(crate) const FRAME_SIZE: usize = HEADER_SIZE + 4;
+
+/// Upper bound of the requests sent in one query.
+const MAX_REQUESTS: usize = (MAXIMUM_TIMEOUT.as_ticks() / RETRY_TIMEOUT.as_ticks() + 1) as usize;
+
+/// A query in progress.
+pub(crate) struct SaQuery {
+    started: Instant,
+    transactions: Vec<u16, MAX_REQUESTS>,
+}
+
+impl SaQuery {
+    pub(crate) fn new() -> Self {
+        SaQuery {
+            started: Instant::now(),
+            transactions: Vec::new(),
+        }
+    }
+
+    /// When the next request is due.
+    pub(crate) fn retry_at(&self) -> Instant {
+        self.started + RETRY_TIMEOUT * self.transactions.len() as u32
+    }
+
+    /// Records a request with the given transaction identifier. Fails if the query timed out and
+    /// the association should be considered lost.
+    pub(crate) fn add_request(&mut self, transaction: u16) -> Result<(), ()> {
+        if Instant::now() >= self.started + MAXIMUM_TIMEOUT {
+            return Err(());
+        }
+
+        self.transactions.push(transaction).map_err(|_| ())
+    }
+
+    /// Whether `frame` is the response of the AP to one of our requests.
+    pub(crate) fn is_response(&self, frame: &[u8], ap_address: &[u8; 6]) -> bool {
+        frame.len() == FRAME_SIZE
+            && frame[10..16] == ap_address[..]
+            && frame[HEADER_SIZE..HEADER_SIZE + 2] == RESPONSE_MATCH
+            && self
+                .transactions
+                .contains(&u16::from_le_bytes([frame[HEADER_SIZE + 2], frame[HEADER_SIZE + 3]]))
+    }
+}
+
+/// Writes an SA Query request to the AP. The firmware protects it with the pairwise key.
+pub(crate) fn request(own_address: &[u8; 6], ap_address: &[u8; 6], transaction: u16) -> [u8; FRAME_SIZE] {
+    const FRAME_CONTROL_ACTION: [u8; 2] = [0xd0, 0x00];
+
+    let mut frame = [0u8; FRAME_SIZE];
+    frame[..2].copy_from_slice(&FRAME_CONTROL_ACTION);
+    frame[4..10].copy_from_slice(ap_address);
+    frame[10..16].copy_from_slice(own_address);
+    frame[16..22].copy_from_slice(ap_address);
+    frame[HEADER_SIZE] = CATEGORY_SA_QUERY;
+    frame[HEADER_SIZE + 1] = ACTION_REQUEST;
+    frame[HEADER_SIZE + 2..].copy_from_slice(&transaction.to_le_bytes());
+    frame
+}
@@ -0,0 +1,1248 @@
+//! A simulation of the nRF70 on the host, to run the driver in tests without the chip.
+//!
+//! The [`Simulator`] models what the driver sees of the chip over the [`Bus`]: the status
+//! registers, the memory regions, the hostport queues and the boot signatures. In place of the
+//! firmware, a scripted UMAC and LMAC answer the commands with the events the driver waits for.
+//! They find the access points added with [`Simulator::add_access_point`] when scanning, join them
+//! as open networks, record the transmitted frames and deliver the frames passed to
+//! [`Simulator::receive`]. The handshake of a protected network is left to the test, which passes
+//! the EAPOL frames of the AP to [`Simulator::receive`] as well.
+//!
+//! The bus, the interrupt line and the power pins handed to [`crate::new`] all share the state of
+//! the simulator with the test driving it.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::future::poll_fn;
+use core::mem::{size_of, zeroed};
+use core::task::{Poll, Waker};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::rc::Rc;
+use std::vec::Vec;
+
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal_async::digital::Wait;
+
+use crate::{
+    bindings::*,
+    bus::Bus,
+    rpu::{
+        memory::regions::{find_region_and_offset, REGIONS, SYSBUS},
+        ProcessorType, MAX_TX_TOKENS, RX_MAX_DATA_SIZE,
+    },
+    util::{meh, slice8, sliceit, unsliceit_padded},
+    SR1_RPU_AWAKE, SR1_RPU_READY, SR2_RPU_WAKEUP_REQ,
+};
+
+/// Version reported by the simulated firmware
+const FIRMWARE_VERSION: u32 = 0x0102_0304;
+
+/// The hostport queues are placed at an unused part of the system bus, each as a pair of enqueue
+/// and dequeue registers
+const QUEUE_BASE: u32 = 0xA400_4000;
+
+const EVENT_BUSY_QUEUE: usize = 0;
+const EVENT_AVAILABLE_QUEUE: usize = 1;
+const COMMAND_BUSY_QUEUE: usize = 2;
+const COMMAND_AVAILABLE_QUEUE: usize = 3;
+const RX_BUFFER_BUSY_QUEUE: usize = 4;
+const QUEUES: usize = RX_BUFFER_BUSY_QUEUE + MAX_NUM_OF_RX_QUEUES as usize;
+
+/// Buffers handed out for control commands, in the GRAM
+const COMMAND_BUFFER_BASE: u32 = 0xB700_1000;
+const COMMAND_BUFFER_SIZE: u32 = 0x200;
+const COMMAND_BUFFERS: u32 = 4;
+
+/// Buffers for the events, in the packet RAM before the area used for packets
+const EVENT_BUFFER_BASE: u32 = 0xB000_1000;
+const EVENT_BUFFER_SIZE: u32 = 0x400;
+const EVENT_BUFFERS: u32 = 8;
+
+/// Where the LMAC takes the receive commands from, in its retained RAM
+const RX_COMMAND_BASE: u32 = 0x8004_8000;
+
+/// Boot exception registers of the LMAC and UMAC, which are set once they come out of reset
+const LMAC_BOOT_EXCEPTION: u32 = 0xA400_0018;
+const UMAC_BOOT_EXCEPTION: u32 = 0xA400_0118;
+
+const MESSAGE_HEADER_SIZE: usize = size_of::<host_rpu_msg>();
+const MANAGEMENT_HEADER_SIZE: usize = 24;
+
+/// The address of a register of the system bus, as accessed over the bus.
+const fn sysbus(rpu_address: u32) -> u32 {
+    rpu_address - SYSBUS.rpu_mem_start + SYSBUS.start
+}
+
+const LMAC_CONTROL: u32 = sysbus(RPU_REG_MIPS_MCU_CONTROL);
+const UMAC_CONTROL: u32 = sysbus(RPU_REG_MIPS_MCU2_CONTROL);
+const LMAC_CORE_MEMORY_CONTROL: u32 = sysbus(RPU_REG_MIPS_MCU_SYS_CORE_MEM_CTRL);
+const LMAC_CORE_MEMORY_DATA: u32 = sysbus(RPU_REG_MIPS_MCU_SYS_CORE_MEM_WDATA);
+const UMAC_CORE_MEMORY_CONTROL: u32 = sysbus(RPU_REG_MIPS_MCU2_SYS_CORE_MEM_CTRL);
+const UMAC_CORE_MEMORY_DATA: u32 = sysbus(RPU_REG_MIPS_MCU2_SYS_CORE_MEM_WDATA);
+const TRIGGER: u32 = sysbus(RPU_REG_INT_TO_MCU_CTRL);
+
+/// Errors of the simulated bus, and of feeding frames to the simulator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulatorError {
+    /// The bus was accessed before both power pins were set high
+    PoweredOff,
+    /// The address is outside of the memory regions
+    InvalidAddress(u32),
+    /// All receive buffers are in use, the driver has not handed any back
+    NoReceiveBuffer,
+    /// The frame is shorter than an Ethernet header or longer than a receive buffer
+    InvalidFrame,
+}
+
+/// An access point found by the simulated firmware, advertising an open network unless its
+/// elements tell otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessPoint {
+    pub bssid: [u8; 6],
+    pub ssid: Vec<u8>,
+    /// Frequency of the channel in MHz
+    pub frequency: u32,
+    /// Signal strength in dBm
+    pub rssi: i32,
+    /// Elements of the beacon after the SSID and DS parameter set, e.g. an RSN element
+    pub elements: Vec<u8>,
+}
+
+/// A scan the driver had the firmware start, as read from the command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scan {
+    /// Whether the scan listens for beacons only, without sending probes
+    pub passive: bool,
+    /// SSIDs to probe for
+    pub ssids: Vec<Vec<u8>>,
+    /// BSSID to find, or all zeros for any
+    pub bssid: [u8; 6],
+    /// Bitmap of the bands to scan, bit 0 for 2.4 GHz and bit 1 for 5 GHz, with none for all
+    pub bands: u8,
+    /// Frequencies of the channels to scan in MHz, with none for all of the bands
+    pub frequencies: Vec<u32>,
+    /// Elements added to the probe requests
+    pub probe_ies: Vec<u8>,
+    /// Time spent on each channel in ms when probing, with zero for the default
+    pub dwell_time_active: u16,
+    /// Time spent on each channel in ms when listening, with zero for the default
+    pub dwell_time_passive: u16,
+}
+
+/// A simulated nRF70, shared between the driver and the test. Cloning it gives another handle to
+/// the same chip.
+#[derive(Clone)]
+pub struct Simulator {
+    chip: Rc<RefCell<Chip>>,
+}
+
+impl Simulator {
+    /// A powered off chip which reports `mac_address` as its MAC address.
+    #[must_use]
+    pub fn new(mac_address: [u8; 6]) -> Self {
+        Self {
+            chip: Rc::new(RefCell::new(Chip::new(mac_address))),
+        }
+    }
+
+    /// The pin enabling the buck regulator, to pass to [`crate::new`].
+    #[must_use]
+    pub fn bucken(&self) -> PowerPin {
+        PowerPin {
+            chip: self.chip.clone(),
+            supply: Supply::Bucken,
+        }
+    }
+
+    /// The pin enabling the IO supply, to pass to [`crate::new`].
+    #[must_use]
+    pub fn iovdd_ctl(&self) -> PowerPin {
+        PowerPin {
+            chip: self.chip.clone(),
+            supply: Supply::Iovdd,
+        }
+    }
+
+    /// The interrupt line to the host, to pass to [`crate::new`]. It is high while there are
+    /// events in the event queue.
+    #[must_use]
+    pub fn host_irq(&self) -> HostIrq {
+        HostIrq {
+            chip: self.chip.clone(),
+        }
+    }
+
+    /// Adds an access point which shows up in the following scans.
+    pub fn add_access_point(&self, access_point: AccessPoint) {
+        self.chip.borrow_mut().access_points.push(access_point);
+    }
+
+    /// How many times the driver has booted the firmware, counted by the initializations which
+    /// end each boot.
+    #[must_use]
+    pub fn boots(&self) -> usize {
+        self.chip.borrow().boots
+    }
+
+    /// The MAC address last set by the driver.
+    #[must_use]
+    pub fn mac_address(&self) -> [u8; 6] {
+        self.chip.borrow().mac_address
+    }
+
+    /// Whether the driver has brought the interface up.
+    #[must_use]
+    pub fn is_interface_up(&self) -> bool {
+        self.chip.borrow().interface_up
+    }
+
+    /// The BSSID of the access point the driver is associated with.
+    #[must_use]
+    pub fn associated(&self) -> Option<[u8; 6]> {
+        self.chip
+            .borrow()
+            .associated
+            .as_ref()
+            .map(|access_point| access_point.bssid)
+    }
+
+    /// Takes the access point the driver is associated with off the air. The firmware misses its
+    /// beacons and ends the association on its own.
+    pub fn lose_access_point(&self) {
+        /// Disassociated due to inactivity
+        const REASON_INACTIVITY: u16 = 4;
+
+        let mut chip = self.chip.borrow_mut();
+
+        if let Some(bssid) = chip.associated.as_ref().map(|access_point| access_point.bssid) {
+            chip.access_points.retain(|access_point| access_point.bssid != bssid);
+            chip.deauthenticate(REASON_INACTIVITY);
+        }
+    }
+
+    /// Makes the firmware report an unprotected deauthentication from the access point the driver
+    /// is associated with, as anyone could have forged it. The access point still answers SA
+    /// Queries.
+    pub fn spoof_deauthentication(&self, reason: u16) {
+        self.chip.borrow_mut().push_unprotected_deauthentication(reason);
+    }
+
+    /// Restarts the access point the driver is associated with, which forgets the association. It
+    /// tells the driver so with an unprotected deauthentication for a class 3 frame and no longer
+    /// answers SA Queries, while the firmware stays associated until told otherwise.
+    pub fn restart_access_point(&self) {
+        /// Class 3 frame received from nonassociated station
+        const REASON_CLASS_3_FRAME: u16 = 7;
+
+        let mut chip = self.chip.borrow_mut();
+        chip.station_known = false;
+        chip.push_unprotected_deauthentication(REASON_CLASS_3_FRAME);
+    }
+
+    /// Takes the Ethernet frames transmitted by the driver so far.
+    #[must_use]
+    pub fn take_transmitted(&self) -> Vec<Vec<u8>> {
+        core::mem::take(&mut self.chip.borrow_mut().transmitted)
+    }
+
+    /// Takes the scans the driver started so far.
+    #[must_use]
+    pub fn take_scans(&self) -> Vec<Scan> {
+        core::mem::take(&mut self.chip.borrow_mut().scans)
+    }
+
+    /// Takes the management frames the driver had the firmware send so far.
+    #[must_use]
+    pub fn take_management_frames(&self) -> Vec<Vec<u8>> {
+        core::mem::take(&mut self.chip.borrow_mut().management_frames)
+    }
+
+    /// Receives an Ethernet frame from the access point the driver is associated with. It is
+    /// placed as a 802.11 data frame in the next receive buffer posted by the driver.
+    pub fn receive(&self, frame: &[u8]) -> Result<(), SimulatorError> {
+        self.chip.borrow_mut().receive(frame)
+    }
+}
+
+impl Bus for Simulator {
+    type Error = SimulatorError;
+
+    async fn read(&mut self, addr: u32, buf: &mut [u32]) -> Result<(), Self::Error> {
+        let chip = self.chip.borrow();
+        let latency = chip.check_access(addr, buf.len())?;
+
+        // The memory regions with a latency send dummy words before the data
+        let (dummy, words) = buf.split_at_mut(latency.min(buf.len()));
+        dummy.fill(0);
+
+        for (index, word) in words.iter_mut().enumerate() {
+            *word = chip.read_register(addr + 4 * index as u32);
+        }
+
+        Ok(())
+    }
+
+    async fn write(&mut self, addr: u32, buf: &[u32]) -> Result<(), Self::Error> {
+        let mut chip = self.chip.borrow_mut();
+        chip.check_access(addr, buf.len())?;
+
+        if let [value] = buf {
+            if chip.write_register(addr, *value) {
+                return Ok(());
+            }
+        }
+
+        chip.store(addr, slice8(buf));
+
+        if Chip::is_command_buffer(addr) {
+            chip.command_lengths.insert(addr, buf.len() * 4);
+        }
+
+        Ok(())
+    }
+
+    async fn read_sr0(&mut self) -> Result<u8, Self::Error> {
+        self.chip.borrow().check_powered()?;
+        Ok(0)
+    }
+
+    async fn read_sr1(&mut self) -> Result<u8, Self::Error> {
+        let chip = self.chip.borrow();
+        chip.check_powered()?;
+
+        if chip.sr2 & SR2_RPU_WAKEUP_REQ != 0 {
+            Ok(SR1_RPU_AWAKE | SR1_RPU_READY)
+        } else {
+            Ok(0)
+        }
+    }
+
+    async fn read_sr2(&mut self) -> Result<u8, Self::Error> {
+        let chip = self.chip.borrow();
+        chip.check_powered()?;
+        Ok(chip.sr2)
+    }
+
+    async fn write_sr2(&mut self, val: u8) -> Result<(), Self::Error> {
+        let mut chip = self.chip.borrow_mut();
+        chip.check_powered()?;
+        chip.sr2 = val;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Supply {
+    Bucken,
+    Iovdd,
+}
+
+/// One of the power pins of the [`Simulator`].
+pub struct PowerPin {
+    chip: Rc<RefCell<Chip>>,
+    supply: Supply,
+}
+
+impl PowerPin {
+    fn set(&mut self, level: bool) {
+        let mut chip = self.chip.borrow_mut();
+
+        match self.supply {
+            Supply::Bucken => chip.bucken = level,
+            Supply::Iovdd => chip.iovdd = level,
+        }
+    }
+}
+
+impl ErrorType for PowerPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for PowerPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set(true);
+        Ok(())
+    }
+}
+
+/// The interrupt line of the [`Simulator`].
+pub struct HostIrq {
+    chip: Rc<RefCell<Chip>>,
+}
+
+impl HostIrq {
+    /// Waits for the line to be at `level`. This always yields once, even if the line is at that
+    /// level already, to let the other tasks see the events read so far, as the bus of the
+    /// simulator never does.
+    async fn wait_for(&mut self, level: bool) {
+        let mut yielded = false;
+
+        poll_fn(|cx| {
+            let mut chip = self.chip.borrow_mut();
+
+            if chip.irq() == level {
+                if yielded {
+                    return Poll::Ready(());
+                }
+
+                cx.waker().wake_by_ref();
+            } else {
+                chip.irq_waker = Some(cx.waker().clone());
+            }
+
+            yielded = true;
+            Poll::Pending
+        })
+        .await;
+    }
+}
+
+impl ErrorType for HostIrq {
+    type Error = Infallible;
+}
+
+impl InputPin for HostIrq {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.chip.borrow().irq())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.chip.borrow().irq())
+    }
+}
+
+impl Wait for HostIrq {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for(true).await;
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for(false).await;
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for(false).await;
+        self.wait_for(true).await;
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for(true).await;
+        self.wait_for(false).await;
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        let level = self.chip.borrow().irq();
+        self.wait_for(!level).await;
+        Ok(())
+    }
+}
+
+/// The state of the simulated chip.
+struct Chip {
+    /// The whole address space of the bus
+    memory: Vec<u8>,
+    sr2: u8,
+    bucken: bool,
+    iovdd: bool,
+
+    /// The byte address of the indirect accesses to the memory of the LMAC and UMAC
+    core_addresses: [u32; 2],
+
+    queues: [VecDeque<u32>; QUEUES],
+
+    /// Length of the last chunk written to each command buffer
+    command_lengths: BTreeMap<u32, usize>,
+
+    /// A command of which not all chunks have arrived yet
+    partial_command: Vec<u8>,
+
+    /// Fragments of events waiting for a free event buffer
+    fragments: VecDeque<Vec<u8>>,
+    free_event_buffers: VecDeque<u32>,
+    irq_waker: Option<Waker>,
+
+    boots: usize,
+    mac_address: [u8; 6],
+    access_points: Vec<AccessPoint>,
+    interface_up: bool,
+    associated: Option<AccessPoint>,
+    transmitted: Vec<Vec<u8>>,
+    scans: Vec<Scan>,
+
+    /// Whether the access point we are associated with still knows the association
+    station_known: bool,
+    management_frames: Vec<Vec<u8>>,
+}
+
+/// The bus address and processor of an address in the memory of the RPU.
+fn bus_address(rpu_address: u32, processor: Option<ProcessorType>) -> u32 {
+    let (region, offset) = unwrap!(find_region_and_offset(rpu_address, processor));
+    region.start + offset
+}
+
+fn umac_header(event: nrf_wifi_umac_events) -> nrf_wifi_umac_hdr {
+    let mut header: nrf_wifi_umac_hdr = unsafe { zeroed() };
+    header.cmd_evnt = event as u32;
+    header
+}
+
+/// A management frame within the BSS from `source` to `destination`.
+fn management_frame(frame_control: u8, destination: [u8; 6], source: [u8; 6], bssid: [u8; 6], body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MANAGEMENT_HEADER_SIZE + body.len());
+
+    frame.extend_from_slice(&[frame_control, 0x00, 0x00, 0x00]);
+    frame.extend_from_slice(&destination);
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&bssid);
+    frame.extend_from_slice(&[0x00, 0x00]);
+    frame.extend_from_slice(body);
+
+    frame
+}
+
+/// Reads the parameters of a scan command, which is followed by the frequencies of its channels.
+fn scan(message: &[u8]) -> Scan {
+    let command: nrf_wifi_umac_cmd_scan = unsliceit_padded(message);
+    let parameters = command.info.scan_params;
+
+    let ssids = parameters.scan_ssids[..usize::from(parameters.num_scan_ssids)]
+        .iter()
+        .map(|ssid| ssid.nrf_wifi_ssid[..usize::from(ssid.nrf_wifi_ssid_len)].to_vec())
+        .collect();
+
+    let frequencies = message[size_of::<nrf_wifi_umac_cmd_scan>()..]
+        .chunks_exact(4)
+        .take(usize::from(parameters.num_scan_channels))
+        .map(|frequency| u32::from_le_bytes(unwrap!(frequency.try_into())))
+        .collect();
+
+    let probe_ies = parameters.ie.ie[..usize::from(parameters.ie.ie_len)]
+        .iter()
+        .map(|byte| *byte as u8)
+        .collect();
+
+    Scan {
+        passive: parameters.passive_scan == 1,
+        ssids,
+        bssid: parameters.mac_addr,
+        bands: parameters.bands,
+        frequencies,
+        probe_ies,
+        dwell_time_active: parameters.dwell_time_active,
+        dwell_time_passive: parameters.dwell_time_passive,
+    }
+}
+
+impl Chip {
+    fn new(mac_address: [u8; 6]) -> Self {
+        let size = REGIONS.iter().map(|region| region.end + 1).max().unwrap_or(0);
+
+        Self {
+            memory: std::vec![0; size as usize],
+            sr2: 0,
+            bucken: false,
+            iovdd: false,
+            core_addresses: [0; 2],
+            queues: core::array::from_fn(|_| VecDeque::new()),
+            command_lengths: BTreeMap::new(),
+            partial_command: Vec::new(),
+            fragments: VecDeque::new(),
+            free_event_buffers: VecDeque::new(),
+            irq_waker: None,
+            boots: 0,
+            mac_address,
+            access_points: Vec::new(),
+            interface_up: false,
+            associated: None,
+            transmitted: Vec::new(),
+            scans: Vec::new(),
+            station_known: false,
+            management_frames: Vec::new(),
+        }
+    }
+
+    fn check_powered(&self) -> Result<(), SimulatorError> {
+        if self.bucken && self.iovdd {
+            Ok(())
+        } else {
+            Err(SimulatorError::PoweredOff)
+        }
+    }
+
+    /// Checks that the access of `words` words at `address` is within a memory region and returns
+    /// the latency of that region.
+    fn check_access(&self, address: u32, words: usize) -> Result<usize, SimulatorError> {
+        self.check_powered()?;
+
+        let end = address + 4 * words as u32;
+
+        REGIONS
+            .iter()
+            .find(|region| address >= region.start && end <= region.end + 1)
+            .map(|region| region.latency as usize)
+            .ok_or(SimulatorError::InvalidAddress(address))
+    }
+
+    fn irq(&self) -> bool {
+        !self.queues[EVENT_BUSY_QUEUE].is_empty()
+    }
+
+    fn wake_irq(&mut self) {
+        if let Some(waker) = self.irq_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn load(&self, address: u32, length: usize) -> &[u8] {
+        &self.memory[address as usize..address as usize + length]
+    }
+
+    fn store(&mut self, address: u32, bytes: &[u8]) {
+        self.memory[address as usize..address as usize + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn load_word(&self, address: u32) -> u32 {
+        u32::from_ne_bytes(unwrap!(self.load(address, 4).try_into()))
+    }
+
+    fn store_word(&mut self, address: u32, value: u32) {
+        self.store(address, &value.to_ne_bytes());
+    }
+
+    /// The queue and whether it is the dequeue register, for an address of the hostport queues.
+    fn queue_register(address: u32) -> Option<(usize, bool)> {
+        let offset = address.checked_sub(sysbus(QUEUE_BASE))? as usize;
+
+        (offset < QUEUES * 8 && offset % 4 == 0).then_some((offset / 8, offset % 8 == 4))
+    }
+
+    fn hostport_queues() -> host_rpu_hpqm_info {
+        let queue = |index: usize| host_rpu_hpq {
+            enqueue_addr: QUEUE_BASE + 8 * index as u32,
+            dequeue_addr: QUEUE_BASE + 8 * index as u32 + 4,
+        };
+
+        host_rpu_hpqm_info {
+            event_busy_queue: queue(EVENT_BUSY_QUEUE),
+            event_avl_queue: queue(EVENT_AVAILABLE_QUEUE),
+            cmd_busy_queue: queue(COMMAND_BUSY_QUEUE),
+            cmd_avl_queue: queue(COMMAND_AVAILABLE_QUEUE),
+            rx_buf_busy_queue: core::array::from_fn(|index| queue(RX_BUFFER_BUSY_QUEUE + index)),
+        }
+    }
+
+    fn is_command_buffer(address: u32) -> bool {
+        let start = bus_address(COMMAND_BUFFER_BASE, None);
+        let end = start + COMMAND_BUFFERS * COMMAND_BUFFER_SIZE;
+
+        (start..end).contains(&address)
+    }
+
+    fn read_register(&self, address: u32) -> u32 {
+        match Self::queue_register(address) {
+            Some((queue, true)) => self.queues[queue].front().copied().unwrap_or(0),
+            _ => self.load_word(address),
+        }
+    }
+
+    /// Handles a write of a single word to a register, returning whether it was taken by it rather
+    /// than written to memory.
+    fn write_register(&mut self, address: u32, value: u32) -> bool {
+        match address {
+            // The control registers clear themselves once the pulsed reset is done
+            LMAC_CONTROL | UMAC_CONTROL => {
+                if value & 0x1 != 0 {
+                    self.reset(if address == LMAC_CONTROL {
+                        ProcessorType::Lmac
+                    } else {
+                        ProcessorType::Umac
+                    });
+                }
+
+                true
+            }
+            LMAC_CORE_MEMORY_CONTROL => {
+                self.core_addresses[0] = value * 4;
+                true
+            }
+            UMAC_CORE_MEMORY_CONTROL => {
+                self.core_addresses[1] = value * 4;
+                true
+            }
+            LMAC_CORE_MEMORY_DATA | UMAC_CORE_MEMORY_DATA => {
+                let (index, processor) = if address == LMAC_CORE_MEMORY_DATA {
+                    (0, ProcessorType::Lmac)
+                } else {
+                    (1, ProcessorType::Umac)
+                };
+
+                let core_address = 0x8000_0000 | self.core_addresses[index];
+                self.store_word(bus_address(core_address, Some(processor)), value);
+                self.core_addresses[index] += 4;
+
+                true
+            }
+            TRIGGER => {
+                self.store_word(address, value);
+                self.process_commands();
+                true
+            }
+            _ => match Self::queue_register(address) {
+                Some((queue, true)) => {
+                    if self.queues[queue].front() == Some(&value) {
+                        self.queues[queue].pop_front();
+                        self.wake_irq();
+                    }
+
+                    true
+                }
+                Some((EVENT_AVAILABLE_QUEUE, false)) => {
+                    self.free_event_buffers.push_back(value);
+                    self.deliver_events();
+                    true
+                }
+                Some((queue, false)) => {
+                    self.queues[queue].push_back(value);
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Brings a processor out of reset, after which the firmware has booted.
+    fn reset(&mut self, processor: ProcessorType) {
+        match processor {
+            ProcessorType::Lmac => {
+                let exception = sysbus(LMAC_BOOT_EXCEPTION);
+                self.store_word(exception, self.load_word(exception) | 0x1);
+
+                self.store_word(bus_address(RPU_MEM_LMAC_BOOT_SIG, None), NRF_WIFI_LMAC_BOOT_SIG);
+                self.store_word(bus_address(RPU_MEM_RX_CMD_BASE, None), RX_COMMAND_BASE);
+
+                for queue in &mut self.queues[RX_BUFFER_BUSY_QUEUE..] {
+                    queue.clear();
+                }
+            }
+            ProcessorType::Umac => {
+                let exception = sysbus(UMAC_BOOT_EXCEPTION);
+                self.store_word(exception, self.load_word(exception) | 0x1);
+
+                let mac = self.mac_address;
+                let mut umac_info: host_rpu_umac_info = unsafe { zeroed() };
+                umac_info.boot_status = NRF_WIFI_UMAC_BOOT_SIG;
+                umac_info.version = FIRMWARE_VERSION;
+                umac_info.hpqm_info = Self::hostport_queues();
+                umac_info.mac_address0 = [
+                    u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]),
+                    u32::from_le_bytes([mac[4], mac[5], 0, 0]),
+                ];
+
+                self.store(bus_address(RPU_MEM_UMAC_BOOT_SIG, None), sliceit(&umac_info));
+
+                for queue in &mut self.queues[..RX_BUFFER_BUSY_QUEUE] {
+                    queue.clear();
+                }
+
+                self.queues[COMMAND_AVAILABLE_QUEUE]
+                    .extend((0..COMMAND_BUFFERS).map(|index| COMMAND_BUFFER_BASE + index * COMMAND_BUFFER_SIZE));
+                self.free_event_buffers = (0..EVENT_BUFFERS)
+                    .map(|index| EVENT_BUFFER_BASE + index * EVENT_BUFFER_SIZE)
+                    .collect();
+
+                self.command_lengths.clear();
+                self.partial_command.clear();
+                self.fragments.clear();
+                self.interface_up = false;
+                self.associated = None;
+            }
+        }
+    }
+
+    /// Takes the commands posted to the command queue. Control commands larger than
+    /// [`MAX_NRF_WIFI_UMAC_CMD_SIZE`] arrive in several chunks, each in a command buffer of its
+    /// own, while data commands are placed in a fixed slot per transmit descriptor.
+    fn process_commands(&mut self) {
+        let transmit_slots = RPU_MEM_TX_CMD_BASE..RPU_MEM_TX_CMD_BASE + RPU_DATA_CMD_SIZE_MAX_TX * MAX_TX_TOKENS as u32;
+
+        while let Some(address) = self.queues[COMMAND_BUSY_QUEUE].pop_front() {
+            if transmit_slots.contains(&address) {
+                self.handle_transmit(address);
+                continue;
+            }
+
+            let bus_address = bus_address(address, None);
+            let length = self.command_lengths.remove(&bus_address).unwrap_or(0);
+
+            let chunk = self.load(bus_address, length).to_vec();
+            self.partial_command.extend_from_slice(&chunk);
+            self.queues[COMMAND_AVAILABLE_QUEUE].push_back(address);
+
+            let header: host_rpu_msg = unsliceit_padded(&self.partial_command);
+
+            if length == MAX_NRF_WIFI_UMAC_CMD_SIZE as usize && self.partial_command.len() < header.hdr.len as usize {
+                continue;
+            }
+
+            let command = core::mem::take(&mut self.partial_command);
+            self.handle_command(&header, command.get(MESSAGE_HEADER_SIZE..).unwrap_or_default());
+        }
+    }
+
+    fn handle_command(&mut self, header: &host_rpu_msg, message: &[u8]) {
+        match nrf_wifi_host_rpu_msg_type::try_from(header.type_ as u32) {
+            Ok(nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_SYSTEM) => {
+                let header: nrf_wifi_sys_head = unsliceit_padded(message);
+
+                if header.cmd_event == nrf_wifi_sys_commands::NRF_WIFI_CMD_INIT as u32 {
+                    self.boots += 1;
+
+                    let event = nrf_wifi_sys_head {
+                        cmd_event: nrf_wifi_sys_events::NRF_WIFI_EVENT_INIT_DONE as u32,
+                        len: 0,
+                    };
+
+                    self.push_event(
+                        nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_SYSTEM,
+                        sliceit(&event),
+                    );
+                }
+            }
+            Ok(nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_UMAC) => self.handle_umac_command(message),
+            _ => warn!("Simulator got a command of unknown type {}", meh(header.type_)),
+        }
+    }
+
+    /// Answers a UMAC command like the firmware would. Commands for which the firmware sends
+    /// nothing back, or which are not simulated, are only taken.
+    fn handle_umac_command(&mut self, message: &[u8]) {
+        let header: nrf_wifi_umac_hdr = unsliceit_padded(message);
+        let command_id = header.cmd_evnt;
+
+        match nrf_wifi_umac_commands::try_from(command_id) {
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_CHANGE_MACADDR) => {
+                let command: nrf_wifi_umac_cmd_change_macaddr = unsliceit_padded(message);
+                self.mac_address = command.macaddr_info.mac_addr;
+                self.push_command_status(command_id, header.seq);
+            }
+            Ok(
+                nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_SET_POWER_SAVE
+                | nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_MCAST_FILTER
+                | nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_SET_STATION,
+            ) => self.push_command_status(command_id, header.seq),
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_SET_IFFLAGS) => {
+                let command: nrf_wifi_umac_cmd_chg_vif_state = unsliceit_padded(message);
+                self.interface_up = command.info.state == 1;
+
+                let mut event: nrf_wifi_umac_event_vif_state = unsafe { zeroed() };
+                event.umac_hdr = umac_header(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_IFFLAGS_STATUS);
+                self.push_umac_event(sliceit(&event));
+            }
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_TRIGGER_SCAN) => {
+                self.scans.push(scan(message));
+
+                let mut event: nrf_wifi_umac_event_trigger_scan = unsafe { zeroed() };
+                event.umac_hdr = umac_header(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_TRIGGER_SCAN_START);
+                self.push_umac_event(sliceit(&event));
+
+                let event = umac_header(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_SCAN_DONE);
+                self.push_umac_event(sliceit(&event));
+            }
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_GET_SCAN_RESULTS) => {
+                let access_points = self.access_points.clone();
+
+                // The sequence number counts down to zero for the last result
+                for (remaining, access_point) in access_points.iter().rev().enumerate().rev() {
+                    self.push_scan_result(access_point, remaining as u32);
+                }
+
+                // The status follows the results, and is all there is without any
+                self.push_command_status(command_id, header.seq);
+            }
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_AUTHENTICATE) => {
+                let command: nrf_wifi_umac_cmd_auth = unsliceit_padded(message);
+                let bssid = command.info.nrf_wifi_bssid;
+
+                // Open system authentication, transaction 2 with status success
+                let frame = self.access_point(bssid).map(|_| {
+                    management_frame(
+                        0xB0,
+                        self.mac_address,
+                        bssid,
+                        bssid,
+                        &[0x00, 0x00, 0x02, 0x00, 0x00, 0x00],
+                    )
+                });
+
+                self.push_mlme_event(
+                    nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_AUTHENTICATE,
+                    bssid,
+                    frame.as_deref(),
+                );
+            }
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_ASSOCIATE) => {
+                let command: nrf_wifi_umac_cmd_assoc = unsliceit_padded(message);
+                let bssid = command.connect_common_info.mac_addr;
+                let access_point = self.access_point(bssid).cloned();
+
+                // Capability of an ESS, status success and the association identifier
+                let frame = access_point.as_ref().map(|_| {
+                    management_frame(
+                        0x10,
+                        self.mac_address,
+                        bssid,
+                        bssid,
+                        &[0x01, 0x00, 0x00, 0x00, 0x01, 0xC0],
+                    )
+                });
+
+                self.push_mlme_event(
+                    nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_ASSOCIATE,
+                    bssid,
+                    frame.as_deref(),
+                );
+
+                if access_point.is_some() {
+                    self.associated = access_point;
+                    self.station_known = true;
+                    self.push_carrier_state(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_CARRIER_ON);
+                }
+            }
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_DEAUTHENTICATE) => {
+                let command: nrf_wifi_umac_cmd_disconn = unsliceit_padded(message);
+                self.deauthenticate(command.info.reason_code);
+            }
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_FRAME) => {
+                let command: nrf_wifi_umac_cmd_mgmt_tx = unsliceit_padded(message);
+                let length = (command.info.frame.frame_len as usize).min(command.info.frame.frame.len());
+                let frame: Vec<u8> = command.info.frame.frame[..length]
+                    .iter()
+                    .map(|byte| *byte as u8)
+                    .collect();
+
+                self.answer_sa_query(&frame);
+                self.management_frames.push(frame);
+            }
+            Ok(nrf_wifi_umac_commands::NRF_WIFI_UMAC_CMD_GET_WIPHY) => self.push_wiphy(),
+            Ok(command) => debug!("Simulator took UMAC command {:?} without answering", command),
+            Err(command_id) => warn!("Simulator got unknown UMAC command {}", command_id),
+        }
+    }
+
+    /// Ends the association with a deauthentication sent to the AP, as the firmware does when told
+    /// to or when the AP is lost.
+    fn deauthenticate(&mut self, reason: u16) {
+        let Some(access_point) = self.associated.take() else {
+            return;
+        };
+
+        let bssid = access_point.bssid;
+        let frame = management_frame(0xC0, bssid, self.mac_address, bssid, &reason.to_le_bytes());
+
+        self.push_mlme_event(
+            nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_DEAUTHENTICATE,
+            bssid,
+            Some(&frame),
+        );
+        self.push_carrier_state(nrf_wifi_umac_data_commands::NRF_WIFI_CMD_CARRIER_OFF);
+    }
+
+    /// Reports a deauthentication from the AP which is not protected, which the firmware leaves to
+    /// the driver to act on.
+    fn push_unprotected_deauthentication(&mut self, reason: u16) {
+        let Some(bssid) = self.associated.as_ref().map(|access_point| access_point.bssid) else {
+            return;
+        };
+
+        let frame = management_frame(0xC0, self.mac_address, bssid, bssid, &reason.to_le_bytes());
+
+        self.push_mlme_event(
+            nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_UNPROT_DEAUTHENTICATE,
+            bssid,
+            Some(&frame),
+        );
+    }
+
+    /// Has the AP answer an SA Query request as long as it knows the association.
+    fn answer_sa_query(&mut self, frame: &[u8]) {
+        /// SA Query category, request action and the transaction identifier
+        const REQUEST_SIZE: usize = MANAGEMENT_HEADER_SIZE + 4;
+
+        let Some(bssid) = self.associated.as_ref().map(|access_point| access_point.bssid) else {
+            return;
+        };
+
+        if !self.station_known || frame.len() != REQUEST_SIZE || frame[MANAGEMENT_HEADER_SIZE..][..2] != [8, 0] {
+            return;
+        }
+
+        let transaction = &frame[MANAGEMENT_HEADER_SIZE + 2..];
+        let response = management_frame(
+            0xD0,
+            self.mac_address,
+            bssid,
+            bssid,
+            &[8, 1, transaction[0], transaction[1]],
+        );
+
+        self.push_mlme_event(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_FRAME, bssid, Some(&response));
+    }
+
+    fn access_point(&self, bssid: [u8; 6]) -> Option<&AccessPoint> {
+        self.access_points
+            .iter()
+            .find(|access_point| access_point.bssid == bssid)
+    }
+
+    /// Reports a command as done, echoing its sequence number.
+    fn push_command_status(&mut self, command_id: u32, sequence: u32) {
+        let mut event: nrf_wifi_umac_event_cmd_status = unsafe { zeroed() };
+        event.umac_hdr = umac_header(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_CMD_STATUS);
+        event.umac_hdr.seq = sequence;
+        event.cmd_id = command_id;
+        event.cmd_status = 0;
+
+        self.push_umac_event(sliceit(&event));
+    }
+
+    fn push_scan_result(&mut self, access_point: &AccessPoint, sequence: u32) {
+        let channel = crate::control::channel(access_point.frequency);
+
+        let mut ies = Vec::new();
+        ies.extend_from_slice(&[0, access_point.ssid.len() as u8]);
+        ies.extend_from_slice(&access_point.ssid);
+        ies.extend_from_slice(&[3, 1, channel]);
+        ies.extend_from_slice(&access_point.elements);
+
+        let mut event: nrf_wifi_umac_event_new_scan_results = unsafe { zeroed() };
+        event.umac_hdr = umac_header(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_SCAN_RESULT);
+        event.umac_hdr.seq = sequence;
+        event.valid_fields = NRF_WIFI_EVENT_NEW_SCAN_RESULTS_IES_VALID;
+        event.frequency = access_point.frequency;
+        event.beacon_interval = 100;
+        event.capability = 0x0001;
+        event.signal.signal_type = NRF_WIFI_SIGNAL_TYPE_MBM;
+        event.signal.signal.mbm_signal = (access_point.rssi * 100) as u32;
+        event.mac_addr = access_point.bssid;
+        event.ies_len = ies.len() as u32;
+
+        let mut message = sliceit(&event).to_vec();
+        message.extend_from_slice(&ies);
+
+        self.push_umac_event(&message);
+    }
+
+    /// Pushes an MLME event with the management frame from the AP, or one telling that the AP did
+    /// not respond.
+    fn push_mlme_event(&mut self, kind: nrf_wifi_umac_events, bssid: [u8; 6], frame: Option<&[u8]>) {
+        let mut event: nrf_wifi_umac_event_mlme = unsafe { zeroed() };
+        event.umac_hdr = umac_header(kind);
+        event.mac_addr = bssid;
+
+        match frame {
+            Some(frame) => {
+                event.valid_fields = NRF_WIFI_EVENT_MLME_FRAME_VALID;
+                event.frame.frame_len = frame.len() as i32;
+
+                for (target, byte) in event.frame.frame.iter_mut().zip(frame) {
+                    *target = *byte as i8;
+                }
+            }
+            None => event.nrf_wifi_flags = NRF_WIFI_EVENT_MLME_TIMED_OUT,
+        }
+
+        self.push_umac_event(sliceit(&event));
+    }
+
+    /// The capabilities of a radio with only the 2.4 GHz band, channels 1 to 13.
+    fn push_wiphy(&mut self) {
+        let mut band: nrf_wifi_event_supported_band = unsafe { zeroed() };
+        band.band = nrf_wifi_band::NRF_WIFI_BAND_2GHZ as i8;
+        band.nrf_wifi_n_channels = 13;
+
+        for (index, channel) in band.channels.iter_mut().take(13).enumerate() {
+            channel.center_frequency = 2412 + 5 * index as u16;
+            channel.ch_valid = 1;
+            channel.nrf_wifi_max_power = 20;
+        }
+
+        let mut event: nrf_wifi_event_get_wiphy = unsafe { zeroed() };
+        event.umac_hdr = umac_header(nrf_wifi_umac_events::NRF_WIFI_UMAC_EVENT_NEW_WIPHY);
+        event.sband[0] = band;
+
+        self.push_umac_event(sliceit(&event));
+    }
+
+    fn push_carrier_state(&mut self, state: nrf_wifi_umac_data_commands) {
+        let event = nrf_wifi_data_carrier_state {
+            umac_head: nrf_wifi_umac_head {
+                cmd: state as u32,
+                len: size_of::<nrf_wifi_data_carrier_state>() as u32,
+            },
+            wdev_id: 0,
+        };
+
+        self.push_event(
+            nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_DATA,
+            sliceit(&event),
+        );
+    }
+
+    /// Takes the frames of a transmit command and hands the descriptor back.
+    fn handle_transmit(&mut self, address: u32) {
+        const INFO_SIZE: usize = size_of::<nrf_wifi_tx_buff_info>();
+
+        let command = self
+            .load(bus_address(address, None), RPU_DATA_CMD_SIZE_MAX_TX as usize)
+            .to_vec();
+        let header: nrf_wifi_tx_buff = unsliceit_padded(&command);
+        let packets = usize::from(header.num_tx_pkts);
+
+        for index in 0..packets {
+            let offset = size_of::<nrf_wifi_tx_buff>() + index * INFO_SIZE;
+            let info: nrf_wifi_tx_buff_info = unsliceit_padded(command.get(offset..).unwrap_or_default());
+
+            let frame = self
+                .load(bus_address(info.ddr_ptr, None), usize::from(info.pkt_length))
+                .to_vec();
+            self.transmitted.push(frame);
+        }
+
+        let done = nrf_wifi_tx_buff_done {
+            umac_head: nrf_wifi_umac_head {
+                cmd: nrf_wifi_umac_data_commands::NRF_WIFI_CMD_TX_BUFF_DONE as u32,
+                len: (size_of::<nrf_wifi_tx_buff_done>() + packets) as u32,
+            },
+            tx_desc_num: header.tx_desc_num,
+            num_tx_status_code: packets as u8,
+            timestamp_t1: [0; 6],
+            timestamp_t4: [0; 6],
+            tx_status_code: __IncompleteArrayField::new(),
+        };
+
+        let mut message = sliceit(&done).to_vec();
+        message.resize(message.len() + packets, 0);
+
+        self.push_event(nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_DATA, &message);
+    }
+
+    fn receive(&mut self, frame: &[u8]) -> Result<(), SimulatorError> {
+        const ETH_HEADER_SIZE: usize = NRF_WIFI_FMAC_ETH_HDR_LEN as usize;
+
+        // RFC 1042 encapsulation of the Ethernet payload
+        const LLC_HEADER: [u8; 6] = [0xAA, 0xAA, 0x03, 0x00, 0x00, 0x00];
+
+        if frame.len() < ETH_HEADER_SIZE
+            || MANAGEMENT_HEADER_SIZE + LLC_HEADER.len() + frame.len() - 12 > RX_MAX_DATA_SIZE
+        {
+            return Err(SimulatorError::InvalidFrame);
+        }
+
+        let (queue, command_address) = self.queues[RX_BUFFER_BUSY_QUEUE..]
+            .iter_mut()
+            .enumerate()
+            .find_map(|(queue, addresses)| addresses.pop_front().map(|address| (queue, address)))
+            .ok_or(SimulatorError::NoReceiveBuffer)?;
+
+        // The receive command holds the address of the data, which is preceded by the descriptor
+        let command: host_rpu_rx_buf_info =
+            unsliceit_padded(self.load(bus_address(command_address, Some(ProcessorType::Lmac)), 4));
+        let descriptor_identifier = self.load_word(bus_address(command.addr - RX_BUF_HEADROOM, None));
+
+        let (bssid, frequency) = self
+            .associated
+            .as_ref()
+            .map_or(([0; 6], 0), |access_point| (access_point.bssid, access_point.frequency));
+
+        // A data frame from the distribution system: the destination, BSSID and source address
+        let mut mpdu = Vec::with_capacity(RX_MAX_DATA_SIZE);
+        mpdu.extend_from_slice(&[0x08, 0x02, 0x00, 0x00]);
+        mpdu.extend_from_slice(&frame[0..6]);
+        mpdu.extend_from_slice(&bssid);
+        mpdu.extend_from_slice(&frame[6..12]);
+        mpdu.extend_from_slice(&[0x00, 0x00]);
+        mpdu.extend_from_slice(&LLC_HEADER);
+        mpdu.extend_from_slice(&frame[12..]);
+
+        self.store(bus_address(command.addr, None), &mpdu);
+
+        let mut event: nrf_wifi_rx_buff = unsafe { zeroed() };
+        event.umac_head = nrf_wifi_umac_head {
+            cmd: nrf_wifi_umac_data_commands::NRF_WIFI_CMD_RX_BUFF as u32,
+            len: (size_of::<nrf_wifi_rx_buff>() + size_of::<nrf_wifi_rx_buff_info>()) as u32,
+        };
+        event.rx_pkt_type = nrf_wifi_rx_pkt_type::NRF_WIFI_RX_PKT_DATA as i16;
+        event.rx_pkt_cnt = 1;
+        event.mac_header_len = MANAGEMENT_HEADER_SIZE as u8;
+        event.frequency = frequency as u16;
+
+        let mut info: nrf_wifi_rx_buff_info = unsafe { zeroed() };
+        info.descriptor_id = descriptor_identifier as u16;
+        info.rx_pkt_len = mpdu.len() as u16;
+        info.pkt_type = PKT_TYPE_MPDU as u8;
+
+        let mut message = sliceit(&event).to_vec();
+        message.extend_from_slice(sliceit(&info));
+
+        debug!(
+            "Simulator received a frame in queue {}, descriptor {}",
+            queue, descriptor_identifier
+        );
+
+        self.push_event(nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_DATA, &message);
+
+        Ok(())
+    }
+
+    fn push_umac_event(&mut self, message: &[u8]) {
+        self.push_event(nrf_wifi_host_rpu_msg_type::NRF_WIFI_HOST_RPU_MSG_TYPE_UMAC, message);
+    }
+
+    /// Queues an event, split in fragments of [`MAX_EVENT_POOL_LEN`] bytes which each take an
+    /// event buffer.
+    fn push_event(&mut self, kind: nrf_wifi_host_rpu_msg_type, message: &[u8]) {
+        let header = host_rpu_msg {
+            hdr: host_rpu_msg_hdr {
+                len: (MESSAGE_HEADER_SIZE + message.len()) as u32,
+                resubmit: 1,
+            },
+            type_: kind as i32,
+            msg: __IncompleteArrayField::new(),
+        };
+
+        let mut event = sliceit(&header).to_vec();
+        event.extend_from_slice(message);
+
+        self.fragments
+            .extend(event.chunks(MAX_EVENT_POOL_LEN as usize).map(<[u8]>::to_vec));
+
+        self.deliver_events();
+    }
+
+    /// Places the pending event fragments in the free event buffers and posts them.
+    fn deliver_events(&mut self) {
+        while !self.fragments.is_empty() {
+            let Some(address) = self.free_event_buffers.pop_front() else {
+                break;
+            };
+
+            let fragment = unwrap!(self.fragments.pop_front());
+            self.store(bus_address(address, None), &fragment);
+            self.queues[EVENT_BUSY_QUEUE].push_back(address);
+        }
+
+        if self.irq() {
+            self.wake_irq();
+        }
+    }
+}
@@ -0,0 +1,566 @@
+//! A minimal WPA2-Personal and WPA3-Personal supplicant.
+//!
+//! Runs the 4-way and group key handshakes of IEEE 802.11-2020 12.7.6 and 12.7.7 for networks
+//! using PSK or SAE authentication with CCMP as pairwise and group cipher, and BIP-CMAC-128 for
+//! management frame protection when the AP supports it. The supplicant only
+//! processes EAPOL-Key frames and hands out the frames to send back and the keys to install, so it
+//! can be verified on a host. The SAE exchange itself, which gives the PMK for the handshake, is
+//! done by [`sae`].
+
+use heapless::Vec;
+use rand_core::CryptoRngCore;
+
+use crate::ie::{id, Decode, Elements, Rsn, Suite};
+
+use crypto::{calculate_mic, prf, unwrap_key_data, verify_mic, Ptk, NONCE_LENGTH, PMK_LENGTH};
+use eapol::{key_information, write_key_frame, KeyFrame, KEY_FRAME_SIZE};
+
+pub mod crypto;
+pub mod eapol;
+pub mod sae;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SupplicantError {
+    /// The passphrase is not 8 to 63 printable ASCII characters
+    InvalidPassphrase,
+
+    /// The network does not offer the authentication with CCMP (and management frame protection
+    /// for SAE) we asked for
+    UnsupportedNetwork,
+
+    InvalidFrame,
+
+    InvalidMic,
+
+    /// The replay counter of the frame is not larger than the one of the last valid frame
+    Replayed,
+
+    InvalidKeyData,
+
+    /// The RSN element in message 3 differs from the one the AP advertised in its beacon
+    RsnElementMismatch,
+
+    /// The frame is not expected in the current state of the handshake
+    UnexpectedFrame,
+
+    /// The scalar or element of a SAE commit is not valid
+    InvalidCommit,
+
+    /// The status of the AP's SAE commit tells of the other way to derive the password element than
+    /// the one of our commit
+    CommitStatusMismatch,
+
+    /// The SAE confirm does not match, i.e. the AP does not know the password
+    InvalidConfirm,
+
+    BufferTooSmall,
+}
+
+const RSN_OUI: [u8; 3] = [0x00, 0x0f, 0xac];
+const KDE_TYPE_GTK: u8 = 1;
+const KDE_TYPE_IGTK: u8 = 9;
+
+/// Length of the CCMP temporal keys.
+pub const KEY_LENGTH: usize = 16;
+
+/// The RSN element for PSK: version 1, CCMP as group and pairwise cipher and no capabilities.
+pub const RSN_ELEMENT_PSK: [u8; 22] = [
+    0x30, 20, 1, 0, 0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 2, 0x00, 0x00,
+];
+
+/// The RSN element for PSK with management frame protection capable, used when the AP supports
+/// it. BIP-CMAC-128 is implied as group management cipher.
+pub const RSN_ELEMENT_PSK_MFP: [u8; 22] = [
+    0x30, 20, 1, 0, 0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 2, 0x80, 0x00,
+];
+
+/// The RSN element for SAE: version 1, CCMP as group and pairwise cipher and management frame
+/// protection required, which WPA3 mandates.
+pub const RSN_ELEMENT_SAE: [u8; 22] = [
+    0x30, 20, 1, 0, 0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 4, 1, 0, 0x00, 0x0f, 0xac, 8, 0xc0, 0x00,
+];
+
+/// The RSN extension element announcing SAE hash-to-element, sent along with the RSN element when
+/// the password element is derived with hash-to-element.
+pub const RSNXE_HASH_TO_ELEMENT: [u8; 3] = [0xf4, 1, 0x20];
+
+/// Longest set of elements we send in the association request and message 2.
+const MAX_OWN_ELEMENTS_LENGTH: usize = 32;
+
+/// Authentication and key management suites supported by the supplicant.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Akm {
+    /// Pre-shared key derived from a passphrase (WPA2-Personal), 00-0F-AC:2
+    Psk,
+
+    /// Simultaneous authentication of equals (WPA3-Personal), 00-0F-AC:8
+    Sae,
+}
+
+impl Akm {
+    fn suite_type(self) -> u8 {
+        match self {
+            Akm::Psk => 2,
+            Akm::Sae => 8,
+        }
+    }
+
+    /// The AKM suite selector in the format of the UMAC commands.
+    pub fn suite(self) -> u32 {
+        0x000f_ac00 | u32::from(self.suite_type())
+    }
+
+    /// The RSN element to send in the association request, depending on whether management frame
+    /// protection is used, see [`check_rsn_element`].
+    pub fn rsn_element(self, management_frame_protection: bool) -> &'static [u8] {
+        match (self, management_frame_protection) {
+            (Akm::Psk, false) => &RSN_ELEMENT_PSK,
+            (Akm::Psk, true) => &RSN_ELEMENT_PSK_MFP,
+            (Akm::Sae, _) => &RSN_ELEMENT_SAE,
+        }
+    }
+
+    fn descriptor_version(self) -> u16 {
+        match self {
+            Akm::Psk => key_information::DESCRIPTOR_VERSION_HMAC_SHA1_AES,
+            Akm::Sae => key_information::DESCRIPTOR_VERSION_AKM_DEFINED,
+        }
+    }
+}
+
+/// Longest RSN element of an AP we keep around to compare with message 3.
+const MAX_RSN_ELEMENT_LENGTH: usize = 64;
+
+/// A group key handed out by the authenticator.
+#[derive(Clone, Debug)]
+pub struct GroupKey {
+    pub index: u8,
+    pub key: [u8; KEY_LENGTH],
+
+    /// Receive sequence counter to start from, 6 bytes little endian
+    pub rsc: [u8; 6],
+}
+
+/// An integrity group key (IGTK) protecting broadcast management frames with BIP-CMAC-128.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntegrityGroupKey {
+    /// Key index, 4 or 5
+    pub index: u8,
+    pub key: [u8; KEY_LENGTH],
+
+    /// Packet number of the last protected frame, 6 bytes little endian
+    pub ipn: [u8; 6],
+}
+
+/// The result of processing an EAPOL-Key frame.
+#[derive(Default)]
+pub struct Outcome {
+    /// Length of the EAPOL-Key frame written to the response buffer, which has to be sent to the
+    /// authenticator before the keys are installed
+    pub response_length: Option<usize>,
+
+    /// Pairwise temporal key to install, given when the 4-way handshake completes
+    pub pairwise_key: Option<[u8; KEY_LENGTH]>,
+
+    /// Group key to install
+    pub group_key: Option<GroupKey>,
+
+    /// Integrity group key to install, given when management frame protection is used
+    pub integrity_group_key: Option<IntegrityGroupKey>,
+}
+
+#[derive(Clone)]
+pub struct Supplicant {
+    akm: Akm,
+    pmk: [u8; PMK_LENGTH],
+
+    /// SNonce of the current handshake
+    snonce: [u8; NONCE_LENGTH],
+
+    /// Key and counter the SNonces of later handshakes are derived from, as suggested by
+    /// IEEE 802.11-2020 12.7.5
+    nonce_key: [u8; NONCE_LENGTH],
+    nonce_counter: u64,
+
+    own_address: [u8; 6],
+    own_elements: Vec<u8, MAX_OWN_ELEMENTS_LENGTH>,
+    authenticator_address: [u8; 6],
+    authenticator_rsn_element: Vec<u8, MAX_RSN_ELEMENT_LENGTH>,
+    management_frame_protection: bool,
+
+    /// Nonce and PTK derived from message 1, confirmed by message 3
+    anonce: Option<[u8; NONCE_LENGTH]>,
+    tentative_ptk: Option<Ptk>,
+
+    ptk: Option<Ptk>,
+    replay_counter: Option<u64>,
+}
+
+impl Supplicant {
+    /// Creates a supplicant for the handshakes with the authenticator that advertised
+    /// `authenticator_rsn_element`. `own_elements` are the RSN element (and RSN extension element)
+    /// sent in the association request, which are repeated in message 2.
+    ///
+    /// The SNonce of the first handshake is taken from `rng`, along with the key the SNonces of
+    /// later ones are derived from.
+    pub fn new(
+        akm: Akm,
+        pmk: [u8; PMK_LENGTH],
+        rng: &mut dyn CryptoRngCore,
+        own_address: [u8; 6],
+        own_elements: &[u8],
+        authenticator_address: [u8; 6],
+        authenticator_rsn_element: &[u8],
+    ) -> Result<Self, SupplicantError> {
+        let management_frame_protection = check_rsn_element(authenticator_rsn_element, akm)?;
+
+        let mut snonce = [0u8; NONCE_LENGTH];
+        let mut nonce_key = [0u8; NONCE_LENGTH];
+        rng.fill_bytes(&mut snonce);
+        rng.fill_bytes(&mut nonce_key);
+
+        Ok(Supplicant {
+            akm,
+            pmk,
+            snonce,
+            nonce_key,
+            nonce_counter: 0,
+            own_address,
+            own_elements: Vec::from_slice(own_elements).map_err(|()| SupplicantError::BufferTooSmall)?,
+            authenticator_address,
+            authenticator_rsn_element: Vec::from_slice(authenticator_rsn_element)
+                .map_err(|()| SupplicantError::UnsupportedNetwork)?,
+            management_frame_protection,
+            anonce: None,
+            tentative_ptk: None,
+            ptk: None,
+            replay_counter: None,
+        })
+    }
+
+    pub fn own_address(&self) -> [u8; 6] {
+        self.own_address
+    }
+
+    pub fn authenticator_address(&self) -> [u8; 6] {
+        self.authenticator_address
+    }
+
+    /// Whether management frame protection is used with the authenticator.
+    pub fn management_frame_protection(&self) -> bool {
+        self.management_frame_protection
+    }
+
+    /// Processes an EAPOL frame (without the Ethernet header) from the authenticator, writing the
+    /// response to `response`.
+    pub fn process(&mut self, frame: &[u8], response: &mut [u8]) -> Result<Outcome, SupplicantError> {
+        use key_information::*;
+
+        let frame = KeyFrame::parse(frame)?;
+        let key_information = frame.key_information();
+
+        if key_information & DESCRIPTOR_VERSION_MASK != self.akm.descriptor_version() {
+            return Err(SupplicantError::UnsupportedNetwork);
+        }
+
+        if !frame.has(KEY_ACK) || frame.has(REQUEST) || frame.has(ERROR) {
+            return Err(SupplicantError::UnexpectedFrame);
+        }
+
+        match (frame.has(KEY_TYPE_PAIRWISE), frame.has(KEY_MIC)) {
+            (true, false) => self.process_message_1(&frame, response),
+            (true, true) if frame.has(INSTALL | ENCRYPTED_KEY_DATA) => self.process_message_3(&frame, response),
+            (false, true) if frame.has(SECURE | ENCRYPTED_KEY_DATA) => self.process_group_message_1(&frame, response),
+            _ => Err(SupplicantError::UnexpectedFrame),
+        }
+    }
+
+    fn process_message_1(&mut self, frame: &KeyFrame, response: &mut [u8]) -> Result<Outcome, SupplicantError> {
+        use key_information::*;
+
+        let anonce = *frame.nonce();
+
+        // The AP repeats message 1 if it missed message 2, which is answered with the same SNonce.
+        // Any other message 1 starts a new handshake, e.g. to rekey, which gets a new one.
+        let retransmission = self.tentative_ptk.is_some() && self.anonce == Some(anonce);
+
+        if self.anonce.is_some() && !retransmission {
+            self.next_snonce();
+        }
+
+        let ptk = Ptk::derive(
+            self.akm,
+            &self.pmk,
+            &self.authenticator_address,
+            &self.own_address,
+            &anonce,
+            &self.snonce,
+        );
+
+        let length = write_key_frame(
+            response,
+            self.akm.descriptor_version() | KEY_TYPE_PAIRWISE | KEY_MIC,
+            frame.replay_counter(),
+            &self.snonce,
+            &self.own_elements,
+        )?;
+
+        self.sign(&ptk, &mut response[..length]);
+
+        self.anonce = Some(anonce);
+        self.tentative_ptk = Some(ptk);
+
+        Ok(Outcome {
+            response_length: Some(length),
+            ..Outcome::default()
+        })
+    }
+
+    /// Derives the SNonce for a new handshake.
+    fn next_snonce(&mut self) {
+        self.nonce_counter += 1;
+
+        let mut data = [0u8; 14];
+        data[..6].copy_from_slice(&self.own_address);
+        data[6..].copy_from_slice(&self.nonce_counter.to_be_bytes());
+
+        prf(&self.nonce_key, b"Init Counter", &data, &mut self.snonce);
+    }
+
+    fn process_message_3(&mut self, frame: &KeyFrame, response: &mut [u8]) -> Result<Outcome, SupplicantError> {
+        use key_information::*;
+
+        // The AP retransmits message 3 if message 4 got lost, which is answered with the PTK in use
+        let retransmission = self.tentative_ptk.is_none();
+
+        let (Some(ptk), Some(anonce)) = (self.tentative_ptk.clone().or(self.ptk.clone()), self.anonce) else {
+            return Err(SupplicantError::UnexpectedFrame);
+        };
+
+        if *frame.nonce() != anonce {
+            return Err(SupplicantError::UnexpectedFrame);
+        }
+
+        self.verify(&ptk, frame)?;
+
+        let mut key_data = [0u8; 256];
+        let key_data = decrypt_key_data(&ptk, frame, &mut key_data)?;
+
+        // Protects against a downgrade of the security by a forged beacon
+        let rsn_element = Elements::new(key_data)
+            .find_id(id::RSN)
+            .ok_or(SupplicantError::RsnElementMismatch)?;
+        if rsn_element.as_bytes() != &self.authenticator_rsn_element[..] {
+            return Err(SupplicantError::RsnElementMismatch);
+        }
+
+        let (group_key, integrity_group_key) = self.parse_group_keys(key_data, frame.rsc())?;
+
+        let length = write_key_frame(
+            response,
+            self.akm.descriptor_version() | KEY_TYPE_PAIRWISE | KEY_MIC | SECURE,
+            frame.replay_counter(),
+            &[0; NONCE_LENGTH],
+            &[],
+        )?;
+
+        self.sign(&ptk, &mut response[..length]);
+
+        self.replay_counter = Some(frame.replay_counter());
+
+        // Installing the keys again would reset their packet numbers
+        if retransmission {
+            return Ok(Outcome {
+                response_length: Some(length),
+                ..Outcome::default()
+            });
+        }
+
+        let pairwise_key = ptk.tk;
+
+        self.tentative_ptk = None;
+        self.ptk = Some(ptk);
+
+        Ok(Outcome {
+            response_length: Some(length),
+            pairwise_key: Some(pairwise_key),
+            group_key: Some(group_key),
+            integrity_group_key,
+        })
+    }
+
+    fn process_group_message_1(&mut self, frame: &KeyFrame, response: &mut [u8]) -> Result<Outcome, SupplicantError> {
+        use key_information::*;
+
+        let Some(ptk) = self.ptk.clone() else {
+            return Err(SupplicantError::UnexpectedFrame);
+        };
+
+        self.verify(&ptk, frame)?;
+
+        let mut key_data = [0u8; 256];
+        let key_data = decrypt_key_data(&ptk, frame, &mut key_data)?;
+
+        let (group_key, integrity_group_key) = self.parse_group_keys(key_data, frame.rsc())?;
+
+        let length = write_key_frame(
+            response,
+            self.akm.descriptor_version() | KEY_MIC | SECURE,
+            frame.replay_counter(),
+            &[0; NONCE_LENGTH],
+            &[],
+        )?;
+
+        self.sign(&ptk, &mut response[..length]);
+
+        self.replay_counter = Some(frame.replay_counter());
+
+        Ok(Outcome {
+            response_length: Some(length),
+            group_key: Some(group_key),
+            integrity_group_key,
+            ..Outcome::default()
+        })
+    }
+
+    /// Finds the GTK and, with management frame protection, the IGTK in decrypted key data.
+    fn parse_group_keys(
+        &self,
+        key_data: &[u8],
+        rsc: [u8; 6],
+    ) -> Result<(GroupKey, Option<IntegrityGroupKey>), SupplicantError> {
+        let mut group_key = None;
+        let mut integrity_group_key = None;
+
+        for element in Elements::new(key_data) {
+            let data = element.data;
+
+            if element.id != id::VENDOR_SPECIFIC || data.len() < 4 || data[..3] != RSN_OUI {
+                continue;
+            }
+
+            match data[3] {
+                KDE_TYPE_GTK => group_key = Some(parse_gtk_kde(&data[4..], rsc)?),
+                KDE_TYPE_IGTK if self.management_frame_protection => {
+                    integrity_group_key = Some(parse_igtk_kde(&data[4..])?);
+                }
+                _ => {}
+            }
+        }
+
+        let group_key = group_key.ok_or(SupplicantError::InvalidKeyData)?;
+
+        if self.management_frame_protection && integrity_group_key.is_none() {
+            return Err(SupplicantError::InvalidKeyData);
+        }
+
+        Ok((group_key, integrity_group_key))
+    }
+
+    /// Checks the replay counter and MIC of a frame.
+    fn verify(&self, ptk: &Ptk, frame: &KeyFrame) -> Result<(), SupplicantError> {
+        if self
+            .replay_counter
+            .is_some_and(|replay_counter| frame.replay_counter() <= replay_counter)
+        {
+            return Err(SupplicantError::Replayed);
+        }
+
+        let mut buffer = [0u8; KEY_FRAME_SIZE + 256];
+        let unsigned = frame.copy_without_mic(&mut buffer)?;
+
+        verify_mic(self.akm, &ptk.kck, unsigned, frame.mic())
+    }
+
+    /// Calculates and sets the MIC of a frame written with [`write_key_frame`].
+    fn sign(&self, ptk: &Ptk, frame: &mut [u8]) {
+        let mic = calculate_mic(self.akm, &ptk.kck, frame);
+        eapol::set_mic(frame, &mic);
+    }
+}
+
+fn decrypt_key_data<'a>(ptk: &Ptk, frame: &KeyFrame, buffer: &'a mut [u8]) -> Result<&'a [u8], SupplicantError> {
+    let wrapped = frame.key_data();
+
+    if wrapped.len() < 16 || wrapped.len() % 8 != 0 || wrapped.len() - 8 > buffer.len() {
+        return Err(SupplicantError::InvalidKeyData);
+    }
+
+    let key_data = &mut buffer[..wrapped.len() - 8];
+    unwrap_key_data(&ptk.kek, wrapped, key_data)?;
+
+    Ok(key_data)
+}
+
+/// Parses the data of a GTK KDE: key ID and Tx flag, a reserved byte and the key itself.
+fn parse_gtk_kde(data: &[u8], rsc: [u8; 6]) -> Result<GroupKey, SupplicantError> {
+    if data.len() != 2 + KEY_LENGTH {
+        return Err(SupplicantError::UnsupportedNetwork);
+    }
+
+    let mut key = [0u8; KEY_LENGTH];
+    key.copy_from_slice(&data[2..]);
+
+    Ok(GroupKey {
+        index: data[0] & 0x03,
+        key,
+        rsc,
+    })
+}
+
+/// Parses the data of an IGTK KDE: key ID, IPN and the key itself.
+fn parse_igtk_kde(data: &[u8]) -> Result<IntegrityGroupKey, SupplicantError> {
+    if data.len() != 2 + 6 + KEY_LENGTH {
+        return Err(SupplicantError::UnsupportedNetwork);
+    }
+
+    let index = u16::from_le_bytes([data[0], data[1]]);
+
+    if !(4..=5).contains(&index) {
+        return Err(SupplicantError::InvalidKeyData);
+    }
+
+    let mut ipn = [0u8; 6];
+    ipn.copy_from_slice(&data[2..8]);
+
+    let mut key = [0u8; KEY_LENGTH];
+    key.copy_from_slice(&data[8..]);
+
+    Ok(IntegrityGroupKey {
+        index: index as u8,
+        key,
+        ipn,
+    })
+}
+
+/// Checks that the RSN element of an AP offers CCMP and the given AKM suite, and negotiates
+/// management frame protection (IEEE 802.11-2020 12.6.3): it is used when the AP is capable of it
+/// with BIP-CMAC-128, which SAE requires. Returns whether management frame protection is used.
+pub fn check_rsn_element(element: &[u8], akm: Akm) -> Result<bool, SupplicantError> {
+    if element.len() < 2 || element[0] != id::RSN || usize::from(element[1]) + 2 != element.len() {
+        return Err(SupplicantError::UnsupportedNetwork);
+    }
+
+    let rsn = Rsn::decode(&element[2..]).ok_or(SupplicantError::UnsupportedNetwork)?;
+
+    if rsn.version != 1
+        || rsn.group_cipher != Suite::CCMP_128
+        || !rsn.pairwise_ciphers().any(|suite| suite == Suite::CCMP_128)
+        || !rsn.akm_suites().any(|suite| suite == Suite::rsn(akm.suite_type()))
+    {
+        return Err(SupplicantError::UnsupportedNetwork);
+    }
+
+    let bip_cmac_128 = rsn
+        .group_management_cipher
+        .is_none_or(|suite| suite == Suite::BIP_CMAC_128);
+    let management_frame_protection = rsn.capabilities & Rsn::CAPABILITY_MFPC != 0 && bip_cmac_128;
+
+    if !management_frame_protection && (akm == Akm::Sae || rsn.capabilities & Rsn::CAPABILITY_MFPR != 0) {
+        return Err(SupplicantError::UnsupportedNetwork);
+    }
+
+    Ok(management_frame_protection)
+}
@@ -0,0 +1,185 @@
+//! Key derivation and integrity primitives used by the RSN handshakes (IEEE 802.11-2020 12.7).
+
+use aes::Aes128;
+use aes_kw::KekAes128;
+use cmac::Cmac;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::Sha256;
+
+use super::{Akm, SupplicantError};
+
+type HmacSha1 = Hmac<Sha1>;
+pub(crate) type HmacSha256 = Hmac<Sha256>;
+
+pub const PMK_LENGTH: usize = 32;
+pub const NONCE_LENGTH: usize = 32;
+pub const MIC_LENGTH: usize = 16;
+
+const KCK_LENGTH: usize = 16;
+const KEK_LENGTH: usize = 16;
+const TK_LENGTH: usize = 16;
+
+const PSK_ITERATIONS: u32 = 4096;
+
+/// Derives the PMK from a passphrase with PBKDF2-SHA1, as described in IEEE 802.11-2020 J.4.1.
+///
+/// The passphrase has to be between 8 and 63 printable ASCII characters.
+pub fn derive_pmk(passphrase: &[u8], ssid: &[u8]) -> Result<[u8; PMK_LENGTH], SupplicantError> {
+    if !(8..=63).contains(&passphrase.len()) || !passphrase.iter().all(|c| (32..=126).contains(c)) {
+        return Err(SupplicantError::InvalidPassphrase);
+    }
+
+    let mut pmk = [0u8; PMK_LENGTH];
+    pbkdf2::pbkdf2::<HmacSha1>(passphrase, ssid, PSK_ITERATIONS, &mut pmk)
+        .map_err(|_| SupplicantError::InvalidPassphrase)?;
+
+    Ok(pmk)
+}
+
+/// The SHA1 based pseudo random function of IEEE 802.11-2020 12.7.1.2, filling all of `output`.
+pub fn prf(key: &[u8], label: &[u8], data: &[u8], output: &mut [u8]) {
+    for (counter, chunk) in output.chunks_mut(20).enumerate() {
+        let mut mac = HmacSha1::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(label);
+        mac.update(&[0]);
+        mac.update(data);
+        mac.update(&[counter as u8]);
+
+        let digest = mac.finalize().into_bytes();
+        chunk.copy_from_slice(&digest[..chunk.len()]);
+    }
+}
+
+/// The SHA-256 based key derivation function of IEEE 802.11-2020 12.7.1.7.2 (KDF-Hash-Length),
+/// filling all of `output`.
+pub fn kdf_sha256(key: &[u8], label: &[u8], context: &[u8], output: &mut [u8]) {
+    let length = (output.len() * 8) as u16;
+
+    for (counter, chunk) in output.chunks_mut(32).enumerate() {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(&(counter as u16 + 1).to_le_bytes());
+        mac.update(label);
+        mac.update(context);
+        mac.update(&length.to_le_bytes());
+
+        let digest = mac.finalize().into_bytes();
+        chunk.copy_from_slice(&digest[..chunk.len()]);
+    }
+}
+
+/// The pairwise transient key for CCMP, split into its parts.
+#[derive(Clone)]
+pub struct Ptk {
+    /// Key confirmation key, protects the integrity of EAPOL-Key frames
+    pub kck: [u8; KCK_LENGTH],
+
+    /// Key encryption key, protects the key data of EAPOL-Key frames
+    pub kek: [u8; KEK_LENGTH],
+
+    /// Temporal key, the pairwise key installed for the data frames
+    pub tk: [u8; TK_LENGTH],
+}
+
+impl Ptk {
+    /// Derives the PTK from the PMK, the addresses of the authenticator and supplicant and their
+    /// nonces, as described in IEEE 802.11-2020 12.7.1.3. PSK uses the SHA1 PRF, SAE the SHA-256
+    /// KDF.
+    pub fn derive(
+        akm: Akm,
+        pmk: &[u8; PMK_LENGTH],
+        authenticator_address: &[u8; 6],
+        supplicant_address: &[u8; 6],
+        anonce: &[u8; NONCE_LENGTH],
+        snonce: &[u8; NONCE_LENGTH],
+    ) -> Self {
+        let (min_address, max_address) = if authenticator_address < supplicant_address {
+            (authenticator_address, supplicant_address)
+        } else {
+            (supplicant_address, authenticator_address)
+        };
+
+        let (min_nonce, max_nonce) = if anonce < snonce {
+            (anonce, snonce)
+        } else {
+            (snonce, anonce)
+        };
+
+        let mut data = [0u8; 2 * 6 + 2 * NONCE_LENGTH];
+        data[..6].copy_from_slice(min_address);
+        data[6..12].copy_from_slice(max_address);
+        data[12..44].copy_from_slice(min_nonce);
+        data[44..].copy_from_slice(max_nonce);
+
+        let mut ptk = [0u8; KCK_LENGTH + KEK_LENGTH + TK_LENGTH];
+        match akm {
+            Akm::Psk => prf(pmk, b"Pairwise key expansion", &data, &mut ptk),
+            Akm::Sae => kdf_sha256(pmk, b"Pairwise key expansion", &data, &mut ptk),
+        }
+
+        let mut result = Ptk {
+            kck: [0; KCK_LENGTH],
+            kek: [0; KEK_LENGTH],
+            tk: [0; TK_LENGTH],
+        };
+
+        result.kck.copy_from_slice(&ptk[..KCK_LENGTH]);
+        result.kek.copy_from_slice(&ptk[KCK_LENGTH..KCK_LENGTH + KEK_LENGTH]);
+        result.tk.copy_from_slice(&ptk[KCK_LENGTH + KEK_LENGTH..]);
+
+        result
+    }
+}
+
+/// Calculates the MIC of an EAPOL-Key frame, HMAC-SHA1-128 for PSK and AES-128-CMAC for SAE. The
+/// MIC field of the frame has to be zeroed.
+pub fn calculate_mic(akm: Akm, kck: &[u8; KCK_LENGTH], frame: &[u8]) -> [u8; MIC_LENGTH] {
+    let mut mic = [0u8; MIC_LENGTH];
+
+    match akm {
+        Akm::Psk => {
+            let mut mac = HmacSha1::new_from_slice(kck).expect("HMAC accepts any key length");
+            mac.update(frame);
+            mic.copy_from_slice(&mac.finalize().into_bytes()[..MIC_LENGTH]);
+        }
+        Akm::Sae => {
+            let mut mac = <Cmac<Aes128> as Mac>::new(kck.into());
+            mac.update(frame);
+            mic.copy_from_slice(&mac.finalize().into_bytes());
+        }
+    }
+
+    mic
+}
+
+/// Verifies the MIC of an EAPOL-Key frame in constant time. The MIC field of the frame has to be
+/// zeroed.
+pub fn verify_mic(
+    akm: Akm,
+    kck: &[u8; KCK_LENGTH],
+    frame: &[u8],
+    mic: &[u8; MIC_LENGTH],
+) -> Result<(), SupplicantError> {
+    let result = match akm {
+        Akm::Psk => {
+            let mut mac = HmacSha1::new_from_slice(kck).expect("HMAC accepts any key length");
+            mac.update(frame);
+            mac.verify_truncated_left(mic)
+        }
+        Akm::Sae => {
+            let mut mac = <Cmac<Aes128> as Mac>::new(kck.into());
+            mac.update(frame);
+            mac.verify_slice(mic)
+        }
+    };
+
+    result.map_err(|_| SupplicantError::InvalidMic)
+}
+
+/// Decrypts the key data of an EAPOL-Key frame with the AES key wrap of RFC 3394. The output has
+/// to be 8 bytes shorter than the wrapped data.
+pub fn unwrap_key_data(kek: &[u8; KEK_LENGTH], wrapped: &[u8], output: &mut [u8]) -> Result<(), SupplicantError> {
+    KekAes128::new(kek.into())
+        .unwrap(wrapped, output)
+        .map_err(|_| SupplicantError::InvalidKeyData)
+}
@@ -0,0 +1,154 @@
+//! EAPOL-Key frames (IEEE 802.1X-2020 11.3 and IEEE 802.11-2020 12.7.2).
+
+use super::{
+    crypto::{MIC_LENGTH, NONCE_LENGTH},
+    SupplicantError,
+};
+
+/// The EtherType of EAPOL frames.
+pub const ETH_P_PAE: u16 = 0x888E;
+
+const EAPOL_VERSION: u8 = 2;
+const EAPOL_TYPE_KEY: u8 = 3;
+const DESCRIPTOR_TYPE_RSN: u8 = 2;
+
+/// Size of the EAPOL header (version, type and body length).
+const EAPOL_HEADER_SIZE: usize = 4;
+
+const DESCRIPTOR_TYPE_OFFSET: usize = EAPOL_HEADER_SIZE;
+const KEY_INFORMATION_OFFSET: usize = DESCRIPTOR_TYPE_OFFSET + 1;
+const KEY_LENGTH_OFFSET: usize = KEY_INFORMATION_OFFSET + 2;
+const REPLAY_COUNTER_OFFSET: usize = KEY_LENGTH_OFFSET + 2;
+const NONCE_OFFSET: usize = REPLAY_COUNTER_OFFSET + 8;
+const IV_OFFSET: usize = NONCE_OFFSET + NONCE_LENGTH;
+const RSC_OFFSET: usize = IV_OFFSET + 16;
+const MIC_OFFSET: usize = RSC_OFFSET + 8 + 8;
+const KEY_DATA_LENGTH_OFFSET: usize = MIC_OFFSET + MIC_LENGTH;
+const KEY_DATA_OFFSET: usize = KEY_DATA_LENGTH_OFFSET + 2;
+
+/// Size of an EAPOL-Key frame without key data.
+pub const KEY_FRAME_SIZE: usize = KEY_DATA_OFFSET;
+
+/// Bits of the key information field.
+pub mod key_information {
+    /// The MIC and key wrap are given by the AKM, used with SAE
+    pub const DESCRIPTOR_VERSION_AKM_DEFINED: u16 = 0;
+    /// HMAC-SHA1-128 MIC with AES key wrap, the only version used with CCMP and PSK
+    pub const DESCRIPTOR_VERSION_HMAC_SHA1_AES: u16 = 2;
+    pub const DESCRIPTOR_VERSION_MASK: u16 = 0x0007;
+    pub const KEY_TYPE_PAIRWISE: u16 = 1 << 3;
+    pub const INSTALL: u16 = 1 << 6;
+    pub const KEY_ACK: u16 = 1 << 7;
+    pub const KEY_MIC: u16 = 1 << 8;
+    pub const SECURE: u16 = 1 << 9;
+    pub const ERROR: u16 = 1 << 10;
+    pub const REQUEST: u16 = 1 << 11;
+    pub const ENCRYPTED_KEY_DATA: u16 = 1 << 12;
+}
+
+/// A received EAPOL-Key frame, starting at the EAPOL header.
+pub struct KeyFrame<'a> {
+    frame: &'a [u8],
+}
+
+impl<'a> KeyFrame<'a> {
+    /// Checks that the frame is a complete RSN EAPOL-Key frame.
+    pub fn parse(frame: &'a [u8]) -> Result<Self, SupplicantError> {
+        if frame.len() < KEY_FRAME_SIZE
+            || frame[1] != EAPOL_TYPE_KEY
+            || frame[DESCRIPTOR_TYPE_OFFSET] != DESCRIPTOR_TYPE_RSN
+        {
+            return Err(SupplicantError::InvalidFrame);
+        }
+
+        let body_length = usize::from(u16::from_be_bytes([frame[2], frame[3]]));
+        let key_data_length = usize::from(u16::from_be_bytes([
+            frame[KEY_DATA_LENGTH_OFFSET],
+            frame[KEY_DATA_LENGTH_OFFSET + 1],
+        ]));
+
+        // Anything after the body is padding of the link layer
+        if EAPOL_HEADER_SIZE + body_length > frame.len() || KEY_FRAME_SIZE + key_data_length > frame.len() {
+            return Err(SupplicantError::InvalidFrame);
+        }
+
+        Ok(KeyFrame {
+            frame: &frame[..KEY_FRAME_SIZE + key_data_length],
+        })
+    }
+
+    pub fn key_information(&self) -> u16 {
+        u16::from_be_bytes([
+            self.frame[KEY_INFORMATION_OFFSET],
+            self.frame[KEY_INFORMATION_OFFSET + 1],
+        ])
+    }
+
+    pub fn has(&self, bits: u16) -> bool {
+        self.key_information() & bits == bits
+    }
+
+    pub fn replay_counter(&self) -> u64 {
+        u64::from_be_bytes(self.frame[REPLAY_COUNTER_OFFSET..NONCE_OFFSET].try_into().unwrap())
+    }
+
+    pub fn nonce(&self) -> &'a [u8; NONCE_LENGTH] {
+        self.frame[NONCE_OFFSET..IV_OFFSET].try_into().unwrap()
+    }
+
+    /// The receive sequence counter of the group key, in the 6 byte little endian format of the
+    /// key commands.
+    pub fn rsc(&self) -> [u8; 6] {
+        self.frame[RSC_OFFSET..RSC_OFFSET + 6].try_into().unwrap()
+    }
+
+    pub fn mic(&self) -> &'a [u8; MIC_LENGTH] {
+        self.frame[MIC_OFFSET..KEY_DATA_LENGTH_OFFSET].try_into().unwrap()
+    }
+
+    pub fn key_data(&self) -> &'a [u8] {
+        &self.frame[KEY_DATA_OFFSET..]
+    }
+
+    /// Copies the frame to `buffer` with the MIC zeroed, which is what the MIC is calculated over.
+    pub fn copy_without_mic<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], SupplicantError> {
+        let buffer = buffer
+            .get_mut(..self.frame.len())
+            .ok_or(SupplicantError::InvalidFrame)?;
+
+        buffer.copy_from_slice(self.frame);
+        buffer[MIC_OFFSET..KEY_DATA_LENGTH_OFFSET].fill(0);
+
+        Ok(buffer)
+    }
+}
+
+/// Writes an EAPOL-Key frame with a zeroed MIC to `buffer` and returns its length.
+pub fn write_key_frame(
+    buffer: &mut [u8],
+    key_information: u16,
+    replay_counter: u64,
+    nonce: &[u8; NONCE_LENGTH],
+    key_data: &[u8],
+) -> Result<usize, SupplicantError> {
+    let length = KEY_FRAME_SIZE + key_data.len();
+    let buffer = buffer.get_mut(..length).ok_or(SupplicantError::BufferTooSmall)?;
+
+    buffer.fill(0);
+    buffer[0] = EAPOL_VERSION;
+    buffer[1] = EAPOL_TYPE_KEY;
+    buffer[2..4].copy_from_slice(&((length - EAPOL_HEADER_SIZE) as u16).to_be_bytes());
+    buffer[DESCRIPTOR_TYPE_OFFSET] = DESCRIPTOR_TYPE_RSN;
+    buffer[KEY_INFORMATION_OFFSET..KEY_LENGTH_OFFSET].copy_from_slice(&key_information.to_be_bytes());
+    buffer[REPLAY_COUNTER_OFFSET..NONCE_OFFSET].copy_from_slice(&replay_counter.to_be_bytes());
+    buffer[NONCE_OFFSET..IV_OFFSET].copy_from_slice(nonce);
+    buffer[KEY_DATA_LENGTH_OFFSET..KEY_DATA_OFFSET].copy_from_slice(&(key_data.len() as u16).to_be_bytes());
+    buffer[KEY_DATA_OFFSET..].copy_from_slice(key_data);
+
+    Ok(length)
+}
+
+/// Sets the MIC of a frame written with [`write_key_frame`].
+pub fn set_mic(frame: &mut [u8], mic: &[u8; MIC_LENGTH]) {
+    frame[MIC_OFFSET..KEY_DATA_LENGTH_OFFSET].copy_from_slice(mic);
+}
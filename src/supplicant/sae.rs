@@ -0,0 +1,469 @@
+//! Simultaneous authentication of equals (IEEE 802.11-2020 12.4) over the NIST P-256 group.
+//!
+//! SAE replaces the PSK of WPA2 with a password authenticated key exchange. Both peers derive a
+//! password element from the password and their addresses, either by hunting-and-pecking or by
+//! hash-to-element, exchange commit and confirm messages in authentication frames and end up with
+//! the PMK for the 4-way handshake. Like the rest of the supplicant this only builds and checks
+//! the bodies of the authentication frames, the frames are sent by the control.
+
+use hkdf::HkdfExtract;
+use hmac::Mac;
+use p256::{
+    elliptic_curve::{
+        bigint::{ArrayEncoding, U256},
+        ff::{Field, PrimeField},
+        generic_array::GenericArray,
+        group::Group,
+        hash2curve::{FromOkm, MapToCurve},
+        point::DecompressPoint,
+        rand_core::CryptoRngCore,
+        sec1::{FromEncodedPoint, ToEncodedPoint},
+        subtle::{Choice, ConditionallySelectable, ConstantTimeEq},
+        Curve,
+    },
+    AffinePoint, EncodedPoint, FieldBytes, FieldElement, NistP256, ProjectivePoint, Scalar,
+};
+use sha2::Sha256;
+
+use super::{
+    crypto::{kdf_sha256, HmacSha256, PMK_LENGTH},
+    SupplicantError,
+};
+
+/// The authentication algorithm number of SAE.
+pub const AUTHENTICATION_ALGORITHM_SAE: u16 = 3;
+
+/// Transaction sequence numbers of the commit and confirm messages.
+pub const TRANSACTION_COMMIT: u16 = 1;
+pub const TRANSACTION_CONFIRM: u16 = 2;
+
+/// Status codes used by SAE in authentication frames.
+pub const STATUS_SUCCESS: u16 = 0;
+pub const STATUS_ANTI_CLOGGING_TOKEN_REQUIRED: u16 = 76;
+pub const STATUS_UNSUPPORTED_FINITE_CYCLIC_GROUP: u16 = 77;
+pub const STATUS_UNKNOWN_PASSWORD_IDENTIFIER: u16 = 123;
+pub const STATUS_SAE_HASH_TO_ELEMENT: u16 = 126;
+
+/// The finite cyclic group of NIST P-256, the only group supported.
+pub const GROUP_P256: u16 = 19;
+
+pub const SCALAR_LENGTH: usize = 32;
+pub const ELEMENT_LENGTH: usize = 64;
+
+/// Length of a commit without anti-clogging token: group, scalar and element.
+pub const COMMIT_LENGTH: usize = 2 + SCALAR_LENGTH + ELEMENT_LENGTH;
+
+/// Length of a confirm: send-confirm counter and confirm.
+pub const CONFIRM_LENGTH: usize = 2 + 32;
+
+/// Hunting-and-pecking always runs this many iterations so the time it takes does not depend on
+/// the password.
+const HUNTING_AND_PECKING_ITERATIONS: u8 = 40;
+
+/// Extension element ID of the anti-clogging token container used with hash-to-element.
+const ELEMENT_ID_EXTENSION: u8 = 0xff;
+const ELEMENT_ID_EXTENSION_ANTI_CLOGGING_TOKEN: u8 = 93;
+
+const KCK_LENGTH: usize = 32;
+
+/// The prime of the field of P-256, big endian.
+const PRIME: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+
+/// A point of the group, used for the password element, its hash-to-element precursor PT and the
+/// commit elements.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Element(AffinePoint);
+
+impl Element {
+    /// Parses an element as it appears in a commit, the big endian x and y coordinates, checking
+    /// that it is on the curve.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SupplicantError> {
+        if bytes.len() != ELEMENT_LENGTH {
+            return Err(SupplicantError::InvalidCommit);
+        }
+
+        let encoded = EncodedPoint::from_affine_coordinates(
+            FieldBytes::from_slice(&bytes[..32]),
+            FieldBytes::from_slice(&bytes[32..]),
+            false,
+        );
+
+        Option::from(AffinePoint::from_encoded_point(&encoded))
+            .map(Element)
+            .ok_or(SupplicantError::InvalidCommit)
+    }
+
+    /// The big endian x and y coordinates.
+    pub fn to_bytes(&self) -> [u8; ELEMENT_LENGTH] {
+        let encoded = self.0.to_encoded_point(false);
+
+        let mut bytes = [0u8; ELEMENT_LENGTH];
+        bytes[..32].copy_from_slice(encoded.x().expect("elements are never the identity"));
+        bytes[32..].copy_from_slice(encoded.y().expect("elements are never the identity"));
+        bytes
+    }
+}
+
+/// Orders two addresses as max || min, which is how the addresses enter the password element.
+fn addresses(a: &[u8; 6], b: &[u8; 6]) -> [u8; 12] {
+    let (max, min) = if a > b { (a, b) } else { (b, a) };
+
+    let mut result = [0u8; 12];
+    result[..6].copy_from_slice(max);
+    result[6..].copy_from_slice(min);
+    result
+}
+
+/// Derives the password element with hunting-and-pecking (IEEE 802.11-2020 12.4.4.2.2), the method
+/// used unless both peers support hash-to-element.
+///
+/// All iterations are run regardless of when a point is found.
+pub fn hunting_and_pecking(
+    password: &[u8],
+    identifier: Option<&[u8]>,
+    own_address: &[u8; 6],
+    peer_address: &[u8; 6],
+) -> Result<Element, SupplicantError> {
+    let addresses = addresses(own_address, peer_address);
+
+    let mut found = Choice::from(0);
+    let mut password_element = AffinePoint::GENERATOR;
+
+    for counter in 1..=HUNTING_AND_PECKING_ITERATIONS {
+        let mut mac = HmacSha256::new_from_slice(&addresses).expect("HMAC accepts any key length");
+        mac.update(password);
+        mac.update(identifier.unwrap_or_default());
+        mac.update(&[counter]);
+        let seed = mac.finalize().into_bytes();
+
+        let mut value = FieldBytes::default();
+        kdf_sha256(&seed, b"SAE Hunting and Pecking", &PRIME, &mut value);
+
+        // Fails both for values that are not below the prime and those that are not the x
+        // coordinate of a point
+        let candidate = AffinePoint::decompress(&value, Choice::from(seed[31] & 1));
+        let take = candidate.is_some() & !found;
+
+        password_element.conditional_assign(&candidate.unwrap_or(AffinePoint::GENERATOR), take);
+        found |= take;
+    }
+
+    if bool::from(found) {
+        Ok(Element(password_element))
+    } else {
+        Err(SupplicantError::InvalidPassphrase)
+    }
+}
+
+/// Derives the secret PT from which the password element is calculated with hash-to-element (IEEE
+/// 802.11-2020 12.4.4.2.3). PT only depends on the network and the password, so it can be
+/// calculated once and reused for each AP.
+pub fn derive_pt(ssid: &[u8], password: &[u8], identifier: Option<&[u8]>) -> Element {
+    let mut hkdf = HkdfExtract::<Sha256>::new(Some(ssid));
+    hkdf.input_ikm(password);
+    hkdf.input_ikm(identifier.unwrap_or_default());
+    let (_, hkdf) = hkdf.finalize();
+
+    let mut point = ProjectivePoint::IDENTITY;
+
+    for info in [&b"SAE Hash to Element u1 P1"[..], b"SAE Hash to Element u2 P2"] {
+        let mut value = GenericArray::default();
+        hkdf.expand(info, &mut value)
+            .expect("the output is shorter than 255 hashes");
+
+        point += FieldElement::from_okm(&value).map_to_curve();
+    }
+
+    Element(point.to_affine())
+}
+
+/// Calculates the password element from PT and the addresses of the peers with hash-to-element
+/// (IEEE 802.11-2020 12.4.4.2.3).
+pub fn password_element_from_pt(pt: &Element, own_address: &[u8; 6], peer_address: &[u8; 6]) -> Element {
+    let mut mac = HmacSha256::new_from_slice(&[0; 32]).expect("HMAC accepts any key length");
+    mac.update(&addresses(own_address, peer_address));
+    let value = U256::from_be_byte_array(mac.finalize().into_bytes());
+
+    // value mod (r - 1) + 1, the value depends only on the addresses so it needs no protection
+    let order_minus_one = NistP256::ORDER.wrapping_sub(&U256::ONE);
+    let value = if value < order_minus_one {
+        value
+    } else {
+        value.wrapping_sub(&order_minus_one)
+    };
+
+    let value =
+        Scalar::from_repr(value.wrapping_add(&U256::ONE).to_be_byte_array()).expect("the value is below the order");
+
+    Element((ProjectivePoint::from(pt.0) * value).to_affine())
+}
+
+/// The keys established by the commit exchange.
+#[derive(Clone)]
+struct Keys {
+    kck: [u8; KCK_LENGTH],
+    pmk: [u8; PMK_LENGTH],
+    peer_scalar: Scalar,
+    peer_element: AffinePoint,
+}
+
+/// One SAE exchange with an AP, from our commit to the PMK.
+#[derive(Clone)]
+pub struct Sae {
+    password_element: AffinePoint,
+    hash_to_element: bool,
+
+    rand: Scalar,
+    scalar: Scalar,
+    element: AffinePoint,
+
+    send_confirm: u16,
+    keys: Option<Keys>,
+}
+
+impl Sae {
+    /// Starts an exchange with a password element derived with hunting-and-pecking or
+    /// hash-to-element, picking the secret rand and mask with `rng`.
+    pub fn new(password_element: &Element, hash_to_element: bool, rng: &mut dyn CryptoRngCore) -> Self {
+        loop {
+            let mut rand = [0u8; SCALAR_LENGTH];
+            let mut mask = [0u8; SCALAR_LENGTH];
+            rng.fill_bytes(&mut rand);
+            rng.fill_bytes(&mut mask);
+
+            if let Ok(sae) = Self::with_randoms(password_element, hash_to_element, &rand, &mask) {
+                return sae;
+            }
+        }
+    }
+
+    /// Starts an exchange with the given rand and mask, which have to be in [2, r) and whose sum
+    /// modulo r has to be at least 2. Only meant for test vectors, use [`Sae::new`] otherwise.
+    pub fn with_randoms(
+        password_element: &Element,
+        hash_to_element: bool,
+        rand: &[u8; SCALAR_LENGTH],
+        mask: &[u8; SCALAR_LENGTH],
+    ) -> Result<Self, SupplicantError> {
+        let rand = parse_scalar(rand)?;
+        let mask = parse_scalar(mask)?;
+        let scalar = rand + mask;
+
+        if !is_valid_scalar(&scalar) {
+            return Err(SupplicantError::InvalidCommit);
+        }
+
+        let element = -(ProjectivePoint::from(password_element.0) * mask);
+
+        Ok(Sae {
+            password_element: password_element.0,
+            hash_to_element,
+            rand,
+            scalar,
+            element: element.to_affine(),
+            send_confirm: 1,
+            keys: None,
+        })
+    }
+
+    /// The status code to send with our commit, which tells the AP how the password element was
+    /// derived.
+    pub fn commit_status(&self) -> u16 {
+        if self.hash_to_element {
+            STATUS_SAE_HASH_TO_ELEMENT
+        } else {
+            STATUS_SUCCESS
+        }
+    }
+
+    /// Writes the body of our commit to `buffer` and returns its length. The anti-clogging token is
+    /// the one the AP asked for by rejecting an earlier commit.
+    pub fn write_commit(
+        &self,
+        anti_clogging_token: Option<&[u8]>,
+        buffer: &mut [u8],
+    ) -> Result<usize, SupplicantError> {
+        let token = anti_clogging_token.unwrap_or_default();
+        let container = if self.hash_to_element && !token.is_empty() {
+            3
+        } else {
+            0
+        };
+        let length = COMMIT_LENGTH + container + token.len();
+
+        if token.len() > 254 {
+            return Err(SupplicantError::InvalidFrame);
+        }
+
+        let buffer = buffer.get_mut(..length).ok_or(SupplicantError::BufferTooSmall)?;
+        buffer[..2].copy_from_slice(&GROUP_P256.to_le_bytes());
+
+        // With hunting-and-pecking the token goes before the scalar, with hash-to-element it is
+        // wrapped in a container element after the element
+        let (commit, rest) = if self.hash_to_element {
+            buffer[2..].split_at_mut(SCALAR_LENGTH + ELEMENT_LENGTH)
+        } else {
+            let (token_field, commit) = buffer[2..].split_at_mut(token.len());
+            token_field.copy_from_slice(token);
+            let (commit, rest) = commit.split_at_mut(SCALAR_LENGTH + ELEMENT_LENGTH);
+            (commit, rest)
+        };
+
+        commit[..SCALAR_LENGTH].copy_from_slice(&self.scalar.to_bytes());
+        commit[SCALAR_LENGTH..].copy_from_slice(&Element(self.element).to_bytes());
+
+        if container != 0 {
+            rest[0] = ELEMENT_ID_EXTENSION;
+            rest[1] = (token.len() + 1) as u8;
+            rest[2] = ELEMENT_ID_EXTENSION_ANTI_CLOGGING_TOKEN;
+            rest[3..].copy_from_slice(token);
+        }
+
+        Ok(length)
+    }
+
+    /// Extracts the token from the body of an authentication frame with status
+    /// [`STATUS_ANTI_CLOGGING_TOKEN_REQUIRED`].
+    pub fn anti_clogging_token<'a>(&self, body: &'a [u8]) -> Result<&'a [u8], SupplicantError> {
+        match body {
+            [group_low, group_high, rest @ ..] if u16::from_le_bytes([*group_low, *group_high]) == GROUP_P256 => {
+                if !self.hash_to_element {
+                    return Ok(rest);
+                }
+
+                match rest {
+                    [ELEMENT_ID_EXTENSION, length, ELEMENT_ID_EXTENSION_ANTI_CLOGGING_TOKEN, token @ ..]
+                        if usize::from(*length) == token.len() + 1 =>
+                    {
+                        Ok(token)
+                    }
+                    _ => Err(SupplicantError::InvalidFrame),
+                }
+            }
+            _ => Err(SupplicantError::InvalidFrame),
+        }
+    }
+
+    /// Processes the commit of the AP and derives the KCK and PMK (IEEE 802.11-2020 12.4.5.4).
+    ///
+    /// Elements following the element of the AP, such as a password identifier, are ignored.
+    pub fn process_commit(&mut self, body: &[u8]) -> Result<(), SupplicantError> {
+        if body.len() < COMMIT_LENGTH {
+            return Err(SupplicantError::InvalidFrame);
+        }
+
+        if u16::from_le_bytes([body[0], body[1]]) != GROUP_P256 {
+            return Err(SupplicantError::UnsupportedNetwork);
+        }
+
+        let peer_scalar = parse_scalar(body[2..2 + SCALAR_LENGTH].try_into().unwrap())?;
+        let peer_element = Element::from_bytes(&body[2 + SCALAR_LENGTH..COMMIT_LENGTH])?.0;
+
+        // A commit equal to ours is a reflection of our own commit
+        if bool::from(peer_scalar.ct_eq(&self.scalar)) && peer_element == self.element {
+            return Err(SupplicantError::InvalidCommit);
+        }
+
+        let shared = (ProjectivePoint::from(self.password_element) * peer_scalar + peer_element) * self.rand;
+
+        if bool::from(shared.is_identity()) {
+            return Err(SupplicantError::InvalidCommit);
+        }
+
+        let shared = shared.to_affine().to_encoded_point(false);
+        let shared_x = shared.x().expect("the shared secret is not the identity");
+
+        let mut mac = HmacSha256::new_from_slice(&[0; 32]).expect("HMAC accepts any key length");
+        mac.update(shared_x);
+        let keyseed = mac.finalize().into_bytes();
+
+        let context = (self.scalar + peer_scalar).to_bytes();
+
+        let mut keys = [0u8; KCK_LENGTH + PMK_LENGTH];
+        kdf_sha256(&keyseed, b"SAE KCK and PMK", &context, &mut keys);
+
+        self.keys = Some(Keys {
+            kck: keys[..KCK_LENGTH].try_into().unwrap(),
+            pmk: keys[KCK_LENGTH..].try_into().unwrap(),
+            peer_scalar,
+            peer_element,
+        });
+
+        Ok(())
+    }
+
+    /// Writes the body of our confirm to `buffer` and returns its length. Has to be preceded by
+    /// [`Sae::process_commit`].
+    pub fn write_confirm(&mut self, buffer: &mut [u8]) -> Result<usize, SupplicantError> {
+        let keys = self.keys.as_ref().ok_or(SupplicantError::UnexpectedFrame)?;
+        let buffer = buffer
+            .get_mut(..CONFIRM_LENGTH)
+            .ok_or(SupplicantError::BufferTooSmall)?;
+
+        let confirm = confirm(
+            &keys.kck,
+            self.send_confirm,
+            (&self.scalar, &self.element),
+            (&keys.peer_scalar, &keys.peer_element),
+        );
+
+        buffer[..2].copy_from_slice(&self.send_confirm.to_le_bytes());
+        buffer[2..].copy_from_slice(&confirm.finalize().into_bytes());
+
+        self.send_confirm = self.send_confirm.saturating_add(1);
+
+        Ok(CONFIRM_LENGTH)
+    }
+
+    /// Verifies the confirm of the AP in constant time and returns the PMK on success.
+    pub fn process_confirm(&self, body: &[u8]) -> Result<[u8; PMK_LENGTH], SupplicantError> {
+        let keys = self.keys.as_ref().ok_or(SupplicantError::UnexpectedFrame)?;
+
+        if body.len() < CONFIRM_LENGTH {
+            return Err(SupplicantError::InvalidFrame);
+        }
+
+        let send_confirm = u16::from_le_bytes([body[0], body[1]]);
+
+        confirm(
+            &keys.kck,
+            send_confirm,
+            (&keys.peer_scalar, &keys.peer_element),
+            (&self.scalar, &self.element),
+        )
+        .verify_slice(&body[2..CONFIRM_LENGTH])
+        .map_err(|_| SupplicantError::InvalidConfirm)?;
+
+        Ok(keys.pmk)
+    }
+}
+
+/// Parses a scalar of a commit, which has to be in (1, r).
+fn parse_scalar(bytes: &[u8; SCALAR_LENGTH]) -> Result<Scalar, SupplicantError> {
+    Option::from(Scalar::from_repr(*FieldBytes::from_slice(bytes)))
+        .filter(is_valid_scalar)
+        .ok_or(SupplicantError::InvalidCommit)
+}
+
+fn is_valid_scalar(scalar: &Scalar) -> bool {
+    !bool::from(scalar.is_zero() | scalar.ct_eq(&Scalar::ONE))
+}
+
+/// The confirm function CN of IEEE 802.11-2020 12.4.5.5, over the commit of the sender first.
+fn confirm(
+    kck: &[u8; KCK_LENGTH],
+    send_confirm: u16,
+    sender: (&Scalar, &AffinePoint),
+    receiver: (&Scalar, &AffinePoint),
+) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(kck).expect("HMAC accepts any key length");
+    mac.update(&send_confirm.to_le_bytes());
+    mac.update(&sender.0.to_bytes());
+    mac.update(&Element(*sender.1).to_bytes());
+    mac.update(&receiver.0.to_bytes());
+    mac.update(&Element(*receiver.1).to_bytes());
+    mac
+}
@@ -1,18 +1,18 @@
-use core::mem::{align_of, size_of};
-use core::slice;
+use core::mem::{size_of, zeroed};
+use core::{ptr, slice};
 
 pub(crate) fn sliceit<T>(t: &T) -> &[u8] {
     unsafe { slice::from_raw_parts(t as *const _ as _, size_of::<T>()) }
 }
 
-pub(crate) fn unsliceit2<T>(t: &[u8]) -> (&T, &[u8]) {
-    assert!(t.len() > size_of::<T>());
-    assert!(t.as_ptr() as usize % align_of::<T>() == 0);
-    (unsafe { &*(t.as_ptr() as *const T) }, &t[size_of::<T>()..])
-}
+/// Reads a binding structure from the start of `t`, where missing bytes are zero.
+pub(crate) fn unsliceit_padded<T>(t: &[u8]) -> T {
+    let mut value: T = unsafe { zeroed() };
+    let length = t.len().min(size_of::<T>());
+
+    unsafe { ptr::copy_nonoverlapping(t.as_ptr(), (&raw mut value).cast::<u8>(), length) };
 
-pub(crate) fn unsliceit<T>(t: &[u8]) -> &T {
-    unsliceit2(t).0
+    value
 }
 
 pub(crate) fn meh<T>(t: T) -> T {
@@ -0,0 +1,259 @@
+//! Verifies what the SPI and QSPI buses ask of the peripheral: the framing of reads and writes,
+//! the dummy words or cycles of reads with a latency and the instructions for the status registers.
+
+use embassy_futures::block_on;
+use embedded_hal::spi::ErrorKind;
+use embedded_hal_async::spi::{ErrorType, Operation, SpiDevice};
+use nrf70::bus::{Bus, QspiBus, QspiDevice, SpiBus};
+
+#[derive(Debug, PartialEq, Eq)]
+enum SpiOperation {
+    Write(Vec<u8>),
+    Read(usize),
+}
+
+/// Records the transactions, and answers the reads of each transaction with consecutive bytes
+/// counted from the start of the transaction.
+struct FakeSpi {
+    transactions: Vec<Vec<SpiOperation>>,
+}
+
+impl ErrorType for &mut FakeSpi {
+    type Error = ErrorKind;
+}
+
+impl SpiDevice for &mut FakeSpi {
+    async fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        let mut transaction = Vec::new();
+        let mut next = 0u8;
+
+        for operation in operations {
+            match operation {
+                Operation::Write(data) => transaction.push(SpiOperation::Write(data.to_vec())),
+                Operation::Read(buf) => {
+                    for byte in buf.iter_mut() {
+                        *byte = next;
+                        next += 1;
+                    }
+
+                    transaction.push(SpiOperation::Read(buf.len()));
+                }
+                _ => return Err(ErrorKind::Other),
+            }
+        }
+
+        self.transactions.push(transaction);
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum QspiOperation {
+    Read {
+        addr: u32,
+        dummy_cycles: usize,
+        length: usize,
+    },
+    Write {
+        addr: u32,
+        data: Vec<u8>,
+    },
+    Instruction {
+        opcode: u8,
+        tx: Vec<u8>,
+        rx_length: usize,
+    },
+}
+
+/// Records the operations, and answers reads with consecutive bytes and instructions with `status`.
+struct FakeQspi {
+    operations: Vec<QspiOperation>,
+    status: u8,
+}
+
+impl FakeQspi {
+    fn new() -> Self {
+        Self {
+            operations: Vec::new(),
+            status: 0,
+        }
+    }
+}
+
+impl QspiDevice for &mut FakeQspi {
+    type Error = ();
+
+    async fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.read_with_dummy_cycles(addr, 0, buf).await
+    }
+
+    async fn read_with_dummy_cycles(
+        &mut self,
+        addr: u32,
+        dummy_cycles: usize,
+        buf: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        for (index, byte) in buf.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+
+        self.operations.push(QspiOperation::Read {
+            addr,
+            dummy_cycles,
+            length: buf.len(),
+        });
+        Ok(())
+    }
+
+    async fn write(&mut self, addr: u32, buf: &[u8]) -> Result<(), Self::Error> {
+        self.operations.push(QspiOperation::Write {
+            addr,
+            data: buf.to_vec(),
+        });
+        Ok(())
+    }
+
+    async fn custom_instruction(&mut self, opcode: u8, tx: &[u8], rx: &mut [u8]) -> Result<(), Self::Error> {
+        rx.fill(self.status);
+
+        self.operations.push(QspiOperation::Instruction {
+            opcode,
+            tx: tx.to_vec(),
+            rx_length: rx.len(),
+        });
+        Ok(())
+    }
+}
+
+#[test]
+fn spi_reads_and_writes() {
+    let mut spi = FakeSpi {
+        transactions: Vec::new(),
+    };
+    let mut bus = SpiBus::new(&mut spi);
+
+    block_on(async {
+        // A burst without dummy words is framed like a plain read
+        let mut words = [0u32; 2];
+        bus.read_with_latency(0x0C_0010, 0, &mut words).await.unwrap();
+        assert_eq!(words, [0x0302_0100, 0x0706_0504]);
+
+        // The dummy words are only clocked once, and the data follows them
+        bus.read_with_latency(0x08_0020, 2, &mut words).await.unwrap();
+        assert_eq!(words, [0x0B0A_0908, 0x0F0E_0D0C]);
+
+        bus.write(0x0C_0010, &[0x0403_0201]).await.unwrap();
+    });
+
+    let header = |addr: u32| SpiOperation::Write(vec![0x0B, (addr >> 16) as u8, (addr >> 8) as u8, addr as u8, 0x00]);
+
+    assert_eq!(
+        spi.transactions,
+        [
+            vec![header(0x0C_0010), SpiOperation::Read(8)],
+            vec![header(0x08_0020), SpiOperation::Read(8), SpiOperation::Read(8)],
+            // Flagged as a write in the address
+            vec![
+                SpiOperation::Write(vec![0x02, 0x8C, 0x00, 0x10]),
+                SpiOperation::Write(vec![0x01, 0x02, 0x03, 0x04]),
+            ],
+        ]
+    );
+}
+
+#[test]
+fn qspi_reads_and_writes() {
+    let mut qspi = FakeQspi::new();
+    let mut bus = QspiBus::new(&mut qspi);
+
+    block_on(async {
+        let mut words = [0u32; 2];
+        bus.read(0x04_8C20, &mut words).await.unwrap();
+        assert_eq!(words, [0x0302_0100, 0x0706_0504]);
+
+        bus.write(0x04_8C20, &[0x0403_0201]).await.unwrap();
+    });
+
+    assert_eq!(
+        qspi.operations,
+        [
+            QspiOperation::Read {
+                addr: 0x04_8C20,
+                dummy_cycles: 0,
+                length: 8,
+            },
+            // Flagged as a write in the address
+            QspiOperation::Write {
+                addr: 0x84_8C20,
+                data: vec![0x01, 0x02, 0x03, 0x04],
+            },
+        ]
+    );
+}
+
+#[test]
+fn qspi_reads_with_latency() {
+    let mut qspi = FakeQspi::new();
+    let mut bus = QspiBus::new(&mut qspi);
+
+    block_on(async {
+        // The peripheral skips the dummy words, so the data fills the whole buffer in a single read
+        let mut words = [0u32; 3];
+        bus.read_with_latency(0x0C_0000, 2, &mut words).await.unwrap();
+        assert_eq!(words, [0x0302_0100, 0x0706_0504, 0x0B0A_0908]);
+
+        bus.read_with_latency(0x0C_0000, 1, &mut words[..1]).await.unwrap();
+        bus.read_with_latency(0x0C_0000, 0, &mut words[..1]).await.unwrap();
+    });
+
+    assert_eq!(
+        qspi.operations,
+        [
+            QspiOperation::Read {
+                addr: 0x0C_0000,
+                dummy_cycles: 16,
+                length: 12,
+            },
+            QspiOperation::Read {
+                addr: 0x0C_0000,
+                dummy_cycles: 8,
+                length: 4,
+            },
+            QspiOperation::Read {
+                addr: 0x0C_0000,
+                dummy_cycles: 0,
+                length: 4,
+            },
+        ]
+    );
+}
+
+#[test]
+fn qspi_status_registers() {
+    let mut qspi = FakeQspi::new();
+    qspi.status = 0x42;
+    let mut bus = QspiBus::new(&mut qspi);
+
+    block_on(async {
+        assert_eq!(bus.read_sr0().await, Ok(0x42));
+        assert_eq!(bus.read_sr1().await, Ok(0x42));
+        assert_eq!(bus.read_sr2().await, Ok(0x42));
+        bus.write_sr2(0x01).await.unwrap();
+    });
+
+    let instruction = |opcode, tx: &[u8], rx_length| QspiOperation::Instruction {
+        opcode,
+        tx: tx.to_vec(),
+        rx_length,
+    };
+
+    assert_eq!(
+        qspi.operations,
+        [
+            instruction(0x05, &[], 1),
+            instruction(0x1f, &[], 1),
+            instruction(0x2f, &[], 1),
+            instruction(0x3f, &[0x01], 0),
+        ]
+    );
+}
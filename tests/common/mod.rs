@@ -0,0 +1,86 @@
+//! Runs the driver against the simulated chip, on a clock which follows the time of the host.
+
+// Not every test uses all of it
+#![allow(dead_code)]
+
+use std::future::Future;
+use std::sync::OnceLock;
+use std::task::Waker;
+use std::time::Instant;
+
+use align_data::{include_aligned, Align16};
+use embassy_futures::block_on;
+use embassy_futures::select::{select, Either};
+use embassy_time_driver::{time_driver_impl, Driver, TICK_HZ};
+use nrf70::sim::{HostIrq, PowerPin, Simulator};
+use nrf70::{Control, NetDriver, Runner, State};
+use rand_core::{impls, CryptoRng, RngCore};
+
+pub static FIRMWARE: &[u8] = include_aligned!(Align16, "../../thirdparty/default.bin");
+
+pub const MAC_ADDRESS: [u8; 6] = [0xF4, 0xCE, 0x36, 0x00, 0x10, 0x20];
+
+pub type SimulatorRunner<'a> = Runner<'a, Simulator, HostIrq, PowerPin>;
+
+struct HostClock {
+    start: OnceLock<Instant>,
+}
+
+impl Driver for HostClock {
+    fn now(&self) -> u64 {
+        let elapsed = self.start.get_or_init(Instant::now).elapsed();
+        (elapsed.as_micros() * u128::from(TICK_HZ) / 1_000_000) as u64
+    }
+
+    // The executor polls all the time, so nothing needs to be woken at a later time
+    fn schedule_wake(&self, _at: u64, waker: &Waker) {
+        waker.wake_by_ref();
+    }
+}
+
+time_driver_impl!(static CLOCK: HostClock = HostClock { start: OnceLock::new() });
+
+/// Gives the same byte over and over, for the SNonce of the recorded handshakes.
+pub struct Repeat(pub u8);
+
+impl RngCore for Repeat {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        dest.fill(self.0);
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for Repeat {}
+
+/// Powers up the driver on `simulator`.
+pub fn new<'a>(state: &'a mut State, simulator: &Simulator) -> (NetDriver<'a>, Control<'a>, SimulatorRunner<'a>) {
+    block_on(nrf70::new(
+        state,
+        simulator.clone(),
+        simulator.bucken(),
+        simulator.iovdd_ctl(),
+        simulator.host_irq(),
+    ))
+}
+
+/// Runs `test` to completion while the runner handles the chip.
+pub fn run<F: Future>(runner: &mut SimulatorRunner<'_>, test: F) -> F::Output {
+    block_on(async {
+        match select(runner.run(), test).await {
+            Either::First(error) => panic!("Runner stopped: {error:?}"),
+            Either::Second(output) => output,
+        }
+    })
+}
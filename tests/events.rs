@@ -0,0 +1,145 @@
+//! Verifies the decoding of messages from the event queue of the RPU into events.
+
+use nrf70::events::Event;
+use nrf70::Error;
+
+const MESSAGE_TYPE_SYSTEM: u32 = 0;
+const MESSAGE_TYPE_DATA: u32 = 2;
+const MESSAGE_TYPE_UMAC: u32 = 3;
+
+/// A message with its `host_rpu_msg` header.
+fn message(message_type: u32, payload: &[u8]) -> Vec<u8> {
+    let length = u32::try_from(12 + payload.len()).unwrap();
+
+    let mut message = Vec::new();
+    message.extend_from_slice(&length.to_le_bytes());
+    message.extend_from_slice(&0u32.to_le_bytes());
+    message.extend_from_slice(&message_type.to_le_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// A UMAC event with its `nrf_wifi_umac_hdr`, followed by `body`.
+fn umac_event(event: u32, body: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&0u32.to_le_bytes());
+    payload.extend_from_slice(&1u32.to_le_bytes());
+    payload.extend_from_slice(&event.to_le_bytes());
+    payload.extend_from_slice(&[0; 24]);
+    payload.extend_from_slice(body);
+    message(MESSAGE_TYPE_UMAC, &payload)
+}
+
+#[test]
+fn init_done() {
+    let event = Event::decode(&message(MESSAGE_TYPE_SYSTEM, &[1, 0, 0, 0, 8, 0, 0, 0])).unwrap();
+    assert!(matches!(event, Event::InitDone));
+}
+
+#[test]
+fn power_data() {
+    let mut payload = Vec::new();
+    payload.extend_from_slice(&0u32.to_le_bytes());
+    payload.extend_from_slice(&24u32.to_le_bytes());
+
+    for value in [0i32, -3, 3300, 25] {
+        payload.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let event = Event::decode(&message(MESSAGE_TYPE_SYSTEM, &payload)).unwrap();
+    assert!(matches!(
+        event,
+        Event::PowerData {
+            lo_frequency_error: -3,
+            battery_voltage: 3300,
+            temperature: 25
+        }
+    ));
+}
+
+#[test]
+fn command_status() {
+    let mut body = Vec::new();
+    body.extend_from_slice(&5u32.to_le_bytes());
+    body.extend_from_slice(&(-22i32).to_le_bytes());
+
+    let event = Event::decode(&umac_event(292, &body)).unwrap();
+    assert!(matches!(
+        event,
+        Event::CommandStatus {
+            command: 5,
+            sequence: 1,
+            status: -22
+        }
+    ));
+}
+
+#[test]
+fn deauthentication_reason() {
+    const FRAME_VALID: u32 = 1;
+
+    // A deauthentication frame, which holds the reason code after the management header
+    let mut frame = vec![0xC0, 0x00];
+    frame.resize(24, 0);
+    frame.extend_from_slice(&7u16.to_le_bytes());
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&FRAME_VALID.to_le_bytes());
+    body.extend_from_slice(&2412u32.to_le_bytes());
+    body.extend_from_slice(&0u32.to_le_bytes());
+    body.extend_from_slice(&0u32.to_le_bytes());
+    body.extend_from_slice(&0u64.to_le_bytes());
+    body.extend_from_slice(&i32::try_from(frame.len()).unwrap().to_le_bytes());
+    body.extend_from_slice(&frame);
+
+    let event = Event::decode(&umac_event(264, &body)).unwrap();
+    assert!(matches!(event, Event::Deauthenticate { reason: Some(7), .. }));
+}
+
+#[test]
+fn deauthentication_without_frame() {
+    let event = Event::decode(&umac_event(264, &[])).unwrap();
+    assert!(matches!(
+        event,
+        Event::Deauthenticate {
+            reason: None,
+            locally_generated: true
+        }
+    ));
+}
+
+#[test]
+fn transmit_done() {
+    let event = Event::decode(&message(MESSAGE_TYPE_DATA, &[2, 0, 0, 0, 22, 0, 0, 0, 3, 2])).unwrap();
+    assert!(matches!(
+        event,
+        Event::TransmitDone {
+            descriptor: 3,
+            packets: 2
+        }
+    ));
+}
+
+#[test]
+fn unknown_events_are_other() {
+    let event = Event::decode(&umac_event(288, &[])).unwrap();
+    assert!(matches!(
+        event,
+        Event::Other {
+            message_type: MESSAGE_TYPE_UMAC,
+            id: 288
+        }
+    ));
+
+    let event = Event::decode(&message(7, &[9, 0, 0, 0])).unwrap();
+    assert!(matches!(event, Event::Other { message_type: 7, id: 9 }));
+}
+
+#[test]
+fn truncated_headers_are_rejected() {
+    assert!(matches!(Event::decode(&[0; 8]), Err(Error::BufferTooSmall)));
+    assert!(matches!(
+        Event::decode(&message(MESSAGE_TYPE_UMAC, &[0; 10])),
+        Err(Error::BufferTooSmall)
+    ));
+}
@@ -0,0 +1,274 @@
+//! Verifies the decoding of the information elements of beacons, on the elements of an AP in
+//! WPA2/WPA3 transition mode with HT, VHT and HE, and on elements which are cut short.
+
+use nrf70::ie::{
+    extension_id, id, BssLoad, Country, Decode, DsParameterSet, Elements, ExtendedCapabilities, HeCapabilities,
+    HtCapabilities, Rate, Rsn, RsnExtension, Ssid, Subband, Suite, SupportedRates, VhtCapabilities, Wpa,
+};
+
+fn hex(string: &str) -> Vec<u8> {
+    (0..string.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&string[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// The elements of a beacon, in the order the AP sends them: SSID, supported rates, DS parameter
+/// set, TIM, country, BSS load, ERP, HT capabilities, extended supported rates, RSN, extended
+/// capabilities, VHT capabilities, HE capabilities, RSN extension and WMM.
+const BEACON: &str = "0008486f6d6557694669\
+                      010882848b960c121824\
+                      030106\
+                      050400010000\
+                      070c4e4f20010d14c95100240417\
+                      0b050300 2a0000\
+                      2a0100\
+                      2d1aef191bffff000000000000000000000000000000000000000000\
+                      3204304860 6c\
+                      301e0100000fac040100000fac040200000fac02000fac088000 0000000fac06\
+                      7f080400080000000040\
+                      bf0c9159820feaff0000eaff0000\
+                      ff16230500081200102220 02c00f039518 00cc00faff faff\
+                      f40120\
+                      dd180050f2020101800003a4000027a4000042435e0062322f00";
+
+fn beacon() -> Vec<u8> {
+    hex(&BEACON.replace(' ', ""))
+}
+
+/// The whole first element with the given id.
+fn element_bytes(elements: &[u8], id: u8) -> &[u8] {
+    Elements::new(elements).find_id(id).unwrap().as_bytes()
+}
+
+#[test]
+fn elements_of_a_beacon() {
+    let beacon = beacon();
+    let elements = Elements::new(&beacon);
+
+    let ids: Vec<u8> = elements.into_iter().map(|element| element.id).collect();
+    assert_eq!(ids, [0, 1, 3, 5, 7, 11, 42, 45, 50, 48, 127, 191, 255, 244, 221]);
+
+    // The elements cover the whole buffer
+    let length: usize = elements.into_iter().map(|element| element.as_bytes().len()).sum();
+    assert_eq!(length, beacon.len());
+
+    assert_eq!(elements.get::<Ssid>(), Some(Ssid(b"HomeWiFi")));
+    assert_eq!(elements.get::<DsParameterSet>(), Some(DsParameterSet { channel: 6 }));
+    assert_eq!(elements.find_id(5).map(|element| element.data), Some(&[0, 1, 0, 0][..]));
+
+    let rates: Vec<Rate> = elements.get::<SupportedRates>().unwrap().rates().collect();
+    assert_eq!(rates.len(), 8);
+    assert!(rates[0].is_basic());
+    assert_eq!(rates[0].kbps(), 1000);
+    assert!(!rates[4].is_basic());
+    assert_eq!(rates[4].kbps(), 6000);
+
+    assert_eq!(
+        elements.get::<BssLoad>(),
+        Some(BssLoad {
+            station_count: 3,
+            channel_utilization: 42,
+            available_admission_capacity: 0,
+        })
+    );
+
+    let ht = elements.get::<HtCapabilities>().unwrap();
+    assert_eq!(ht.capability_information, 0x19ef);
+    assert!(ht.supports_40mhz());
+    assert!(ht.supports_streams(2));
+    assert!(!ht.supports_streams(3));
+
+    let vht = elements.get::<VhtCapabilities>().unwrap();
+    assert_eq!(vht.capability_information, 0x0f82_5991);
+    assert_eq!(vht.rx_mcs_map, 0xffea);
+    assert_eq!(vht.tx_mcs_map, 0xffea);
+
+    let extended = elements.get::<ExtendedCapabilities>().unwrap();
+    assert!(extended.has(ExtendedCapabilities::BSS_TRANSITION));
+    assert!(extended.has(ExtendedCapabilities::OPERATING_MODE_NOTIFICATION));
+    assert!(!extended.has(ExtendedCapabilities::INTERWORKING));
+    // Beyond the end of the element
+    assert!(!extended.has(ExtendedCapabilities::TWT_RESPONDER_SUPPORT));
+
+    assert!(elements.get::<RsnExtension>().unwrap().sae_hash_to_element());
+
+    // WMM is the only vendor specific element
+    assert_eq!(elements.get::<Wpa>(), None);
+}
+
+#[test]
+fn country() {
+    let beacon = beacon();
+    let country = Elements::new(&beacon).get::<Country>().unwrap();
+
+    assert_eq!(&country.country, b"NO");
+    assert_eq!(country.environment, b' ');
+
+    // The operating class triplet in between is left out
+    let subbands: Vec<Subband> = country.subbands().collect();
+    assert_eq!(
+        subbands,
+        [
+            Subband {
+                first_channel: 1,
+                channels: 13,
+                max_transmit_power: 20,
+            },
+            Subband {
+                first_channel: 36,
+                channels: 4,
+                max_transmit_power: 23,
+            },
+        ]
+    );
+
+    // Without any triplets
+    assert_eq!(
+        Country::decode(b"US ").map(|country| country.subbands().count()),
+        Some(0)
+    );
+    assert_eq!(Country::decode(b"US"), None);
+}
+
+#[test]
+fn rsn_of_a_transition_mode_network() {
+    let beacon = beacon();
+    let rsn = Elements::new(&beacon).get::<Rsn>().unwrap();
+
+    assert_eq!(rsn.version, 1);
+    assert_eq!(rsn.group_cipher, Suite::CCMP_128);
+    assert_eq!(rsn.pairwise_ciphers().collect::<Vec<_>>(), [Suite::CCMP_128]);
+    assert_eq!(rsn.akm_suites().collect::<Vec<_>>(), [Suite::AKM_PSK, Suite::AKM_SAE]);
+    assert_eq!(rsn.capabilities, Rsn::CAPABILITY_MFPC);
+    assert_eq!(rsn.pmkids().count(), 0);
+    assert_eq!(rsn.group_management_cipher, Some(Suite::BIP_CMAC_128));
+    assert_eq!(Suite::AKM_SAE.selector(), 0x000f_ac08);
+}
+
+#[test]
+fn rsn_with_pmkids() {
+    // As sent in a reassociation request with a cached PMK, without a group management cipher
+    let rsn = hex("0100000fac040100000fac040100000fac020000010000112233445566778899aabbccddeeff");
+    let rsn = Rsn::decode(&rsn).unwrap();
+
+    let pmkids: Vec<&[u8; 16]> = rsn.pmkids().collect();
+    assert_eq!(pmkids, [&hex("00112233445566778899aabbccddeeff")[..]]);
+    assert_eq!(rsn.group_management_cipher, None);
+
+    // And with one, management frame protection required and capable
+    let rsn = hex("0100000fac040100000fac040100000fac08c0000100000102030405060708090a0b0c0d0e0f000fac06");
+    let rsn = Rsn::decode(&rsn).unwrap();
+
+    assert_eq!(rsn.capabilities, Rsn::CAPABILITY_MFPR | Rsn::CAPABILITY_MFPC);
+    assert_eq!(rsn.pmkids().count(), 1);
+    assert_eq!(rsn.group_management_cipher, Some(Suite::BIP_CMAC_128));
+}
+
+#[test]
+fn rsn_without_optional_fields() {
+    // Ends after the AKM suites
+    let rsn = hex("0100000fac040100000fac040100000fac02");
+    let rsn = Rsn::decode(&rsn).unwrap();
+
+    assert_eq!(rsn.capabilities, 0);
+    assert_eq!(rsn.pmkids().count(), 0);
+    assert_eq!(rsn.group_management_cipher, None);
+
+    // Ends after the capabilities
+    let rsn = hex("0100000fac040100000fac040100000fac020c00");
+    assert_eq!(Rsn::decode(&rsn).map(|rsn| rsn.capabilities), Some(0x000c));
+}
+
+#[test]
+fn truncated_rsn() {
+    let rsn = hex("0100000fac040200000fac04000fac020100000fac020000");
+
+    // Cut within the pairwise ciphers and the AKM suites
+    assert_eq!(Rsn::decode(&rsn[..12]), None);
+    assert_eq!(Rsn::decode(&rsn[..20]), None);
+    // Without the group cipher
+    assert_eq!(Rsn::decode(&rsn[..4]), None);
+
+    // More PMKIDs announced than there are
+    let rsn = hex("0100000fac040100000fac040100000fac020000020000112233445566778899aabbccddeeff");
+    assert_eq!(Rsn::decode(&rsn), None);
+}
+
+#[test]
+fn truncated_elements() {
+    let beacon = beacon();
+
+    // Cut within the HT capabilities, which ends the iteration there
+    let cut = 2 + 8 + 2 + 8 + 3 + 6 + 14 + 7 + 3 + 10;
+    let elements = Elements::new(&beacon[..cut]);
+
+    assert_eq!(elements.into_iter().count(), 7);
+    assert_eq!(elements.get::<HtCapabilities>(), None);
+    assert_eq!(elements.get::<Rsn>(), None);
+    assert_eq!(elements.get::<Ssid>(), Some(Ssid(b"HomeWiFi")));
+
+    // An id without a length
+    assert_eq!(Elements::new(&beacon[..11]).into_iter().count(), 1);
+
+    // A well formed element with a body too short for its type
+    let elements = hex("2d19ef191bffff0000000000000000000000000000000000000000");
+    assert_eq!(Elements::new(&elements).get::<HtCapabilities>(), None);
+
+    let elements = hex("0300");
+    assert_eq!(Elements::new(&elements).get::<DsParameterSet>(), None);
+
+    // Too long for an SSID
+    let mut elements = vec![id::SSID, 33];
+    elements.extend_from_slice(&[b'a'; 33]);
+    assert_eq!(Elements::new(&elements).get::<Ssid>(), None);
+}
+
+#[test]
+fn extension_elements() {
+    let beacon = beacon();
+    let elements = Elements::new(&beacon);
+
+    let he = elements.get::<HeCapabilities>().unwrap();
+    assert!(he.supports_twt_responder());
+    assert_eq!(he.mcs_nss, &hex("fafffaff")[..]);
+
+    let element = elements.find_id(id::EXTENSION).unwrap();
+    assert_eq!(element.extension_id(), Some(extension_id::HE_CAPABILITIES));
+    assert_eq!(elements.find_id(id::SSID).unwrap().extension_id(), None);
+
+    // The HE operation element, with another extension id, does not decode as HE capabilities
+    // and is skipped for the one after it
+    let mut elements = hex("ff0724f43f000000fc");
+    elements.extend_from_slice(element_bytes(&beacon, id::EXTENSION));
+
+    let elements = Elements::new(&elements);
+    assert_eq!(elements.find_id(id::EXTENSION).unwrap().extension_id(), Some(36));
+    assert_eq!(elements.get::<HeCapabilities>(), Some(he));
+
+    // Without an extension id
+    let elements = hex("ff00");
+    let element = Elements::new(&elements).find_id(id::EXTENSION).unwrap();
+    assert_eq!(element.extension_id(), None);
+    assert_eq!(element.decode::<HeCapabilities>(), None);
+}
+
+#[test]
+fn wpa() {
+    // WMM first, which shares the vendor specific element id and the OUI
+    let mut elements = element_bytes(&beacon(), id::VENDOR_SPECIFIC).to_vec();
+    elements.extend_from_slice(&hex("dd160050f20101000050f20201000050f20201000050f202"));
+
+    let wpa = Elements::new(&elements).get::<Wpa>().unwrap();
+    // Both type 2 of the WPA OUI
+    let tkip = Suite([0x00, 0x50, 0xf2, 2]);
+    let psk = Suite([0x00, 0x50, 0xf2, 2]);
+
+    assert_eq!(wpa.version, 1);
+    assert_eq!(wpa.group_cipher, tkip);
+    assert_eq!(wpa.pairwise_ciphers().collect::<Vec<_>>(), [tkip]);
+    assert_eq!(wpa.akm_suites().collect::<Vec<_>>(), [psk]);
+
+    // Cut within the AKM suites
+    assert_eq!(Wpa::decode(&elements[28..elements.len() - 2]), None);
+}
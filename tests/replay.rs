@@ -0,0 +1,233 @@
+//! Locks in the boot sequence of the driver by replaying a trace of it, recorded on the simulated
+//! chip. Once a change to the driver alters the sequence on purpose, record the trace again with
+//! `cargo test --test replay -- --ignored` and review the difference.
+
+mod common;
+
+use core::future::poll_fn;
+use core::task::Poll;
+use std::convert::Infallible;
+
+use common::{FIRMWARE, MAC_ADDRESS};
+use embassy_futures::block_on;
+use embassy_futures::select::{select3, Either3};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal_async::digital::Wait;
+use nrf70::bus::replay::{ReplayBus, ReplayError};
+use nrf70::bus::trace::{Access, Record, TraceSink, TracingBus};
+use nrf70::bus::Bus;
+use nrf70::sim::Simulator;
+use nrf70::State;
+
+const BOOT_TRACE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/traces/boot.trace");
+
+static BOOT_TRACE: &[u8] = include_bytes!("traces/boot.trace");
+
+/// Collects the serialized records.
+#[derive(Default)]
+struct Recording(Vec<u8>);
+
+impl TraceSink for Recording {
+    fn record(&mut self, record: &Record<'_>) {
+        let start = self.0.len();
+        self.0.resize(start + record.encoded_len(), 0);
+        record.encode(&mut self.0[start..]).unwrap();
+    }
+}
+
+/// A pin which is never driven, for the power supplies and for an interrupt line which never fires.
+/// Without events from the firmware, the driver goes quiet once it has sent the init command at
+/// the end of the boot.
+struct Unconnected;
+
+impl ErrorType for Unconnected {
+    type Error = Infallible;
+}
+
+impl OutputPin for Unconnected {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl InputPin for Unconnected {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(true)
+    }
+}
+
+impl Wait for Unconnected {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|_| Poll::Pending).await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|_| Poll::Pending).await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|_| Poll::Pending).await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|_| Poll::Pending).await
+    }
+}
+
+fn trace(records: &[(Access, u32, &[u8])]) -> Vec<u8> {
+    let mut recording = Recording::default();
+
+    for &(access, address, data) in records {
+        recording.record(&Record {
+            timestamp: Instant::from_micros(0),
+            access,
+            address,
+            data,
+        });
+    }
+
+    recording.0
+}
+
+#[test]
+fn boot_sequence_is_unchanged() {
+    let replay = ReplayBus::new(BOOT_TRACE);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) =
+        block_on(nrf70::new(&mut state, &replay, Unconnected, Unconnected, Unconnected));
+
+    let finished = poll_fn(|_| {
+        if replay.is_finished() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    });
+
+    match block_on(select3(runner.run(), control.init(FIRMWARE), finished)) {
+        Either3::First(error) => panic!(
+            "Runner stopped with {:?} after {} records: {:?}, expected {:?}",
+            error,
+            replay.replayed(),
+            replay.error(),
+            replay.expected()
+        ),
+        Either3::Second(result) => panic!("Init finished without the firmware: {result:?}"),
+        Either3::Third(()) => {}
+    }
+
+    assert!(replay.replayed() > 0);
+}
+
+#[test]
+fn replay_answers_reads() {
+    let trace = trace(&[
+        (Access::ReadSr1, 0, &[0x06]),
+        (Access::Read, 0x0C_0000, &[1, 2, 3, 4, 5, 6, 7, 8]),
+    ]);
+
+    let replay = ReplayBus::new(&trace);
+    let mut bus = &replay;
+
+    assert_eq!(block_on(bus.read_sr1()), Ok(0x06));
+
+    let mut words = [0u32; 2];
+    block_on(bus.read_with_latency(0x0C_0000, 0, &mut words)).unwrap();
+
+    assert_eq!(words, [0x0403_0201, 0x0807_0605]);
+    assert!(replay.is_finished());
+    assert_eq!(block_on(bus.read_sr2()), Err(ReplayError::EndOfTrace));
+}
+
+#[test]
+fn replay_checks_writes() {
+    let trace = trace(&[
+        (Access::Write, 0x04_8C20, &[1, 0, 0, 0]),
+        (Access::Write, 0x04_8C24, &[2, 0, 0, 0]),
+    ]);
+
+    let replay = ReplayBus::new(&trace);
+    let mut bus = &replay;
+
+    block_on(bus.write(0x04_8C20, &[1])).unwrap();
+
+    assert_eq!(replay.replayed(), 1);
+    assert_eq!(replay.expected().map(|record| record.address), Some(0x04_8C24));
+
+    // Other data than recorded, after which the replay is off track
+    let mismatch = ReplayError::DataMismatch {
+        index: 1,
+        address: 0x04_8C24,
+    };
+
+    assert_eq!(block_on(bus.write(0x04_8C24, &[3])), Err(mismatch));
+    assert_eq!(block_on(bus.write(0x04_8C24, &[2])), Err(mismatch));
+    assert!(!replay.is_finished());
+}
+
+#[test]
+fn replay_fails_on_unexpected_access() {
+    let trace = trace(&[(Access::Read, 0x0C_0000, &[0; 8])]);
+
+    let replay = ReplayBus::new(&trace);
+    let mut bus = &replay;
+
+    // A read of the right address, but shorter than recorded
+    let mut words = [0u32; 1];
+
+    assert_eq!(
+        block_on(bus.read(0x0C_0000, &mut words)),
+        Err(ReplayError::UnexpectedAccess {
+            index: 0,
+            access: Access::Read,
+            address: 0x0C_0000
+        })
+    );
+    assert_eq!(replay.replayed(), 0);
+}
+
+/// Records the boot sequence on the simulated chip into the trace replayed above.
+#[test]
+#[ignore = "records the trace of the boot sequence"]
+fn record_boot_sequence() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut recording = Recording::default();
+
+    {
+        let bus = TracingBus::new(simulator.clone(), &mut recording);
+        let mut state = State::new();
+        let (_device, mut control, mut runner) = block_on(nrf70::new(
+            &mut state,
+            bus,
+            simulator.bucken(),
+            simulator.iovdd_ctl(),
+            Unconnected,
+        ));
+
+        // The driver is idle once it waits for the firmware to tell that init is done
+        match block_on(select3(
+            runner.run(),
+            control.init(FIRMWARE),
+            Timer::after(Duration::from_secs(1)),
+        )) {
+            Either3::First(error) => panic!("Runner stopped: {error:?}"),
+            Either3::Second(result) => panic!("Init finished without the firmware: {result:?}"),
+            Either3::Third(()) => {}
+        }
+    }
+
+    std::fs::write(BOOT_TRACE_PATH, &recording.0).unwrap();
+}
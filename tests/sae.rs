@@ -0,0 +1,299 @@
+//! Verifies SAE against the published test vectors of IEEE 802.11-2020 annex J.10 and hostap,
+//! and a complete commit and confirm exchange and 4-way handshake with an AP calculated with an
+//! independent implementation.
+//!
+//! Published: the hash-to-element PT and password element, and the rand, mask and commit scalar of
+//! hunting-and-pecking. Calculated: the hunting-and-pecking password element and commit element,
+//! everything the AP sends, and the resulting keys.
+
+mod common;
+
+use common::Repeat;
+use nrf70::supplicant::{
+    check_rsn_element,
+    sae::{self, Element, Sae},
+    Akm, Supplicant, SupplicantError, RSNXE_HASH_TO_ELEMENT, RSN_ELEMENT_SAE,
+};
+
+fn hex(string: &str) -> Vec<u8> {
+    (0..string.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&string[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn hex32(string: &str) -> [u8; 32] {
+    hex(string).try_into().unwrap()
+}
+
+// --- Hash-to-element ---
+
+#[test]
+fn hash_to_element() {
+    let pt = sae::derive_pt(b"byteme", b"mekmitasdigoat", Some(b"psk4internet"));
+    assert_eq!(
+        pt.to_bytes().to_vec(),
+        hex("b6e38c98750c684b5d17c3d8c9a4100b39931279187ca6cced5f37ef46ddfa97\
+             5687e972e50f73e3898861e7edad21bea7d5f622df88243bb804920ae8e647fa")
+    );
+
+    let password_element = sae::password_element_from_pt(
+        &pt,
+        &[0x00, 0x09, 0x5b, 0x66, 0xec, 0x1e],
+        &[0x00, 0x0b, 0x6b, 0xd9, 0x02, 0x46],
+    );
+    assert_eq!(
+        password_element.to_bytes().to_vec(),
+        hex("c93049b9e64000f848201649e999f2b5c22dea69b5632c9df4d633b8aa1f6c1e\
+             73634e94b53d82e7383a8d258199d9dc1a5ee8269d060382ccbf33e614ff59a0")
+    );
+
+    // The order of the addresses does not matter
+    assert_eq!(
+        sae::password_element_from_pt(
+            &pt,
+            &[0x00, 0x0b, 0x6b, 0xd9, 0x02, 0x46],
+            &[0x00, 0x09, 0x5b, 0x66, 0xec, 0x1e],
+        ),
+        password_element
+    );
+}
+
+// --- Hunting-and-pecking and the commit and confirm exchange ---
+
+const STATION_ADDRESS: [u8; 6] = [0x4d, 0x3f, 0x2f, 0xff, 0xe3, 0x87];
+const AP_ADDRESS: [u8; 6] = [0xa5, 0xd8, 0xaa, 0x95, 0x8e, 0x3c];
+const PASSWORD: &[u8] = b"mekmitasdigoat";
+
+const RAND: &str = "a906f61e4d3a5d4eb2965ff34cf917dd044445c878c17ca5d5b93786da9f83cf";
+const MASK: &str = "4234b4fb17aa435c52fbfdebe64039b43478200e54ff7b6e07b69cad74153c15";
+
+const SCALAR: &str = "eb3bab1964e4a0ab05925ddf3339519138bc65d6cdc0f813dd6fd4344eb4bfe4";
+const ELEMENT: &str = "94f55919913e4585ba03b9d3e64e57a90c4337cc194238d52940d1444bd3e83a\
+                       13c6f206beebeefce8c4c2617b851fddf3ae45783fa77d9c95e4501b058416fa";
+
+const AP_SCALAR: &str = "0e4f14b63456ce3b199c6ef45848c05225c415b7396855f186d500f74bb02f13";
+const AP_ELEMENT: &str = "344a34b019d705f8a836db527046fb7962db423949d5fe892cd16ac018d07128\
+                          5723f1773e6d71d3077c71b1573a8efc4163907ca67c2ddfd469661a77e59235";
+
+const CONFIRM: &str = "0100bea26215f8f7ca90e457a2a48becd14032cf1165eb6e008cbfcd70de6ac4a970";
+const AP_CONFIRM: &str = "010077a2ad3a93b5b77ad783b1bc1a517ec1524e7a4e49639e18c2207817b60392b3";
+
+const PMK: &str = "8400ebb489b18659e8cac744ef6cf2953f71b784aeac412e5bed9f36a4ffd38d";
+
+fn password_element() -> Element {
+    sae::hunting_and_pecking(PASSWORD, None, &STATION_ADDRESS, &AP_ADDRESS).unwrap()
+}
+
+fn station() -> Sae {
+    Sae::with_randoms(&password_element(), false, &hex32(RAND), &hex32(MASK)).unwrap()
+}
+
+fn commit(scalar: &str, element: &str) -> Vec<u8> {
+    [&[19, 0][..], &hex(scalar), &hex(element)].concat()
+}
+
+#[test]
+fn hunting_and_pecking() {
+    assert_eq!(
+        password_element().to_bytes().to_vec(),
+        hex("da6eb7b06a1ac5624974f90afdd6a8e9d5722634cf987c34defc91a9874e5658\
+             f4fefd130bd5be08fe68af3e4a290272ec065fd3671f3c25bf8ec419ddc9b822")
+    );
+
+    assert_eq!(
+        sae::hunting_and_pecking(PASSWORD, None, &AP_ADDRESS, &STATION_ADDRESS).unwrap(),
+        password_element()
+    );
+}
+
+#[test]
+fn commit_and_confirm_exchange() {
+    let mut station = station();
+    let mut buffer = [0u8; 256];
+
+    assert_eq!(station.commit_status(), sae::STATUS_SUCCESS);
+
+    let length = station.write_commit(None, &mut buffer).unwrap();
+    assert_eq!(buffer[..length].to_vec(), commit(SCALAR, ELEMENT));
+
+    station.process_commit(&commit(AP_SCALAR, AP_ELEMENT)).unwrap();
+
+    let length = station.write_confirm(&mut buffer).unwrap();
+    assert_eq!(buffer[..length].to_vec(), hex(CONFIRM));
+
+    let pmk = station.process_confirm(&hex(AP_CONFIRM)).unwrap();
+    assert_eq!(pmk.to_vec(), hex(PMK));
+}
+
+#[test]
+fn wrong_password_fails_confirm() {
+    let password_element = sae::hunting_and_pecking(b"not the password", None, &STATION_ADDRESS, &AP_ADDRESS).unwrap();
+    let mut station = Sae::with_randoms(&password_element, false, &hex32(RAND), &hex32(MASK)).unwrap();
+
+    station.process_commit(&commit(AP_SCALAR, AP_ELEMENT)).unwrap();
+
+    assert_eq!(
+        station.process_confirm(&hex(AP_CONFIRM)),
+        Err(SupplicantError::InvalidConfirm)
+    );
+}
+
+#[test]
+fn invalid_commits_are_rejected() {
+    let mut station = station();
+
+    // Our own commit reflected back
+    assert_eq!(
+        station.process_commit(&commit(SCALAR, ELEMENT)),
+        Err(SupplicantError::InvalidCommit)
+    );
+
+    // An element which is not on the curve
+    let mut element = hex(AP_ELEMENT);
+    element[63] ^= 1;
+    assert_eq!(
+        station.process_commit(&[&[19, 0][..], &hex(AP_SCALAR), &element].concat()),
+        Err(SupplicantError::InvalidCommit)
+    );
+
+    // Scalars of 0, 1 and the order of the group
+    for scalar in [
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "0000000000000000000000000000000000000000000000000000000000000001",
+        "ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551",
+    ] {
+        assert_eq!(
+            station.process_commit(&commit(scalar, AP_ELEMENT)),
+            Err(SupplicantError::InvalidCommit)
+        );
+    }
+
+    // Another group
+    let mut other_group = commit(AP_SCALAR, AP_ELEMENT);
+    other_group[0] = 20;
+    assert_eq!(
+        station.process_commit(&other_group),
+        Err(SupplicantError::UnsupportedNetwork)
+    );
+
+    // Confirming without a valid commit
+    assert_eq!(
+        station.process_confirm(&hex(AP_CONFIRM)),
+        Err(SupplicantError::UnexpectedFrame)
+    );
+}
+
+#[test]
+fn anti_clogging_token() {
+    let token = [0xaa, 0xbb, 0xcc, 0xdd];
+    let mut buffer = [0u8; 256];
+
+    // With hunting-and-pecking the token follows the group
+    let station = station();
+    assert_eq!(
+        station.anti_clogging_token(&[19, 0, 0xaa, 0xbb, 0xcc, 0xdd]),
+        Ok(&token[..])
+    );
+
+    let length = station.write_commit(Some(&token), &mut buffer).unwrap();
+    assert_eq!(
+        buffer[..length].to_vec(),
+        [&[19, 0][..], &token, &hex(SCALAR), &hex(ELEMENT)].concat()
+    );
+
+    // With hash-to-element it is in a container element after the commit
+    let station = Sae::with_randoms(&password_element(), true, &hex32(RAND), &hex32(MASK)).unwrap();
+    assert_eq!(station.commit_status(), sae::STATUS_SAE_HASH_TO_ELEMENT);
+    assert_eq!(
+        station.anti_clogging_token(&[19, 0, 0xff, 5, 93, 0xaa, 0xbb, 0xcc, 0xdd]),
+        Ok(&token[..])
+    );
+    assert_eq!(
+        station.anti_clogging_token(&[19, 0, 0xaa, 0xbb, 0xcc, 0xdd]),
+        Err(SupplicantError::InvalidFrame)
+    );
+
+    let length = station.write_commit(Some(&token), &mut buffer).unwrap();
+    assert_eq!(
+        buffer[..length].to_vec(),
+        [&[19, 0][..], &hex(SCALAR), &hex(ELEMENT), &[0xff, 5, 93], &token].concat()
+    );
+}
+
+// --- 4-way handshake with the PMK from SAE ---
+
+const MESSAGE_1: &str = "0203005f020088001000000000000000011111111111111111111111111111111111111111111111111111111111\
+                         11111100000000000000000000000000000000000000000000000000000000000000000000000000000000000000\
+                         00000000000000";
+const MESSAGE_2: &str = "02030078020108000000000000000000012222222222222222222222222222222222222222222222222222222222\
+                         22222200000000000000000000000000000000000000000000000000000000000000009bb33d6dc0ebce30cfe2c9\
+                         b97843459b001930140100000fac040100000fac040100000fac08c000f40120";
+const MESSAGE_3: &str = "020300b70213c8001000000000000000021111111111111111111111111111111111111111111111111111111111\
+                         11111100000000000000000000000000000000050000000000000000000000000000001f2a2942b619ded9a9ac6e\
+                         e91c61968f0058c56c83123c129b674120fa4a17491a1d9c5182d1e1418b37bc145d591c3b4ff05f5c2d204d55ee\
+                         2ea21170d52efd975f9471fa432c71fe86458787086d37db984451bbab99ae28bc1865a14edbaa3336e629b0ccc4\
+                         57bf3b";
+const MESSAGE_4: &str = "0203005f020308000000000000000000020000000000000000000000000000000000000000000000000000000000\
+                         000000000000000000000000000000000000000000000000000000000000000000000058ed3758430337ce4ad909\
+                         6f768c5a3b0000";
+
+#[test]
+fn four_way_handshake() {
+    let own_elements = [&RSN_ELEMENT_SAE[..], &RSNXE_HASH_TO_ELEMENT].concat();
+
+    let mut supplicant = Supplicant::new(
+        Akm::Sae,
+        hex32(PMK),
+        &mut Repeat(0x22),
+        STATION_ADDRESS,
+        &own_elements,
+        AP_ADDRESS,
+        &RSN_ELEMENT_SAE,
+    )
+    .unwrap();
+
+    let mut response = [0u8; 256];
+
+    let outcome = supplicant.process(&hex(MESSAGE_1), &mut response).unwrap();
+    assert_eq!(response[..outcome.response_length.unwrap()].to_vec(), hex(MESSAGE_2));
+
+    let outcome = supplicant.process(&hex(MESSAGE_3), &mut response).unwrap();
+    assert_eq!(response[..outcome.response_length.unwrap()].to_vec(), hex(MESSAGE_4));
+    assert_eq!(
+        outcome.pairwise_key.unwrap().to_vec(),
+        hex("342f7e67841aed2d3627bb55a803f788")
+    );
+    assert_eq!(
+        outcome.group_key.unwrap().key.to_vec(),
+        hex("404142434445464748494a4b4c4d4e4f")
+    );
+
+    // WPA3-Personal always uses management frame protection
+    let integrity_group_key = outcome.integrity_group_key.unwrap();
+    assert_eq!(integrity_group_key.index, 4);
+    assert_eq!(
+        integrity_group_key.key.to_vec(),
+        hex("808182838485868788898a8b8c8d8e8f")
+    );
+}
+
+#[test]
+fn sae_requires_management_frame_protection() {
+    assert_eq!(check_rsn_element(&RSN_ELEMENT_SAE, Akm::Sae), Ok(true));
+
+    // SAE without management frame protection capability
+    let without_mfp = hex("30140100000fac040100000fac040100000fac080000");
+    assert_eq!(
+        check_rsn_element(&without_mfp, Akm::Sae),
+        Err(SupplicantError::UnsupportedNetwork)
+    );
+
+    // A WPA2 only network
+    let psk = hex("30140100000fac040100000fac040100000fac02c000");
+    assert_eq!(
+        check_rsn_element(&psk, Akm::Sae),
+        Err(SupplicantError::UnsupportedNetwork)
+    );
+    assert_eq!(check_rsn_element(&psk, Akm::Psk), Ok(true));
+}
@@ -0,0 +1,755 @@
+//! Exercises the driver end to end against the simulated chip: booting, querying the capabilities,
+//! scanning, joining and passing frames both ways.
+
+mod common;
+
+use core::future::poll_fn;
+use core::task::Poll;
+
+use common::{new, run, Repeat, FIRMWARE, MAC_ADDRESS};
+use embassy_futures::join::join;
+use embassy_futures::select::{select, Either};
+use embassy_futures::yield_now;
+use embassy_net_driver::{Driver, LinkState, RxToken, TxToken};
+use embassy_time::{Duration, Instant, Timer};
+use nrf70::control::{Band, ScanOptions, ScanType, DEFAULT_TIMEOUT};
+use nrf70::events::{Event, EventSubscriber, MAX_SUBSCRIBERS};
+use nrf70::sim::{AccessPoint, Scan, Simulator};
+use nrf70::supplicant::RSN_ELEMENT_PSK_MFP;
+use nrf70::{Control, Error, State};
+
+const BSSID: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+
+fn access_point(bssid: [u8; 6], ssid: &str, frequency: u32, rssi: i32) -> AccessPoint {
+    AccessPoint {
+        bssid,
+        ssid: ssid.as_bytes().to_vec(),
+        frequency,
+        rssi,
+        elements: Vec::new(),
+    }
+}
+
+/// Waits for the next event of the link: connected, disconnected or beacon loss.
+async fn next_link_event(subscriber: &mut EventSubscriber<'_>) -> Event {
+    loop {
+        let event = subscriber.next_message_pure().await;
+
+        if matches!(event, Event::Connected | Event::Disconnected { .. } | Event::BeaconLoss) {
+            return event;
+        }
+    }
+}
+
+/// An Ethernet frame carrying an IPv4 payload.
+fn ethernet_frame(destination: [u8; 6], source: [u8; 6], payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&destination);
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&[0x08, 0x00]);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+#[test]
+fn init() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+    });
+
+    assert!(simulator.is_interface_up());
+    assert_eq!(simulator.mac_address(), MAC_ADDRESS);
+}
+
+#[test]
+fn init_waits_for_the_boot_in_progress() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    run(&mut runner, async {
+        // Given up once the firmware is loaded and being initialized, as when timing out
+        let initializing = async {
+            while simulator.boots() == 0 {
+                yield_now().await;
+            }
+        };
+
+        assert!(matches!(
+            select(control.init(FIRMWARE), initializing).await,
+            Either::Second(())
+        ));
+
+        control.init(FIRMWARE).await.unwrap();
+    });
+
+    assert_eq!(simulator.boots(), 1);
+    assert!(simulator.is_interface_up());
+}
+
+#[test]
+fn capabilities() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    // The wiphy event is larger than an event buffer, so it arrives in fragments
+    let capabilities = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        control.capabilities().await.unwrap()
+    });
+
+    assert_eq!(capabilities.bands.len(), 1);
+
+    let band = capabilities.band(Band::Band2G4).unwrap();
+    let channels: Vec<_> = band.channels.iter().map(|channel| channel.number).collect();
+
+    assert_eq!(channels, (1..=13).collect::<Vec<_>>());
+    assert_eq!(band.max_tx_power(), Some(20));
+}
+
+#[test]
+fn scan() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    simulator.add_access_point(access_point(BSSID, "first", 2412, -40));
+    simulator.add_access_point(access_point([0x02, 0, 0, 0, 0, 0x02], "second", 2437, -70));
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let results = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+
+        let mut scanner = control.scan(ScanOptions::default()).await.unwrap();
+        let mut results = Vec::new();
+
+        while let Some(result) = scanner.next().await.unwrap() {
+            results.push(result);
+        }
+
+        results
+    });
+
+    let results: Vec<_> = results
+        .iter()
+        .map(|result| (result.bssid, result.ssid.to_vec(), result.channel, result.rssi))
+        .collect();
+
+    assert_eq!(
+        results,
+        [
+            (BSSID, b"first".to_vec(), 1, -40),
+            ([0x02, 0, 0, 0, 0, 0x02], b"second".to_vec(), 6, -70)
+        ]
+    );
+}
+
+#[test]
+fn scan_options_in_the_command() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    // A vendor specific element
+    let probe_ies = [0xdd, 0x05, 0x00, 0x11, 0x22, 0x01, 0x02];
+
+    run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+
+        let mut options = ScanOptions::default();
+        options.ssids = &[b"hidden", b"HomeWiFi"];
+        options.bssid = Some(BSSID);
+        options.channels = &[1, 13, 36];
+        options.band = Some(Band::Band5G);
+        options.probe_ies = &probe_ies;
+        options.scan_type = ScanType::Active;
+        options.dwell_time = Some(Duration::from_millis(30));
+
+        let mut scanner = control.scan(options).await.unwrap();
+        while scanner.next().await.unwrap().is_some() {}
+        drop(scanner);
+
+        let mut scanner = control.scan(ScanOptions::default()).await.unwrap();
+        while scanner.next().await.unwrap().is_some() {}
+        drop(scanner);
+
+        // More SSIDs than the firmware takes, which is never sent
+        let mut options = ScanOptions::default();
+        options.ssids = &[b"first", b"second", b"third"];
+        assert!(matches!(control.scan(options).await, Err(Error::InvalidArgument)));
+    });
+
+    assert_eq!(
+        simulator.take_scans(),
+        [
+            Scan {
+                passive: false,
+                ssids: vec![b"hidden".to_vec(), b"HomeWiFi".to_vec()],
+                bssid: BSSID,
+                bands: 1 << 1,
+                frequencies: vec![2412, 2472, 5180],
+                probe_ies: probe_ies.to_vec(),
+                dwell_time_active: 30,
+                dwell_time_passive: 0,
+            },
+            Scan {
+                passive: true,
+                ssids: Vec::new(),
+                bssid: [0; 6],
+                bands: 0,
+                frequencies: Vec::new(),
+                probe_ies: Vec::new(),
+                dwell_time_active: 0,
+                dwell_time_passive: 0,
+            },
+        ]
+    );
+}
+
+#[test]
+fn empty_scan_ends_without_waiting_for_the_timeout() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let result = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        control.set_timeout(Duration::from_secs(60));
+
+        let mut scanner = control.scan(ScanOptions::default()).await.unwrap();
+
+        match select(scanner.next(), Timer::after(Duration::from_secs(1))).await {
+            Either::First(result) => result.map(|result| result.is_none()),
+            Either::Second(()) => Err(Error::Timeout),
+        }
+    });
+
+    assert!(matches!(result, Ok(true)));
+}
+
+#[test]
+fn dropped_scan_leaves_control_usable() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    simulator.add_access_point(access_point(BSSID, "first", 2412, -40));
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let results = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+
+        // Cancelled while its command is on the way to the runner
+        select(control.scan(ScanOptions::default()), yield_now()).await;
+
+        let mut scanner = control.scan(ScanOptions::default()).await.unwrap();
+        let mut results = Vec::new();
+
+        while let Some(result) = scanner.next().await.unwrap() {
+            results.push(result.bssid);
+        }
+
+        results
+    });
+
+    assert_eq!(results, [BSSID]);
+}
+
+#[test]
+fn timed_out_commands_give_up_their_slot() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let capabilities = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+
+        // The simulator never answers a statistics request, so each one is left waiting for a
+        // response, more of them than there are slots for actions
+        control.set_timeout(Duration::from_millis(20));
+
+        for _ in 0..8 {
+            assert!(matches!(control.get_stats().await, Err(Error::Timeout)));
+        }
+
+        control.set_timeout(DEFAULT_TIMEOUT);
+        control.capabilities().await
+    });
+
+    assert!(capabilities.is_ok());
+}
+
+#[test]
+fn scan_results_wait_for_a_slow_reader() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+
+    // More results than the runner can queue for the control before it has to wait for it
+    let bssids: Vec<_> = (0..10u8).map(|index| [0x02, 0, 0, 0, 1, index]).collect();
+
+    for bssid in &bssids {
+        simulator.add_access_point(access_point(*bssid, "other", 2412, -60));
+    }
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let results = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+
+        let mut scanner = control.scan(ScanOptions::default()).await.unwrap();
+        let mut results = Vec::new();
+
+        while let Some(result) = scanner.next().await.unwrap() {
+            results.push(result.bssid);
+            Timer::after(Duration::from_millis(5)).await;
+        }
+
+        results
+    });
+
+    assert_eq!(results, bssids);
+}
+
+#[test]
+fn held_scanner_does_not_hold_up_the_runner() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+
+    for index in 0..10u8 {
+        simulator.add_access_point(access_point([0x02, 0, 0, 0, 1, index], "other", 2412, -60));
+    }
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut other = control.clone();
+
+    let (capabilities, result) = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        other.set_timeout(Duration::from_secs(1));
+
+        // The first result requests the rest, which are then left unread
+        let mut scanner = control.scan(ScanOptions::default()).await.unwrap();
+        assert!(scanner.next().await.unwrap().is_some());
+
+        let capabilities = other.capabilities().await;
+
+        // The scanner finds out that it lost results
+        let result = loop {
+            match scanner.next().await {
+                Ok(Some(_)) => continue,
+                result => break result.map(|_| ()),
+            }
+        };
+
+        (capabilities, result)
+    });
+
+    assert!(capabilities.is_ok());
+    assert!(matches!(result, Err(Error::BufferOverflow)));
+}
+
+#[test]
+fn join_and_exchange_frames() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    simulator.add_access_point(access_point(BSSID, "open", 2437, -50));
+
+    let mut state = State::new();
+    let (mut device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let outgoing = ethernet_frame(BSSID, MAC_ADDRESS, &[0x45; 64]);
+    let incoming = ethernet_frame(MAC_ADDRESS, [0x02, 0, 0, 0, 0, 0x99], &[0x45; 100]);
+
+    let received = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        control.join_open("open", None).await.unwrap();
+
+        assert_eq!(simulator.associated(), Some(BSSID));
+        assert!(matches!(
+            poll_fn(|cx| Poll::Ready(device.link_state(cx))).await,
+            LinkState::Up
+        ));
+
+        poll_fn(|cx| match device.transmit(cx) {
+            Some(token) => {
+                token.consume(outgoing.len(), |buffer| buffer.copy_from_slice(&outgoing));
+                Poll::Ready(())
+            }
+            None => Poll::Pending,
+        })
+        .await;
+
+        simulator.receive(&incoming).unwrap();
+
+        let received = poll_fn(|cx| match device.receive(cx) {
+            Some((token, _)) => Poll::Ready(token.consume(|buffer| buffer.to_vec())),
+            None => Poll::Pending,
+        })
+        .await;
+
+        control.leave().await.unwrap();
+
+        received
+    });
+
+    assert_eq!(received, incoming);
+    assert_eq!(simulator.take_transmitted(), [outgoing]);
+    assert_eq!(simulator.associated(), None);
+}
+
+#[test]
+fn subscribers_follow_the_link() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    simulator.add_access_point(access_point(BSSID, "open", 2437, -50));
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut subscriber = control.subscribe().unwrap();
+
+    let events = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        control.join_open("open", None).await.unwrap();
+
+        let connected = next_link_event(&mut subscriber).await;
+
+        control.leave().await.unwrap();
+
+        let disconnected = next_link_event(&mut subscriber).await;
+
+        // The deauthentication of the UMAC must not come through as a lost AP
+        control.join_open("open", None).await.unwrap();
+
+        [connected, disconnected, next_link_event(&mut subscriber).await]
+    });
+
+    assert!(matches!(
+        events,
+        [
+            Event::Connected,
+            Event::Disconnected { reason: Some(3) },
+            Event::Connected
+        ]
+    ));
+}
+
+#[test]
+fn beacon_loss_is_published() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    simulator.add_access_point(access_point(BSSID, "open", 2437, -50));
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut subscriber = control.subscribe().unwrap();
+
+    let events = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        control.join_open("open", None).await.unwrap();
+
+        assert!(matches!(next_link_event(&mut subscriber).await, Event::Connected));
+
+        simulator.lose_access_point();
+
+        [
+            next_link_event(&mut subscriber).await,
+            next_link_event(&mut subscriber).await,
+        ]
+    });
+
+    assert!(matches!(
+        events,
+        [Event::BeaconLoss, Event::Disconnected { reason: Some(4) }]
+    ));
+    assert_eq!(simulator.associated(), None);
+}
+
+#[test]
+fn leave_after_the_access_point_is_lost() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    simulator.add_access_point(access_point(BSSID, "open", 2437, -50));
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut subscriber = control.subscribe().unwrap();
+
+    let result = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        control.join_open("open", None).await.unwrap();
+
+        simulator.lose_access_point();
+
+        while !matches!(next_link_event(&mut subscriber).await, Event::Disconnected { .. }) {}
+
+        // Nothing to leave, so the simulator is not asked to deauthenticate, which it would not
+        // answer without an association
+        control.set_timeout(Duration::from_secs(60));
+
+        match select(control.leave(), Timer::after(Duration::from_secs(1))).await {
+            Either::First(result) => result,
+            Either::Second(()) => Err(Error::Timeout),
+        }
+    });
+
+    assert!(result.is_ok());
+}
+
+#[test]
+fn leave_after_a_dropped_join() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    simulator.add_access_point(AccessPoint {
+        elements: RSN_ELEMENT_PSK_MFP.to_vec(),
+        ..access_point(BSSID, "IEEE", 2437, -50)
+    });
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let result = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+
+        // Given up while waiting for the handshake, which the AP never starts
+        let associated = async {
+            while simulator.associated().is_none() {
+                yield_now().await;
+            }
+        };
+
+        let mut rng = Repeat(0x22);
+        let join = control.join_wpa2("IEEE", "password", None, &mut rng);
+        assert!(matches!(select(join, associated).await, Either::Second(())));
+
+        control.leave().await
+    });
+
+    assert!(result.is_ok());
+    assert_eq!(simulator.associated(), None);
+}
+
+// --- SA Query ---
+
+// The AP's part of the 4-way handshake with management frame protection from tests/supplicant.rs,
+// for the passphrase "password" of the network "IEEE"
+const STATION_ADDRESS: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+const MESSAGE_1: &str = "0203005f02008a001000000000000000011111111111111111111111111111111111111111111111111111111111\
+                         11111100000000000000000000000000000000000000000000000000000000000000000000000000000000000000\
+                         00000000000000";
+const MFP_MESSAGE_3: &str = "020300b70213ca001000000000000000021111111111111111111111111111111111111111111111111111111\
+                             11111111100000000000000000000000000000000050000000000000000000000000000006239f30b59a594f0\
+                             512922675362230e0058324d1a6c9c603433f6a48c58cf1bd6788e8c0da1b3ad0cc124240e332062d097b89cc\
+                             459f8963af10708b7548c283946911dc88bfc4606b6304f11a615d9ed604dba72a12441b67b32978e4b031eba\
+                             636bdeaee4421e8e6e";
+
+/// Class 3 frame received from nonassociated station
+const REASON_CLASS_3_FRAME: u16 = 7;
+
+fn hex(string: &str) -> Vec<u8> {
+    (0..string.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&string[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// Joins "IEEE" at [`BSSID`], which offers management frame protection, playing the part of the AP
+/// in the handshake.
+async fn join_protected(control: &mut Control<'_>, simulator: &Simulator) {
+    simulator.add_access_point(AccessPoint {
+        elements: RSN_ELEMENT_PSK_MFP.to_vec(),
+        ..access_point(BSSID, "IEEE", 2437, -50)
+    });
+
+    let handshake = async {
+        while simulator.associated().is_none() {
+            yield_now().await;
+        }
+
+        for message in [MESSAGE_1, MFP_MESSAGE_3] {
+            let mut frame = Vec::new();
+            frame.extend_from_slice(&STATION_ADDRESS);
+            frame.extend_from_slice(&BSSID);
+            frame.extend_from_slice(&[0x88, 0x8E]);
+            frame.extend_from_slice(&hex(message));
+
+            simulator.receive(&frame).unwrap();
+        }
+    };
+
+    let (result, ()) = join(
+        control.join_wpa2("IEEE", "password", None, &mut Repeat(0x22)),
+        handshake,
+    )
+    .await;
+
+    result.unwrap();
+}
+
+#[test]
+fn spoofed_deauthentication_is_checked_with_the_access_point() {
+    let simulator = Simulator::new(STATION_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut subscriber = control.subscribe().unwrap();
+
+    let event = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        join_protected(&mut control, &simulator).await;
+
+        assert!(matches!(next_link_event(&mut subscriber).await, Event::Connected));
+
+        simulator.spoof_deauthentication(REASON_CLASS_3_FRAME);
+
+        // Longer than the query may take
+        select(
+            next_link_event(&mut subscriber),
+            Timer::after(Duration::from_millis(1500)),
+        )
+        .await
+    });
+
+    assert!(matches!(event, Either::Second(())));
+    assert_eq!(simulator.associated(), Some(BSSID));
+
+    // Answered right away
+    let frames = simulator.take_management_frames();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0][24..26], [8, 0]);
+}
+
+#[test]
+fn deauthentication_for_other_reasons_is_not_checked() {
+    /// Deauthenticated because the sending station is leaving
+    const REASON_LEAVING: u16 = 3;
+
+    let simulator = Simulator::new(STATION_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut subscriber = control.subscribe().unwrap();
+
+    let event = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        join_protected(&mut control, &simulator).await;
+
+        assert!(matches!(next_link_event(&mut subscriber).await, Event::Connected));
+
+        simulator.spoof_deauthentication(REASON_LEAVING);
+
+        select(
+            next_link_event(&mut subscriber),
+            Timer::after(Duration::from_millis(500)),
+        )
+        .await
+    });
+
+    assert!(matches!(event, Either::Second(())));
+    assert_eq!(simulator.associated(), Some(BSSID));
+    assert!(simulator.take_management_frames().is_empty());
+}
+
+#[test]
+fn silent_access_point_is_left_after_the_query() {
+    let simulator = Simulator::new(STATION_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut subscriber = control.subscribe().unwrap();
+
+    let (event, elapsed) = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        join_protected(&mut control, &simulator).await;
+
+        assert!(matches!(next_link_event(&mut subscriber).await, Event::Connected));
+
+        let started = Instant::now();
+        simulator.restart_access_point();
+
+        (next_link_event(&mut subscriber).await, started.elapsed())
+    });
+
+    // Previous authentication no longer valid, after the maximum timeout of the query
+    assert!(matches!(event, Event::Disconnected { reason: Some(2) }));
+    assert!(elapsed >= Duration::from_millis(1000));
+    assert_eq!(simulator.associated(), None);
+
+    // Retried until then
+    let frames = simulator.take_management_frames();
+    assert!(frames.len() > 1);
+    assert!(frames.iter().all(|frame| frame[24..26] == [8, 0]));
+}
+
+#[test]
+fn subscribers_are_limited() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut state = State::new();
+    let (_device, control, _runner) = new(&mut state, &simulator);
+
+    let subscribers: Vec<_> = (0..MAX_SUBSCRIBERS).map(|_| control.subscribe().unwrap()).collect();
+
+    assert!(matches!(control.subscribe(), Err(Error::Busy)));
+
+    drop(subscribers);
+    assert!(control.subscribe().is_ok());
+}
+
+#[test]
+fn handles_have_commands_in_flight_at_once() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut other = control.clone();
+
+    let (first, second) = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+        join(control.capabilities(), other.capabilities()).await
+    });
+
+    assert_eq!(first.unwrap().bands.len(), 1);
+    assert_eq!(second.unwrap().bands.len(), 1);
+}
+
+#[test]
+fn handles_take_turns_to_scan_and_join() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+    simulator.add_access_point(access_point(BSSID, "open", 2437, -50));
+
+    let mut state = State::new();
+    let (_device, mut control, mut runner) = new(&mut state, &simulator);
+
+    let mut other = control.clone();
+
+    let (results, joined) = run(&mut runner, async {
+        control.init(FIRMWARE).await.unwrap();
+
+        let scan = async {
+            let mut scanner = control.scan(ScanOptions::default()).await.unwrap();
+            let mut results = Vec::new();
+
+            while let Some(result) = scanner.next().await.unwrap() {
+                results.push(result.bssid);
+            }
+
+            results
+        };
+
+        join(scan, other.join_open("open", None)).await
+    });
+
+    assert_eq!(results, [BSSID]);
+    assert!(joined.is_ok());
+    assert_eq!(simulator.associated(), Some(BSSID));
+}
+
+#[test]
+fn bus_is_powered_off_until_the_supplies_are_enabled() {
+    let simulator = Simulator::new(MAC_ADDRESS);
+
+    let mut bus = simulator.clone();
+    let result = embassy_futures::block_on(nrf70::bus::Bus::read_sr0(&mut bus));
+
+    assert_eq!(result, Err(nrf70::sim::SimulatorError::PoweredOff));
+}
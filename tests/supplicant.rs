@@ -0,0 +1,461 @@
+//! Verifies the WPA2-PSK supplicant against the test vectors of IEEE 802.11-2020 annex J and a
+//! complete 4-way and group key handshake calculated with an independent implementation, with
+//! and without management frame protection.
+
+mod common;
+
+use common::Repeat;
+use nrf70::supplicant::{
+    check_rsn_element,
+    crypto::{derive_pmk, prf, unwrap_key_data},
+    Akm, Supplicant, SupplicantError, RSN_ELEMENT_PSK, RSN_ELEMENT_PSK_MFP,
+};
+
+fn hex(string: &str) -> Vec<u8> {
+    (0..string.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&string[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+// --- IEEE 802.11-2020 J.4.2, PSK mapping ---
+
+#[test]
+fn pmk_from_passphrase() {
+    let vectors = [
+        (
+            "password",
+            "IEEE",
+            "f42c6fc52df0ebef9ebb4b90b38a5f902e83fe1b135a70e23aed762e9710a12e",
+        ),
+        (
+            "ThisIsAPassword",
+            "ThisIsASSID",
+            "0dc0d6eb90555ed6419756b9a15ec3e3209b63df707dd508d14581f8982721af",
+        ),
+        (
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            "ZZZZZZZZZZZZZZZZZZZZZZZZZZZZZZZZ",
+            "becb93866bb8c3832cb777c2f559807c8c59afcb6eae734885001300a981cc62",
+        ),
+    ];
+
+    for (passphrase, ssid, pmk) in vectors {
+        assert_eq!(
+            derive_pmk(passphrase.as_bytes(), ssid.as_bytes()).unwrap().to_vec(),
+            hex(pmk)
+        );
+    }
+}
+
+#[test]
+fn pmk_rejects_invalid_passphrases() {
+    assert_eq!(derive_pmk(b"short", b"IEEE"), Err(SupplicantError::InvalidPassphrase));
+    assert_eq!(
+        derive_pmk(&[b'a'; 64], b"IEEE"),
+        Err(SupplicantError::InvalidPassphrase)
+    );
+    assert_eq!(
+        derive_pmk(b"pass\nword", b"IEEE"),
+        Err(SupplicantError::InvalidPassphrase)
+    );
+}
+
+// --- IEEE 802.11-2020 J.3.2, PRF ---
+
+#[test]
+fn prf_test_vectors() {
+    let mut output = [0u8; 64];
+
+    prf(&[0x0b; 20], b"prefix", b"Hi There", &mut output);
+    assert_eq!(
+        output.to_vec(),
+        hex("bcd4c650b30b9684951829e0d75f9d54b862175ed9f00606e17d8da35402ffee\
+             75df78c3d31e0f889f012120c0862beb67753e7439ae242edb8373698356cf5a")
+    );
+
+    prf(b"Jefe", b"prefix-2", b"what do ya want for nothing?", &mut output);
+    assert_eq!(
+        output.to_vec(),
+        hex("47c4908e30c947521ad20be9053450ecbea23d3aa604b77326d8b3825ff7475c\
+             06f51fb9c5313d1e9f90d897d134b72e090fc23150bc8414382043418678e700")
+    );
+}
+
+// --- RFC 3394 4.1, AES key wrap ---
+
+#[test]
+fn key_data_unwrap() {
+    let kek: [u8; 16] = hex("000102030405060708090a0b0c0d0e0f").try_into().unwrap();
+    let wrapped = hex("1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5");
+
+    let mut key = [0u8; 16];
+    unwrap_key_data(&kek, &wrapped, &mut key).unwrap();
+    assert_eq!(key.to_vec(), hex("00112233445566778899aabbccddeeff"));
+
+    let mut tampered = wrapped.clone();
+    tampered[0] ^= 1;
+    assert_eq!(
+        unwrap_key_data(&kek, &tampered, &mut key),
+        Err(SupplicantError::InvalidKeyData)
+    );
+}
+
+// --- Handshakes ---
+
+const AUTHENTICATOR_ADDRESS: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const SUPPLICANT_ADDRESS: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+
+const MESSAGE_1: &str = "0203005f02008a001000000000000000011111111111111111111111111111111111111111111111111111111111\
+                         11111100000000000000000000000000000000000000000000000000000000000000000000000000000000000000\
+                         00000000000000";
+const MESSAGE_2: &str = "0203007502010a000000000000000000012222222222222222222222222222222222222222222222222222222222\
+                         2222220000000000000000000000000000000000000000000000000000000000000000688787e519a79f7230dac6\
+                         4e7f72e155001630140100000fac040100000fac040100000fac020000";
+const MESSAGE_3: &str = "020300970213ca001000000000000000021111111111111111111111111111111111111111111111111111111111\
+                         11111100000000000000000000000000000000050000000000000000000000000000000a6bcbd13401678828291c\
+                         3c5e9c1ba700384c15664afdfa01700f8dc7deeaf760e0330a572ccb46b321eadbfe40f7b0cca5154c4e300df5a5\
+                         9e5c0d91167f3ec3968e4010a9c9b27d89";
+const MESSAGE_4: &str = "0203005f02030a000000000000000000020000000000000000000000000000000000000000000000000000000000\
+                         0000000000000000000000000000000000000000000000000000000000000000000000332e8d619ff65cfdea053b\
+                         664479549d0000";
+const GROUP_MESSAGE_1: &str = "0203007f021382001000000000000000030000000000000000000000000000000000000000000000000000\
+                               00000000000000000000000000000000000000000000090100000000000000000000000000004da903fb68\
+                               ee941722d2c3278de4008c0020da6416fedae68692eb8deb472b5379fa960c628721917a10a145f9b04bd1\
+                               2492";
+const GROUP_MESSAGE_2: &str = "0203005f020302000000000000000000030000000000000000000000000000000000000000000000000000\
+                               00000000000000000000000000000000000000000000000000000000000000000000000000007d5b790b42\
+                               356d9c76ba7578e6abde630000";
+
+const MESSAGE_3_RETRANSMITTED: &str = "020300970213ca0010000000000000000311111111111111111111111111111111111111111111\
+                                       111111111111111111110000000000000000000000000000000005000000000000000000000000\
+                                       000000de2b28e9985056631c2401897f52159400384c15664afdfa01700f8dc7deeaf760e0330a\
+                                       572ccb46b321eadbfe40f7b0cca5154c4e300df5a59e5c0d91167f3ec3968e4010a9c9b27d89";
+const MESSAGE_4_RETRANSMITTED: &str = "0203005f02030a0000000000000000000300000000000000000000000000000000000000000000\
+                                       000000000000000000000000000000000000000000000000000000000000000000000000000000\
+                                       000000bb29ef87725579cb1167b3290d2a17c40000";
+
+fn supplicant() -> Supplicant {
+    let pmk = derive_pmk(b"password", b"IEEE").unwrap();
+
+    Supplicant::new(
+        Akm::Psk,
+        pmk,
+        &mut Repeat(0x22),
+        SUPPLICANT_ADDRESS,
+        &RSN_ELEMENT_PSK,
+        AUTHENTICATOR_ADDRESS,
+        &RSN_ELEMENT_PSK,
+    )
+    .unwrap()
+}
+
+/// Runs the 4-way handshake, checking the responses and the installed keys.
+fn run_four_way_handshake(supplicant: &mut Supplicant) {
+    let mut response = [0u8; 256];
+
+    let outcome = supplicant.process(&hex(MESSAGE_1), &mut response).unwrap();
+    assert_eq!(response[..outcome.response_length.unwrap()].to_vec(), hex(MESSAGE_2));
+    assert!(outcome.pairwise_key.is_none());
+    assert!(outcome.group_key.is_none());
+
+    let outcome = supplicant.process(&hex(MESSAGE_3), &mut response).unwrap();
+    assert_eq!(response[..outcome.response_length.unwrap()].to_vec(), hex(MESSAGE_4));
+    assert_eq!(
+        outcome.pairwise_key.unwrap().to_vec(),
+        hex("5b88694a8268c05bfe4c53de87b97d81")
+    );
+
+    let group_key = outcome.group_key.unwrap();
+    assert_eq!(group_key.index, 1);
+    assert_eq!(group_key.key.to_vec(), hex("404142434445464748494a4b4c4d4e4f"));
+    assert_eq!(group_key.rsc, [5, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn four_way_handshake() {
+    run_four_way_handshake(&mut supplicant());
+}
+
+#[test]
+fn group_key_handshake() {
+    let mut supplicant = supplicant();
+    run_four_way_handshake(&mut supplicant);
+
+    let mut response = [0u8; 256];
+
+    let outcome = supplicant.process(&hex(GROUP_MESSAGE_1), &mut response).unwrap();
+    assert_eq!(
+        response[..outcome.response_length.unwrap()].to_vec(),
+        hex(GROUP_MESSAGE_2)
+    );
+    assert!(outcome.pairwise_key.is_none());
+
+    let group_key = outcome.group_key.unwrap();
+    assert_eq!(group_key.index, 2);
+    assert_eq!(group_key.key.to_vec(), hex("606162636465666768696a6b6c6d6e6f"));
+    assert_eq!(group_key.rsc, [9, 1, 0, 0, 0, 0]);
+}
+
+#[test]
+fn replayed_frames_are_rejected() {
+    let mut supplicant = supplicant();
+    run_four_way_handshake(&mut supplicant);
+
+    let mut response = [0u8; 256];
+
+    assert_eq!(
+        supplicant.process(&hex(MESSAGE_3), &mut response).err(),
+        Some(SupplicantError::Replayed)
+    );
+
+    supplicant.process(&hex(GROUP_MESSAGE_1), &mut response).unwrap();
+
+    assert_eq!(
+        supplicant.process(&hex(GROUP_MESSAGE_1), &mut response).err(),
+        Some(SupplicantError::Replayed)
+    );
+}
+
+#[test]
+fn retransmitted_message_3_does_not_reinstall_keys() {
+    let mut supplicant = supplicant();
+    run_four_way_handshake(&mut supplicant);
+
+    // Message 3 again with the next replay counter
+    let mut response = [0u8; 256];
+
+    let outcome = supplicant
+        .process(&hex(MESSAGE_3_RETRANSMITTED), &mut response)
+        .unwrap();
+    assert_eq!(
+        response[..outcome.response_length.unwrap()].to_vec(),
+        hex(MESSAGE_4_RETRANSMITTED)
+    );
+    assert!(outcome.pairwise_key.is_none());
+    assert!(outcome.group_key.is_none());
+}
+
+/// The SNonce in message 2.
+fn snonce(message_2: &[u8]) -> &[u8] {
+    &message_2[17..49]
+}
+
+#[test]
+fn new_handshakes_get_a_new_snonce() {
+    let mut supplicant = supplicant();
+    let mut response = [0u8; 256];
+
+    // The AP repeats message 1 when message 2 got lost, which gets the same answer
+    let length = supplicant
+        .process(&hex(MESSAGE_1), &mut response)
+        .unwrap()
+        .response_length
+        .unwrap();
+    assert_eq!(response[..length].to_vec(), hex(MESSAGE_2));
+
+    let length = supplicant
+        .process(&hex(MESSAGE_1), &mut response)
+        .unwrap()
+        .response_length
+        .unwrap();
+    assert_eq!(response[..length].to_vec(), hex(MESSAGE_2));
+
+    // A message 1 with another ANonce starts over
+    let mut message_1 = hex(MESSAGE_1);
+    message_1[17..49].fill(0x33);
+
+    let length = supplicant
+        .process(&message_1, &mut response)
+        .unwrap()
+        .response_length
+        .unwrap();
+    assert_ne!(snonce(&response[..length]), snonce(&hex(MESSAGE_2)));
+
+    // And so does rekeying once the handshake is done, even if the AP kept its ANonce
+    let mut supplicant = self::supplicant();
+    run_four_way_handshake(&mut supplicant);
+
+    let mut message_1 = hex(MESSAGE_1);
+    message_1[9..17].copy_from_slice(&3u64.to_be_bytes());
+
+    let length = supplicant
+        .process(&message_1, &mut response)
+        .unwrap()
+        .response_length
+        .unwrap();
+    assert_ne!(snonce(&response[..length]), snonce(&hex(MESSAGE_2)));
+}
+
+#[test]
+fn tampered_frames_are_rejected() {
+    let mut supplicant = supplicant();
+    let mut response = [0u8; 256];
+
+    supplicant.process(&hex(MESSAGE_1), &mut response).unwrap();
+
+    // Flip a bit in the key data
+    let mut message_3 = hex(MESSAGE_3);
+    let last = message_3.len() - 1;
+    message_3[last] ^= 1;
+
+    assert_eq!(
+        supplicant.process(&message_3, &mut response).err(),
+        Some(SupplicantError::InvalidMic)
+    );
+}
+
+#[test]
+fn message_3_without_message_1_is_rejected() {
+    let mut response = [0u8; 256];
+
+    assert_eq!(
+        supplicant().process(&hex(MESSAGE_3), &mut response).err(),
+        Some(SupplicantError::UnexpectedFrame)
+    );
+}
+
+#[test]
+fn downgraded_rsn_element_is_detected() {
+    // The beacon advertised CCMP and TKIP, while message 3 only has CCMP
+    let beacon_rsn_element = hex("30180100000fac040200000fac04000fac020100000fac020000");
+
+    let mut supplicant = Supplicant::new(
+        Akm::Psk,
+        derive_pmk(b"password", b"IEEE").unwrap(),
+        &mut Repeat(0x22),
+        SUPPLICANT_ADDRESS,
+        &RSN_ELEMENT_PSK,
+        AUTHENTICATOR_ADDRESS,
+        &beacon_rsn_element,
+    )
+    .unwrap();
+
+    let mut response = [0u8; 256];
+
+    supplicant.process(&hex(MESSAGE_1), &mut response).unwrap();
+
+    assert_eq!(
+        supplicant.process(&hex(MESSAGE_3), &mut response).err(),
+        Some(SupplicantError::RsnElementMismatch)
+    );
+}
+
+#[test]
+fn networks_without_psk_and_ccmp_are_unsupported() {
+    // TKIP only
+    let tkip = hex("30140100000fac020100000fac020100000fac020000");
+    // 802.1X authentication
+    let enterprise = hex("30140100000fac040100000fac040100000fac010000");
+
+    for rsn_element in [tkip, enterprise] {
+        assert!(matches!(
+            Supplicant::new(
+                Akm::Psk,
+                [0; 32],
+                &mut Repeat(0),
+                SUPPLICANT_ADDRESS,
+                &RSN_ELEMENT_PSK,
+                AUTHENTICATOR_ADDRESS,
+                &rsn_element
+            ),
+            Err(SupplicantError::UnsupportedNetwork)
+        ));
+    }
+}
+
+// --- Management frame protection ---
+
+// The same handshake with an AP capable of management frame protection, which hands out an IGTK
+// in message 3
+const MFP_MESSAGE_2: &str = "0203007502010a000000000000000000012222222222222222222222222222222222222222222222222222222\
+                             2222222220000000000000000000000000000000000000000000000000000000000000000419226b1991f5427\
+                             55a5622e069a567d001630140100000fac040100000fac040100000fac028000";
+const MFP_MESSAGE_3: &str = "020300b70213ca001000000000000000021111111111111111111111111111111111111111111111111111111\
+                             11111111100000000000000000000000000000000050000000000000000000000000000006239f30b59a594f0\
+                             512922675362230e0058324d1a6c9c603433f6a48c58cf1bd6788e8c0da1b3ad0cc124240e332062d097b89cc\
+                             459f8963af10708b7548c283946911dc88bfc4606b6304f11a615d9ed604dba72a12441b67b32978e4b031eba\
+                             636bdeaee4421e8e6e";
+const MFP_MESSAGE_3_WITHOUT_IGTK: &str = "020300970213ca00100000000000000002111111111111111111111111111111111111111111\
+                                          1111111111111111111111000000000000000000000000000000000500000000000000000000\
+                                          0000000000870fda1735d36959c9761ac4bc96dc7a0038327a588c3b139d85c49c45ca895b37\
+                                          c78adcabddedcb3a4a59d3a53d116fa9d7416405edfbd4afe395e54e9c1a03c3246c203a11c4\
+                                          bbbde8";
+
+fn supplicant_with_management_frame_protection() -> Supplicant {
+    Supplicant::new(
+        Akm::Psk,
+        derive_pmk(b"password", b"IEEE").unwrap(),
+        &mut Repeat(0x22),
+        SUPPLICANT_ADDRESS,
+        &RSN_ELEMENT_PSK_MFP,
+        AUTHENTICATOR_ADDRESS,
+        &RSN_ELEMENT_PSK_MFP,
+    )
+    .unwrap()
+}
+
+#[test]
+fn management_frame_protection_is_negotiated() {
+    assert_eq!(check_rsn_element(&RSN_ELEMENT_PSK, Akm::Psk), Ok(false));
+    assert_eq!(check_rsn_element(&RSN_ELEMENT_PSK_MFP, Akm::Psk), Ok(true));
+
+    // Capable with BIP-CMAC-128 as explicit group management cipher
+    let bip_cmac = hex("301a0100000fac040100000fac040100000fac0280000000000fac06");
+    assert_eq!(check_rsn_element(&bip_cmac, Akm::Psk), Ok(true));
+
+    // Required and capable, but with BIP-GMAC-256 which is not supported
+    let bip_gmac = hex("301a0100000fac040100000fac040100000fac02c0000000000fac0c");
+    assert_eq!(
+        check_rsn_element(&bip_gmac, Akm::Psk),
+        Err(SupplicantError::UnsupportedNetwork)
+    );
+
+    // Capable with BIP-GMAC-256 only, so the association goes ahead without protection
+    let bip_gmac = hex("301a0100000fac040100000fac040100000fac0280000000000fac0c");
+    assert_eq!(check_rsn_element(&bip_gmac, Akm::Psk), Ok(false));
+}
+
+#[test]
+fn four_way_handshake_with_management_frame_protection() {
+    let mut supplicant = supplicant_with_management_frame_protection();
+    assert!(supplicant.management_frame_protection());
+
+    let mut response = [0u8; 256];
+
+    let outcome = supplicant.process(&hex(MESSAGE_1), &mut response).unwrap();
+    assert_eq!(
+        response[..outcome.response_length.unwrap()].to_vec(),
+        hex(MFP_MESSAGE_2)
+    );
+
+    let outcome = supplicant.process(&hex(MFP_MESSAGE_3), &mut response).unwrap();
+    assert_eq!(response[..outcome.response_length.unwrap()].to_vec(), hex(MESSAGE_4));
+    assert_eq!(
+        outcome.group_key.unwrap().key.to_vec(),
+        hex("404142434445464748494a4b4c4d4e4f")
+    );
+
+    let integrity_group_key = outcome.integrity_group_key.unwrap();
+    assert_eq!(integrity_group_key.index, 4);
+    assert_eq!(
+        integrity_group_key.key.to_vec(),
+        hex("808182838485868788898a8b8c8d8e8f")
+    );
+    assert_eq!(integrity_group_key.ipn, [3, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn message_3_without_igtk_is_rejected() {
+    let mut supplicant = supplicant_with_management_frame_protection();
+    let mut response = [0u8; 256];
+
+    supplicant.process(&hex(MESSAGE_1), &mut response).unwrap();
+
+    assert_eq!(
+        supplicant
+            .process(&hex(MFP_MESSAGE_3_WITHOUT_IGTK), &mut response)
+            .err(),
+        Some(SupplicantError::InvalidKeyData)
+    );
+}
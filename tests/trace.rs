@@ -0,0 +1,118 @@
+//! Verifies the serialization of bus traces and the eviction of the oldest records in the ring
+//! buffer.
+
+use embassy_time::Instant;
+use nrf70::bus::trace::{Access, Record, TraceBuffer, TraceSink};
+
+fn record(access: Access, address: u32, timestamp: u64, data: &[u8]) -> Record<'_> {
+    Record {
+        timestamp: Instant::from_micros(timestamp),
+        access,
+        address,
+        data,
+    }
+}
+
+fn decode_all(mut trace: &[u8]) -> Vec<(Access, u32, u64, Vec<u8>)> {
+    let mut records = Vec::new();
+
+    while !trace.is_empty() {
+        let (record, length) = Record::decode(trace).unwrap();
+        records.push((
+            record.access,
+            record.address,
+            record.timestamp.as_micros(),
+            record.data.to_vec(),
+        ));
+        trace = &trace[length..];
+    }
+
+    records
+}
+
+#[test]
+fn encode_and_decode() {
+    let original = record(Access::Write, 0x04_8C20, 1234, &[0x00, 0x01, 0x00, 0x00]);
+
+    let mut buffer = [0u8; 64];
+    let length = original.encode(&mut buffer).unwrap();
+
+    assert_eq!(length, Record::HEADER_SIZE + 4);
+    assert_eq!(
+        &buffer[..length],
+        &[
+            0x01, 0x20, 0x8C, 0x04, 0x00, 0xD2, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00,
+            0x01, 0x00, 0x00
+        ]
+    );
+
+    let (decoded, decoded_length) = Record::decode(&buffer[..length]).unwrap();
+
+    assert_eq!(decoded, original);
+    assert_eq!(decoded_length, length);
+
+    assert!(original.encode(&mut buffer[..length - 1]).is_err());
+    assert!(Record::decode(&buffer[..length - 1]).is_err());
+
+    buffer[0] = 0xFF;
+    assert!(Record::decode(&buffer[..length]).is_err());
+}
+
+#[test]
+fn display() {
+    let line = format!("{}", record(Access::ReadSr1, 0, 42, &[0x06]));
+
+    assert_eq!(line, "          42 read sr1  000000 [1] 06");
+}
+
+#[test]
+fn ring_buffer_drops_oldest_records() {
+    // Room for two records with a single byte of data
+    let mut trace = TraceBuffer::<{ 2 * (Record::HEADER_SIZE + 1) }>::new();
+
+    trace.record(&record(Access::WriteSr2, 0, 1, &[0x01]));
+    trace.record(&record(Access::ReadSr2, 0, 2, &[0x01]));
+    trace.record(&record(Access::ReadSr1, 0, 3, &[0x02]));
+
+    assert_eq!(trace.dropped(), 1);
+
+    // Larger than the whole buffer
+    trace.record(&record(Access::Write, 0x0C_0000, 4, &[0; 64]));
+
+    assert_eq!(trace.dropped(), 2);
+
+    let mut buffer = [0u8; 128];
+    let length = trace.read(&mut buffer);
+
+    assert!(trace.is_empty());
+    assert_eq!(
+        decode_all(&buffer[..length]),
+        [(Access::ReadSr2, 0, 2, vec![0x01]), (Access::ReadSr1, 0, 3, vec![0x02])]
+    );
+}
+
+#[test]
+fn read_only_takes_whole_records() {
+    let mut trace = TraceBuffer::<256>::new();
+
+    trace.record(&record(Access::Read, 0x0C_0000, 1, &[1, 2, 3, 4]));
+    trace.record(&record(Access::Read, 0x0C_0004, 2, &[5, 6, 7, 8]));
+
+    // Fits the first record and half of the second
+    let mut buffer = [0u8; Record::HEADER_SIZE + 4 + 10];
+    let length = trace.read(&mut buffer);
+
+    assert_eq!(length, Record::HEADER_SIZE + 4);
+    assert_eq!(trace.len(), Record::HEADER_SIZE + 4);
+    assert_eq!(
+        decode_all(&buffer[..length]),
+        [(Access::Read, 0x0C_0000, 1, vec![1, 2, 3, 4])]
+    );
+
+    let length = trace.read(&mut buffer);
+
+    assert_eq!(
+        decode_all(&buffer[..length]),
+        [(Access::Read, 0x0C_0004, 2, vec![5, 6, 7, 8])]
+    );
+}